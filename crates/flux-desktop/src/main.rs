@@ -1,4 +1,7 @@
-use flux::settings::{BlendMethod, ColorScheme, Noise, Settings};
+use flux::settings::{
+    Antialiasing, Background, BlendMethod, BlendMode, Camera, ColorScheme, EndpointShape,
+    LineStyle, Noise, RenderMode, Settings, TrailBlendMode,
+};
 use flux::Flux;
 use glutin::event::{Event, WindowEvent};
 use glutin::event_loop::{ControlFlow, EventLoop};
@@ -12,21 +15,53 @@ const settings: Settings = Settings {
     fluid_width: 128,
     fluid_height: 128,
     fluid_simulation_frame_rate: 20.0,
+    line_simulation_frame_rate: 120.0,
     diffusion_iterations: 30,
     pressure_iterations: 50,
     color_scheme: ColorScheme::Plasma,
+    background: Background::Solid([0.0, 0.0, 0.0, 1.0]),
+    render_mode: RenderMode::Lines,
+    antialiasing: Antialiasing::Off,
     line_length: 160.0,
     line_width: 5.0,
     line_begin_offset: 0.4,
     line_fade_out_length: 0.05,
+    line_opaque_length: 0.7,
+    line_min_width: 0.15,
+    line_max_width: 1.0,
+    line_max_length: 0.0,
+    line_color_response: 1.0,
+    line_style: LineStyle::Straight,
+    blend_mode: BlendMode::Additive,
+    endpoint_shape: EndpointShape::Circle { resolution: 16 },
+    mask_opacity: 1.0,
+    mask_line_length: 0.0,
+    streamline_segments: 8,
+    trail_decay: 0.0,
+    trail_blend_mode: TrailBlendMode::Additive,
+    bloom_intensity: 0.0,
+    bloom_threshold: 0.8,
+    bloom_radius: 1.0,
+    particle_count: 10000,
+    particle_lifetime: 4.0,
+    particle_speed: 30.0,
+    particle_size: 3.0,
+    particle_streak_length: 0.1,
     spring_stiffness: 0.3,
     spring_variance: 0.25,
     spring_mass: 2.0,
     spring_rest_length: 0.0,
+    spring_damping: 0.0,
+    spring_coupling_stiffness: 0.0,
     advection_direction: 1.0,
     adjust_advection: 16.0,
     grid_spacing: 18,
     view_scale: 1.2,
+    velocity_scale: 1.0,
+    velocity_offset: [0.0, 0.0],
+    camera: Camera::Orthographic,
+    endpoint_height: 0.0,
+    layers: Vec::new(),
     noise_channel_1: Noise {
         scale: 1.1,
         multiplier: 0.3,
@@ -59,9 +94,37 @@ fn main() {
     let context = Rc::new(context);
     let pixel_ratio = window.window().scale_factor();
     let mut flux = Flux::new(&context, width, height, pixel_ratio, &Rc::new(settings)).unwrap();
+    if flux.antialiasing() != settings.antialiasing {
+        println!(
+            "{:?} antialiasing isn’t supported. Using {:?} instead.",
+            settings.antialiasing,
+            flux.antialiasing()
+        );
+    }
+
+    #[cfg(feature = "gpu-timings")]
+    if !flux.set_gpu_profiling(true) {
+        println!("This context doesn’t support timer queries, so there won’t be any GPU timings.");
+    }
 
     let start = std::time::Instant::now();
 
+    #[cfg(feature = "hot-reload")]
+    let mut shader_watcher = match flux::ShaderWatcher::new() {
+        Ok(shader_watcher) => Some(shader_watcher),
+        Err(error) => {
+            eprintln!(
+                "Can’t watch the shaders, so they won’t be reloaded: {}",
+                error
+            );
+            None
+        }
+    };
+    #[cfg(feature = "hot-reload")]
+    let mut last_shader_check = start;
+    #[cfg(feature = "gpu-timings")]
+    let mut last_timings_report = start;
+
     event_loop.run(move |event, _, control_flow| {
         let next_frame_time =
             std::time::Instant::now() + std::time::Duration::from_nanos(16_666_667);
@@ -83,7 +146,9 @@ fn main() {
                     window.resize(*physical_size);
                     let glutin::dpi::LogicalSize { width, height } =
                         physical_size.to_logical(pixel_ratio);
-                    flux.resize(width, height);
+                    if let Err(problem) = flux.resize(width, height) {
+                        eprintln!("Can’t resize: {:?}", problem);
+                    }
                 }
                 WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
                 _ => (),
@@ -91,11 +156,66 @@ fn main() {
             _ => (),
         }
 
+        #[cfg(feature = "hot-reload")]
+        if last_shader_check.elapsed() >= std::time::Duration::from_millis(500) {
+            last_shader_check = std::time::Instant::now();
+            if let Some(shader_watcher) = &mut shader_watcher {
+                reload_shaders(&mut flux, shader_watcher);
+            }
+        }
+
         flux.animate(start.elapsed().as_millis() as f32);
         window.swap_buffers().unwrap();
+
+        #[cfg(feature = "gpu-timings")]
+        if last_timings_report.elapsed() >= std::time::Duration::from_secs(5) {
+            last_timings_report = std::time::Instant::now();
+            print_gpu_timings(&flux);
+        }
     });
 }
 
+#[cfg(feature = "gpu-timings")]
+fn print_gpu_timings(flux: &Flux) -> () {
+    let timings = flux.gpu_timings();
+    if timings.is_empty() {
+        return;
+    }
+
+    // Only the work inside the passes is timed. Anything drawn between them,
+    // like the uniform uploads, isn’t.
+    let total: f32 = timings.iter().map(|timing| timing.milliseconds).sum();
+    println!(
+        "GPU time per frame, summed over the passes: {:.2} ms",
+        total
+    );
+    for timing in timings.iter() {
+        println!("{:>28} {:>7.2} ms", timing.pass, timing.milliseconds);
+    }
+}
+
+#[cfg(feature = "hot-reload")]
+fn reload_shaders(flux: &mut Flux, shader_watcher: &mut flux::ShaderWatcher) -> () {
+    let changed = match shader_watcher.changed_files() {
+        Ok(changed) => changed,
+        Err(error) => {
+            eprintln!("Can’t check the shaders for changes: {}", error);
+            return;
+        }
+    };
+    if changed.is_empty() {
+        return;
+    }
+
+    let errors = flux.reload_shaders(&changed);
+    for error in errors.iter() {
+        eprintln!("Can’t reload {}", error);
+    }
+    if errors.is_empty() {
+        println!("Reloaded {}", changed.join(", "));
+    }
+}
+
 pub fn get_rendering_context(
     width: u32,
     height: u32,
@@ -107,10 +227,14 @@ pub fn get_rendering_context(
     let event_loop = glutin::event_loop::EventLoop::new();
     let window_builder = glutin::window::WindowBuilder::new()
         .with_title("Flux")
+        // Let the desktop show through a transparent background
+        .with_transparent(true)
         .with_inner_size(glutin::dpi::LogicalSize::new(width, height));
     let window = unsafe {
         glutin::ContextBuilder::new()
             .with_vsync(true)
+            // Some drivers only report KHR_debug messages for debug contexts
+            .with_gl_debug_flag(cfg!(feature = "debug"))
             .build_windowed(window_builder, &event_loop)
             .unwrap()
             .make_current()
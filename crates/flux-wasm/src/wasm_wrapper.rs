@@ -23,14 +23,26 @@ pub struct Flux {
 
 #[wasm_bindgen]
 impl Flux {
-    #[wasm_bindgen(setter)]
-    pub fn set_settings(&mut self, settings_object: &JsValue) {
-        let settings: flux::settings::Settings = settings_object.into_serde().unwrap();
-        self.id.update(&Rc::new(settings));
+    // Returns the antialiasing actually applied, like "Msaa4". This falls back
+    // to fewer samples, or to "Fxaa", if the browser doesn’t support the
+    // requested number of samples.
+    pub fn set_settings(&mut self, settings_object: &JsValue) -> Result<JsValue, JsValue> {
+        let settings: flux::settings::Settings = settings_object
+            .into_serde()
+            .map_err(|err| JsValue::from_str(&err.to_string()))?;
+        let antialiasing = self
+            .id
+            .update(&Rc::new(settings))
+            .map_err(|err| JsValue::from_str(&format!("{:?}", err)))?;
+
+        JsValue::from_serde(&antialiasing).map_err(|err| JsValue::from_str(&err.to_string()))
     }
 
     #[wasm_bindgen(constructor)]
     pub fn new(settings_object: &JsValue) -> Result<Flux, JsValue> {
+        #[cfg(feature = "debug")]
+        flux::set_log_sink(|message| log!("{}", message));
+
         let (canvas, gl, logical_width, logical_height, pixel_ratio) =
             get_rendering_context("canvas")?;
         let context = Rc::new(gl);
@@ -58,11 +70,185 @@ impl Flux {
         })
     }
 
+    pub fn set_background_image(
+        &mut self,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+    ) -> Result<(), JsValue> {
+        self.id
+            .set_background_image(width, height, pixels)
+            .map_err(|_err| JsValue::from_str("failed"))
+    }
+
+    pub fn set_endpoint_sprite(
+        &mut self,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+    ) -> Result<(), JsValue> {
+        self.id
+            .set_endpoint_sprite(width, height, pixels)
+            .map_err(|_err| JsValue::from_str("failed"))
+    }
+
+    // A grayscale mask, one byte per pixel.
+    pub fn set_mask(&mut self, width: u32, height: u32, pixels: &[u8]) -> Result<(), JsValue> {
+        self.id
+            .set_mask(width, height, pixels)
+            .map_err(|_err| JsValue::from_str("failed"))
+    }
+
+    // Render a line of text into the mask. `font` is a CSS font string, like
+    // `bold 200px sans-serif`.
+    pub fn set_mask_text(&mut self, text: &str, font: &str) -> Result<(), JsValue> {
+        let (width, height, pixels) = rasterize_text(text, font)?;
+        self.set_mask(width, height, &pixels)
+    }
+
+    pub fn clear_mask(&mut self) {
+        self.id.clear_mask();
+    }
+
+    // The translation is in logical pixels. Rotation is in radians.
+    pub fn set_mask_transform(
+        &mut self,
+        translation_x: f32,
+        translation_y: f32,
+        scale: f32,
+        rotation: f32,
+    ) {
+        self.id.set_mask_transform(flux::view::Transform2D {
+            translation: [translation_x, translation_y],
+            scale,
+            rotation,
+        });
+    }
+
+    // All in logical pixels. The viewport is this node’s part of the canvas.
+    pub fn set_virtual_canvas(
+        &mut self,
+        width: u32,
+        height: u32,
+        viewport_x: u32,
+        viewport_y: u32,
+        viewport_width: u32,
+        viewport_height: u32,
+    ) -> Result<(), JsValue> {
+        let virtual_canvas = flux::view::VirtualCanvas {
+            width,
+            height,
+            viewport: flux::view::Viewport {
+                x: viewport_x,
+                y: viewport_y,
+                width: viewport_width,
+                height: viewport_height,
+            },
+        };
+
+        self.id
+            .set_virtual_canvas(Some(virtual_canvas))
+            .map_err(|err| JsValue::from_str(&format!("{:?}", err)))
+    }
+
+    // Rotation is in radians. Set `duration` to 0 to jump to the new view.
+    pub fn set_view(
+        &mut self,
+        translation_x: f32,
+        translation_y: f32,
+        scale: f32,
+        rotation: f32,
+        duration: f32,
+    ) {
+        let view = flux::view::Transform2D {
+            translation: [translation_x, translation_y],
+            scale,
+            rotation,
+        };
+
+        if duration > 0.0 {
+            self.id.transition_view(view, duration);
+        } else {
+            self.id.set_view(view);
+        }
+    }
+
+    // Nine floats per line: the basepoint, the endpoint, the RGB color, the
+    // width and the opacity. Positions are in logical pixels, relative to the
+    // center of the canvas, with y pointing up, before the view is applied.
+    pub fn line_snapshot(&self) -> Vec<f32> {
+        self.id
+            .line_snapshot()
+            .iter()
+            .flat_map(|line| {
+                [
+                    line.basepoint[0],
+                    line.basepoint[1],
+                    line.endpoint[0],
+                    line.endpoint[1],
+                    line.color[0],
+                    line.color[1],
+                    line.color[2],
+                    line.width,
+                    line.opacity,
+                ]
+            })
+            .collect()
+    }
+
+    pub fn export_svg(&self) -> Result<String, JsValue> {
+        let mut svg = Vec::new();
+        self.id
+            .export_svg(&mut svg)
+            .map_err(|err| JsValue::from_str(&err.to_string()))?;
+
+        String::from_utf8(svg).map_err(|err| JsValue::from_str(&err.to_string()))
+    }
+
+    // Returns false if the browser doesn’t support
+    // EXT_disjoint_timer_query_webgl2.
+    pub fn set_gpu_profiling(&mut self, is_enabled: bool) -> bool {
+        self.id.set_gpu_profiling(is_enabled)
+    }
+
+    // An array of `{ pass, milliseconds }`, averaged over the last second or
+    // so of frames.
+    pub fn gpu_timings(&self) -> Result<JsValue, JsValue> {
+        let timings: Vec<GpuTiming> = self
+            .id
+            .gpu_timings()
+            .iter()
+            .map(|timing| GpuTiming {
+                pass: timing.pass,
+                milliseconds: timing.milliseconds,
+            })
+            .collect();
+
+        JsValue::from_serde(&timings).map_err(|err| JsValue::from_str(&err.to_string()))
+    }
+
+    pub fn log_gpu_timings(&self) {
+        let timings = self.id.gpu_timings();
+        if timings.is_empty() {
+            return;
+        }
+
+        // Only the work inside the passes is timed.
+        let total: f32 = timings.iter().map(|timing| timing.milliseconds).sum();
+        log!(
+            "GPU time per frame, summed over the passes: {:.2} ms",
+            total
+        );
+        for timing in timings.iter() {
+            log!("{:>28} {:>7.2} ms", timing.pass, timing.milliseconds);
+        }
+    }
+
     pub fn animate(&mut self, timestamp: f32) {
         self.id.animate(timestamp);
     }
 
-    pub fn resize(&mut self, logical_width: u32, logical_height: u32) {
+    pub fn resize(&mut self, logical_width: u32, logical_height: u32) -> Result<(), JsValue> {
         if (self.logical_width != logical_width) || (self.logical_height != logical_height) {
             let physical_width = (self.pixel_ratio * f64::from(logical_width)) as u32;
             let physical_height = (self.pixel_ratio * f64::from(logical_height)) as u32;
@@ -70,11 +256,15 @@ impl Flux {
             self.canvas.set_width(physical_width);
             self.canvas.set_height(physical_height);
 
-            self.id.resize(logical_width, logical_height);
-
             self.logical_width = logical_width;
             self.logical_height = logical_height;
+
+            self.id
+                .resize(logical_width, logical_height)
+                .map_err(|err| JsValue::from_str(&format!("{:?}", err)))?;
         }
+
+        Ok(())
     }
 }
 
@@ -142,6 +332,52 @@ pub fn get_rendering_context(
     Ok((canvas, gl, logical_width, logical_height, pixel_ratio))
 }
 
+// Draw white text on black on a scratch canvas and keep the red channel.
+fn rasterize_text(text: &str, font: &str) -> Result<(u32, u32, Vec<u8>), JsValue> {
+    use wasm_bindgen::JsCast;
+
+    let document = window().document().expect("I expected to find a document");
+    let canvas = document
+        .create_element("canvas")?
+        .dyn_into::<web_sys::HtmlCanvasElement>()?;
+    let context = |canvas: &web_sys::HtmlCanvasElement| -> Result<_, JsValue> {
+        canvas
+            .get_context("2d")?
+            .ok_or_else(|| JsValue::from_str("Can’t create a 2D rendering context"))?
+            .dyn_into::<web_sys::CanvasRenderingContext2d>()
+    };
+
+    let ctx = context(&canvas)?;
+    ctx.set_font(font);
+    let metrics = ctx.measure_text(text)?;
+    let ascent = metrics.actual_bounding_box_ascent();
+    let descent = metrics.actual_bounding_box_descent();
+    let padding = 0.1 * (ascent + descent);
+    let width = (metrics.width() + 2.0 * padding).ceil().max(1.0) as u32;
+    let height = (ascent + descent + 2.0 * padding).ceil().max(1.0) as u32;
+
+    // Resizing the canvas resets the context state.
+    canvas.set_width(width);
+    canvas.set_height(height);
+    let ctx = context(&canvas)?;
+    ctx.set_fill_style(&JsValue::from_str("black"));
+    ctx.fill_rect(0.0, 0.0, width as f64, height as f64);
+    ctx.set_font(font);
+    ctx.set_fill_style(&JsValue::from_str("white"));
+    ctx.fill_text(text, padding, padding + ascent)?;
+
+    let image_data = ctx.get_image_data(0.0, 0.0, width as f64, height as f64)?;
+    let pixels = image_data.data().0.chunks(4).map(|rgba| rgba[0]).collect();
+
+    Ok((width, height, pixels))
+}
+
+#[derive(Serialize, Debug)]
+pub struct GpuTiming {
+    pub pass: &'static str,
+    pub milliseconds: f32,
+}
+
 #[derive(Serialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct ContextOptions {
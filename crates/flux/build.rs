@@ -1,29 +1,84 @@
-use std::io::prelude::*;
-use std::{env, error::Error, fs, fs::File, path::Path};
+#[path = "src/preprocessor.rs"]
+mod preprocessor;
+#[path = "build/validation.rs"]
+mod validation;
 
-// Specify the correct GLSL version in the shaders at build time.
+use preprocessor::Target;
+use std::{env, error::Error, fs, path::Path, process};
+
+// Preprocess the shaders for the target at build time, and check that they
+// are valid for both WebGL and desktop OpenGL.
 fn main() -> Result<(), Box<dyn Error>> {
     let out_dir = env::var_os("OUT_DIR").unwrap();
     let target = env::var("TARGET").unwrap();
     let shaders_files = fs::read_dir("shaders")?;
 
-    let version = match target.as_str() {
-        "wasm32-unknown-unknown" => "#version 300 es\n",
-        _ => "#version 330\n",
+    let target = match target.as_str() {
+        "wasm32-unknown-unknown" => Target::WebGL,
+        _ => Target::Desktop,
+    };
+
+    // Shared includes are checked once per shader and target, so the same
+    // problem can come up many times. Report each one once, along with the
+    // targets it affects.
+    let mut problems: Vec<(String, Vec<Target>)> = Vec::new();
+    let mut add_problem = |problem: String, target: Target| match problems
+        .iter_mut()
+        .find(|(other, _)| *other == problem)
+    {
+        Some((_, targets)) if !targets.contains(&target) => targets.push(target),
+        Some(_) => (),
+        None => problems.push((problem, vec![target])),
     };
 
     for shader_file in shaders_files {
         if let Ok(entry) = shader_file {
             let path = entry.path();
-            let mut shader_source = File::open(&path)?;
+            // The WGSL shaders and the shared GLSL includes are in
+            // subdirectories.
+            if path.is_dir() {
+                continue;
+            }
+
+            let name = entry.file_name().to_string_lossy().into_owned();
 
-            let mut version_shader_source = String::new() + version;
-            shader_source.read_to_string(&mut version_shader_source)?;
+            for other_target in [Target::WebGL, Target::Desktop] {
+                let shader =
+                    match preprocessor::preprocess(Path::new("shaders"), &name, other_target) {
+                        Ok(shader) => shader,
+                        Err(error) => {
+                            add_problem(error.to_string(), other_target);
+                            continue;
+                        }
+                    };
+
+                if let Err(shader_problems) = validation::validate(&name, &shader, other_target) {
+                    for problem in shader_problems {
+                        add_problem(problem, other_target);
+                    }
+                }
+
+                if other_target == target {
+                    let dest_path = Path::new(&out_dir).join(&path);
+                    fs::create_dir_all(Path::new(&out_dir).join(Path::new("shaders")))?;
+                    fs::write(&dest_path, shader.source.as_bytes())?;
+                }
+            }
+        }
+    }
 
-            let dest_path = Path::new(&out_dir).join(&path);
-            fs::create_dir_all(Path::new(&out_dir).join(Path::new("shaders")))?;
-            fs::write(&dest_path, version_shader_source.as_bytes())?;
+    if !problems.is_empty() {
+        for (problem, targets) in problems {
+            eprintln!("shaders/{}", problem);
+            if targets.len() == 1 {
+                eprintln!(
+                    "    (only when built for {})",
+                    validation::describe(targets[0])
+                );
+            }
+            eprintln!();
         }
+        process::exit(1);
     }
 
     Ok(())
@@ -0,0 +1,182 @@
+// Validate the preprocessed shaders with naga, so that broken shaders fail the
+// build instead of failing on whatever GPU the app happens to run on.
+//
+// naga only reads Vulkan-flavoured GLSL 4.50, so the shaders are first
+// rewritten into that dialect: precision qualifiers are dropped, loose
+// uniforms get their own blocks and bindings, and samplers are split into
+// textures and samplers. The rewrite never adds lines after the `#line`
+// directives, so naga’s error locations still map back to the original files.
+use crate::preprocessor::{self, Shader, Target};
+
+use naga::front::glsl::{Options, Parser};
+use naga::valid::{Capabilities, ValidationFlags, Validator};
+use naga::ShaderStage;
+use std::error::Error;
+
+// Returns a list of problems, each with the location in the original sources
+// and the offending line.
+pub fn validate(name: &str, shader: &Shader, target: Target) -> Result<(), Vec<String>> {
+    let stage = if name.ends_with(".vert") {
+        ShaderStage::Vertex
+    } else {
+        ShaderStage::Fragment
+    };
+
+    if target == Target::WebGL && stage == ShaderStage::Fragment {
+        let has_precision = shader
+            .source
+            .lines()
+            .any(|line| line.trim_start().starts_with("precision") && line.contains("float"));
+        if !has_precision {
+            return Err(vec![format!("{}: missing a default float precision", name)]);
+        }
+    }
+
+    let source = to_vulkan_glsl(&shader.source, stage);
+    let report = |offset: Option<usize>, message: String| {
+        let location = offset.and_then(|offset| {
+            let line = source.get(..offset)?.matches('\n').count() + 1;
+            let code = source.lines().nth(line - 1)?;
+            let (file, line) = preprocessor::locate(&source, offset)?;
+            Some((file, line, code))
+        });
+
+        match location {
+            Some((file, line, code)) => {
+                format!("{}:{}: {}\n    {}", file, line, message, code.trim())
+            }
+            None => format!("{}: {}", name, message),
+        }
+    };
+
+    let module = Parser::default()
+        .parse(&Options::from(stage), &source)
+        .map_err(|errors| {
+            errors
+                .iter()
+                .map(|error| {
+                    let offset = error.meta.to_range().map(|range| range.start);
+                    report(offset, error.kind.to_string())
+                })
+                .collect::<Vec<String>>()
+        })?;
+
+    Validator::new(ValidationFlags::all(), Capabilities::all())
+        .validate(&module)
+        .map_err(|error| {
+            // The spans go from the function down to the offending
+            // expression, and so do the messages.
+            let offset = error
+                .spans()
+                .filter_map(|(span, _)| span.to_range())
+                .map(|range| range.start)
+                .last();
+
+            let error = error.into_inner();
+            let mut message = error.to_string();
+            let mut cause = error.source();
+            while let Some(inner) = cause {
+                message.push_str(": ");
+                message.push_str(&inner.to_string());
+                cause = inner.source();
+            }
+
+            vec![report(offset, message)]
+        })?;
+
+    Ok(())
+}
+
+pub fn describe(target: Target) -> &'static str {
+    match target {
+        Target::WebGL => "GLSL ES 3.00",
+        Target::Desktop => "GLSL 3.30",
+    }
+}
+
+fn to_vulkan_glsl(source: &str, stage: ShaderStage) -> String {
+    let mut header = String::from(
+        "#version 450 core\n\
+         #define gl_VertexID int(gl_VertexIndex)\n\
+         #define gl_InstanceID int(gl_InstanceIndex)\n",
+    );
+    // Vertex shaders can’t compute derivatives, so OpenGL samples the base
+    // level. naga wants that spelled out.
+    if stage == ShaderStage::Vertex {
+        header.push_str("#define texture(sampler, coord) textureLod(sampler, coord, 0.0)\n");
+    }
+
+    let mut body = String::new();
+    let mut binding = 0;
+    let mut input_location = 0;
+    let mut output_location = 0;
+
+    for line in source.lines() {
+        if line.starts_with("#version") {
+            continue;
+        }
+
+        let line = ["highp", "mediump", "lowp"]
+            .iter()
+            .fold(line.to_string(), |line, qualifier| {
+                remove_word(&line, qualifier)
+            });
+        let trimmed = line.trim();
+
+        if trimmed.starts_with("precision") {
+            body.push('\n');
+        } else if let Some(block) = trimmed.strip_prefix("layout(std140) uniform ") {
+            body.push_str(&format!(
+                "layout(std140, set = 0, binding = {}) uniform {}\n",
+                binding, block
+            ));
+            binding += 1;
+        } else if let Some(declaration) = trimmed.strip_prefix("uniform ") {
+            let declaration = declaration.trim_end_matches(';');
+            let (type_, name) = declaration.split_once(' ').unwrap_or((declaration, ""));
+
+            if type_ == "sampler2D" {
+                header.push_str(&format!(
+                    "#define {0} sampler2D({0}_texture, {0}_sampler)\n",
+                    name
+                ));
+                body.push_str(&format!(
+                    "layout(set = 0, binding = {}) uniform texture2D {2}_texture; \
+                     layout(set = 0, binding = {}) uniform sampler {2}_sampler;\n",
+                    binding,
+                    binding + 1,
+                    name
+                ));
+                binding += 2;
+            } else {
+                body.push_str(&format!(
+                    "layout(std140, set = 0, binding = {0}) uniform Uniform{0} {{ {1}; }};\n",
+                    binding, declaration
+                ));
+                binding += 1;
+            }
+        } else if trimmed.starts_with("in ") || trimmed.starts_with("flat in ") {
+            body.push_str(&format!("layout(location = {}) {}\n", input_location, line));
+            input_location += 1;
+        } else if trimmed.starts_with("out ") || trimmed.starts_with("flat out ") {
+            body.push_str(&format!(
+                "layout(location = {}) {}\n",
+                output_location, line
+            ));
+            output_location += 1;
+        } else {
+            body.push_str(&line);
+            body.push('\n');
+        }
+    }
+
+    header + &body
+}
+
+fn remove_word(line: &str, word: &str) -> String {
+    let is_identifier = |c: char| c.is_ascii_alphanumeric() || c == '_';
+
+    line.split_inclusive(|c: char| !is_identifier(c))
+        .filter(|token| token.trim_end_matches(|c: char| !is_identifier(c)) != word)
+        .collect()
+}
@@ -0,0 +1,45 @@
+precision mediump float;
+precision mediump sampler2D;
+
+#define SOLID 0
+#define LINEAR_GRADIENT 1
+#define RADIAL_GRADIENT 2
+#define IMAGE 3
+
+uniform int uBackgroundType;
+uniform vec4 uColor1;
+uniform vec4 uColor2;
+uniform vec2 uGradientDirection;
+uniform vec2 uAspectRatio;
+uniform vec2 uImageScale;
+uniform sampler2D imageTexture;
+
+in vec2 textureCoord;
+out vec4 fragColor;
+
+void main() {
+  vec4 color;
+
+  if (uBackgroundType == LINEAR_GRADIENT) {
+    // Project onto the gradient direction so that the gradient spans the
+    // whole canvas, corner to corner.
+    vec2 position = (textureCoord - 0.5) * uAspectRatio;
+    float extent = dot(0.5 * uAspectRatio, abs(uGradientDirection));
+    float t = 0.5 + 0.5 * dot(position, uGradientDirection) / extent;
+    color = mix(uColor1, uColor2, clamp(t, 0.0, 1.0));
+  } else if (uBackgroundType == RADIAL_GRADIENT) {
+    vec2 position = (textureCoord - 0.5) * uAspectRatio;
+    float t = length(position) / length(0.5 * uAspectRatio);
+    color = mix(uColor1, uColor2, clamp(t, 0.0, 1.0));
+  } else if (uBackgroundType == IMAGE) {
+    vec2 imageCoord = (textureCoord - 0.5) * uImageScale + 0.5;
+    // Images are uploaded top row first.
+    imageCoord.y = 1.0 - imageCoord.y;
+    color = texture(imageTexture, imageCoord);
+  } else {
+    color = uColor1;
+  }
+
+  // The canvas expects premultiplied alpha.
+  fragColor = vec4(color.rgb * color.a, color.a);
+}
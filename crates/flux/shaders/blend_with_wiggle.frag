@@ -1,15 +1,7 @@
 precision highp float;
 precision highp sampler2D;
 
-layout(std140) uniform NoiseUniforms {
-  highp float uFrequency;
-  highp float uOffset1;
-  highp float uOffset2;
-  highp float uMultiplier;
-  highp vec2 uTexelSize;
-  highp float uBlendThreshold;
-  lowp float pad2;
-};
+#include "common/noise_uniforms.glsl"
 
 uniform float uBlendProgress;
 
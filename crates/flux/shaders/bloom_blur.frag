@@ -0,0 +1,20 @@
+precision highp float;
+precision mediump sampler2D;
+
+uniform sampler2D inputTexture;
+// The offset between taps, in texture coordinates, along the blur direction.
+uniform vec2 uDirection;
+
+in vec2 textureCoord;
+out vec4 fragColor;
+
+// A 9-tap Gaussian blur, using linear filtering to sample two texels per tap.
+void main() {
+  vec3 color = texture(inputTexture, textureCoord).rgb * 0.2270270270;
+  color += texture(inputTexture, textureCoord + uDirection * 1.3846153846).rgb * 0.3162162162;
+  color += texture(inputTexture, textureCoord - uDirection * 1.3846153846).rgb * 0.3162162162;
+  color += texture(inputTexture, textureCoord + uDirection * 3.2307692308).rgb * 0.0702702703;
+  color += texture(inputTexture, textureCoord - uDirection * 3.2307692308).rgb * 0.0702702703;
+
+  fragColor = vec4(color, 0.0);
+}
@@ -0,0 +1,14 @@
+precision highp float;
+precision mediump sampler2D;
+
+uniform sampler2D inputTexture;
+uniform float uIntensity;
+
+in vec2 textureCoord;
+out vec4 fragColor;
+
+// Added on top of the destination. The alpha is left alone so that the glow
+// doesn’t make a transparent background opaque.
+void main() {
+  fragColor = vec4(uIntensity * texture(inputTexture, textureCoord).rgb, 0.0);
+}
@@ -0,0 +1,31 @@
+precision highp float;
+precision mediump sampler2D;
+
+uniform sampler2D inputTexture;
+uniform vec2 uTexelSize;
+uniform float uThreshold;
+
+in vec2 textureCoord;
+out vec4 fragColor;
+
+// The width of the soft knee around the threshold.
+const float KNEE = 0.5;
+
+void main() {
+  // Downsample with a 4-tap box filter. Each tap lands between four texels,
+  // so we average 16 texels in total.
+  vec3 color = 0.25 * (
+    texture(inputTexture, textureCoord + vec2(-1.0, -1.0) * uTexelSize).rgb +
+    texture(inputTexture, textureCoord + vec2(1.0, -1.0) * uTexelSize).rgb +
+    texture(inputTexture, textureCoord + vec2(-1.0, 1.0) * uTexelSize).rgb +
+    texture(inputTexture, textureCoord + vec2(1.0, 1.0) * uTexelSize).rgb
+  );
+
+  float brightness = max(color.r, max(color.g, color.b));
+  float knee = uThreshold * KNEE;
+  float soft = clamp(brightness - uThreshold + knee, 0.0, 2.0 * knee);
+  soft = soft * soft / (4.0 * knee + 0.0001);
+  float contribution = max(soft, brightness - uThreshold) / max(brightness, 0.0001);
+
+  fragColor = vec4(color * contribution, 0.0);
+}
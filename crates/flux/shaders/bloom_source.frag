@@ -0,0 +1,13 @@
+precision highp float;
+precision mediump sampler2D;
+
+uniform sampler2D inputTexture;
+
+in vec2 textureCoord;
+out vec4 fragColor;
+
+// The captured frame is premultiplied, so it blends onto the background the
+// same way the lines would.
+void main() {
+  fragColor = texture(inputTexture, textureCoord);
+}
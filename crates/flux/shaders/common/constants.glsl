@@ -0,0 +1 @@
+#define PI 3.1415926535897932384626433832795
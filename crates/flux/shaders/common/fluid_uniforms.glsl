@@ -0,0 +1,10 @@
+layout(std140) uniform FluidUniforms
+{
+  highp float deltaT;
+  highp float epsilon;
+  highp float halfEpsilon;
+  highp float dissipation;
+  highp vec2 uTexelSize;
+  lowp float pad1;
+  lowp float pad2;
+};
@@ -0,0 +1,8 @@
+layout(std140) uniform LineUniforms
+{
+  highp float uLineWidth;
+  highp float uLineLength;
+  highp float uLineBeginOffset;
+  highp float uLineFadeOutLength;
+  highp float uEndpointHeight;
+};
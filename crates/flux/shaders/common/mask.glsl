@@ -0,0 +1,18 @@
+uniform bool uHasMask;
+// Maps a basepoint onto the mask, which covers -1 to 1 on each axis.
+uniform mat4 uMaskMatrix;
+uniform sampler2D maskTexture;
+
+// Sample the mask at the basepoint. Everything outside the mask is masked out.
+float getMask(vec2 point) {
+  if (!uHasMask) {
+    return 1.0;
+  }
+
+  vec2 maskCoord = (uMaskMatrix * vec4(point, 0.0, 1.0)).xy * vec2(0.5, -0.5) + 0.5;
+  if (any(lessThan(maskCoord, vec2(0.0))) || any(greaterThan(maskCoord, vec2(1.0)))) {
+    return 0.0;
+  }
+
+  return texture(maskTexture, maskCoord).r;
+}
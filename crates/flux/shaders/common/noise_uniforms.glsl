@@ -0,0 +1,10 @@
+layout(std140) uniform NoiseUniforms
+{
+  highp float uFrequency;
+  highp float uOffset1;
+  highp float uOffset2;
+  highp float uMultiplier;
+  highp vec2 uTexelSize;
+  highp float uBlendThreshold;
+  lowp float pad2;
+};
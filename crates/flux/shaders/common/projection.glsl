@@ -0,0 +1,5 @@
+layout(std140) uniform Projection
+{
+  mat4 uProjection;
+  mat4 uView;
+};
@@ -0,0 +1,11 @@
+precision mediump float;
+precision mediump sampler2D;
+
+uniform sampler2D trailTexture;
+
+in vec2 textureCoord;
+out vec4 fragColor;
+
+void main() {
+  fragColor = texture(trailTexture, textureCoord);
+}
@@ -6,17 +6,22 @@ in vec3 vPremultipliedLineColor;
 in float vOpacity;
 in vec2 vPerpendicularVector;
 
-layout(std140) uniform LineUniforms
-{
-  highp float uLineWidth;
-  highp float uLineLength;
-  highp float uLineBeginOffset;
-  highp float uLineFadeOutLength;
-};
+#include "common/line_uniforms.glsl"
+
+uniform bool uUseSprite;
+uniform sampler2D spriteTexture;
 
 out vec4 fragColor;
 
 void main() {
+  if (uUseSprite) {
+    // The sprite has straight alpha and is tinted with the line color.
+    vec4 sprite = texture(spriteTexture, vPosition * vec2(0.5, -0.5) + 0.5);
+    float alpha = sprite.a * vOpacity;
+    fragColor = vec4(sprite.rgb * vColor * alpha, alpha);
+    return;
+  }
+
   // sign((B.x - center.x) * (y - center.y) - (B.y - center.y) * (x - center.x));
   float sideOfEndpoint = vPerpendicularVector.x * vPosition.y - vPerpendicularVector.y * vPosition.x;
   bool isUpperEndpoint = sideOfEndpoint >= 0.0 ? true : false;
@@ -29,4 +34,6 @@ void main() {
   } else {
     fragColor = lowerHalfColor;
   }
+
+  fragColor.rgb *= fragColor.a;
 }
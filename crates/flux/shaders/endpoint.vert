@@ -1,19 +1,11 @@
-#define PI 3.1415926535897932384626433832795
 precision highp float;
 
-layout(std140) uniform Projection
-{
-  mat4 uProjection;
-  mat4 uView;
-};
+#include "common/constants.glsl"
+#include "common/projection.glsl"
+#include "common/line_uniforms.glsl"
+#include "common/mask.glsl"
 
-layout(std140) uniform LineUniforms
-{
-  highp float uLineWidth;
-  highp float uLineLength;
-  highp float uLineBeginOffset;
-  highp float uLineFadeOutLength;
-};
+uniform float uMaskOpacity;
 
 in vec2 vertex;
 in vec2 basepoint;
@@ -63,9 +55,12 @@ void main() {
     0.0, 0.0, 0.0, 1.0
   );
 
-  gl_Position = uProjection * uView * translate(vec3(endpoint, 0.0)) * modelMatrix * vec4(vertex, 0.0, 1.0);
+  float height = uEndpointHeight * uLineLength * length(iVelocityVector);
+
+  gl_Position = uProjection * uView * translate(vec3(endpoint, height)) * modelMatrix * vec4(vertex, 0.0, 1.0);
 
   float endpointOpacity = smoothstep(uLineFadeOutLength, uLineFadeOutLength + 0.3, length(iEndpointVector));
+  endpointOpacity *= mix(1.0, getMask(basepoint), uMaskOpacity);
   vPosition = vertex;
   vColor = iColor.rgb;
   vPremultipliedLineColor = vColor * iOpacity;
@@ -0,0 +1,10 @@
+precision mediump float;
+
+uniform float uFadeFactor;
+
+out vec4 fragColor;
+
+// Blended with the trails as `destination * source`.
+void main() {
+  fragColor = vec4(uFadeFactor);
+}
@@ -1,15 +1,6 @@
 precision highp float;
 
-layout(std140) uniform FluidUniforms
-{
-  highp float deltaT;
-  highp float epsilon;
-  highp float halfEpsilon;
-  highp float dissipation;
-  highp vec2 uTexelSize;
-  lowp float pad1;
-  lowp float pad2;
-};
+#include "common/fluid_uniforms.glsl"
 
 in vec3 position;
 
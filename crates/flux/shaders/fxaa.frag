@@ -0,0 +1,58 @@
+precision highp float;
+precision mediump sampler2D;
+
+// A simplified FXAA, after Timothy Lottes’ FXAA 3.11.
+
+uniform sampler2D inputTexture;
+uniform vec2 uTexelSize;
+
+in vec2 textureCoord;
+out vec4 fragColor;
+
+const float EDGE_THRESHOLD_MIN = 1.0 / 128.0;
+const float EDGE_THRESHOLD_MUL = 1.0 / 8.0;
+const float SPAN_MAX = 8.0;
+
+float luma(vec4 color) {
+  return dot(color.rgb, vec3(0.299, 0.587, 0.114));
+}
+
+void main() {
+  vec4 colorM = texture(inputTexture, textureCoord);
+  float lumaNW = luma(texture(inputTexture, textureCoord + vec2(-1.0, -1.0) * uTexelSize));
+  float lumaNE = luma(texture(inputTexture, textureCoord + vec2(1.0, -1.0) * uTexelSize));
+  float lumaSW = luma(texture(inputTexture, textureCoord + vec2(-1.0, 1.0) * uTexelSize));
+  float lumaSE = luma(texture(inputTexture, textureCoord + vec2(1.0, 1.0) * uTexelSize));
+  float lumaM = luma(colorM);
+
+  float lumaMin = min(lumaM, min(min(lumaNW, lumaNE), min(lumaSW, lumaSE)));
+  float lumaMax = max(lumaM, max(max(lumaNW, lumaNE), max(lumaSW, lumaSE)));
+
+  vec2 direction = vec2(
+    -((lumaNW + lumaNE) - (lumaSW + lumaSE)),
+    (lumaNW + lumaSW) - (lumaNE + lumaSE)
+  );
+
+  float directionReduce = max(
+    (lumaNW + lumaNE + lumaSW + lumaSE) * 0.25 * EDGE_THRESHOLD_MUL,
+    EDGE_THRESHOLD_MIN
+  );
+  float inverseDirectionMin = 1.0 / (min(abs(direction.x), abs(direction.y)) + directionReduce);
+  direction = clamp(direction * inverseDirectionMin, vec2(-SPAN_MAX), vec2(SPAN_MAX)) * uTexelSize;
+
+  vec4 colorA = 0.5 * (
+    texture(inputTexture, textureCoord + direction * (1.0 / 3.0 - 0.5)) +
+    texture(inputTexture, textureCoord + direction * (2.0 / 3.0 - 0.5))
+  );
+  vec4 colorB = colorA * 0.5 + 0.25 * (
+    texture(inputTexture, textureCoord + direction * -0.5) +
+    texture(inputTexture, textureCoord + direction * 0.5)
+  );
+
+  float lumaB = luma(colorB);
+  if (lumaB < lumaMin || lumaB > lumaMax) {
+    fragColor = colorA;
+  } else {
+    fragColor = colorB;
+  }
+}
@@ -10,19 +10,8 @@ in float iLineWidth;
 in vec4 iColor;
 in float iOpacity;
 
-layout(std140) uniform Projection
-{
-  mat4 uProjection;
-  mat4 uView;
-};
-
-layout(std140) uniform LineUniforms
-{
-  highp float uLineWidth;
-  highp float uLineLength;
-  highp float uLineBeginOffset;
-  highp float uLineFadeOutLength;
-};
+#include "common/projection.glsl"
+#include "common/line_uniforms.glsl"
 
 out vec2 vVertex;
 out vec3 vColor;
@@ -43,8 +32,9 @@ void main() {
   vec2 xBasis = endpoint - basepoint;
   vec2 yBasis = normalize(vec2(-xBasis.y, xBasis.x));
   vec2 point = basepoint + xBasis * lineVertex.x + yBasis * (iLineWidth * uLineWidth) * lineVertex.y;
+  float height = lineVertex.x * uEndpointHeight * uLineLength * length(iVelocityVector);
 
-  gl_Position = uProjection * uView * vec4(point, 0.0, 1.0);
+  gl_Position = uProjection * uView * vec4(point, height, 1.0);
 
   vVertex = lineVertex;
   vColor = iColor.rgb;
@@ -0,0 +1,9 @@
+precision highp float;
+
+in vec2 vEndpointVector;
+
+out vec4 fragColor;
+
+void main() {
+  fragColor = vec4(vEndpointVector, 0.0, 0.0);
+}
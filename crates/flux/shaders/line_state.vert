@@ -0,0 +1,18 @@
+precision highp float;
+
+in vec2 iEndpointVector;
+
+// The number of columns and rows in the grid of lines
+uniform ivec2 uGridSize;
+
+out vec2 vEndpointVector;
+
+// Write each line into its own texel, laid out the same way as the grid.
+void main() {
+  ivec2 cell = ivec2(gl_VertexID % uGridSize.x, gl_VertexID / uGridSize.x);
+  vec2 position = (vec2(cell) + 0.5) / vec2(uGridSize);
+
+  gl_Position = vec4(2.0 * position - 1.0, 0.0, 1.0);
+  gl_PointSize = 1.0;
+  vEndpointVector = iEndpointVector;
+}
@@ -0,0 +1,22 @@
+precision mediump float;
+
+in vec2 vVertex;
+in vec3 vColor;
+in float vOpacity;
+in float vStreakLength;
+in float vRadius;
+
+out vec4 fragColor;
+
+void main() {
+  // Distance to the streak, which runs from the tail at the origin to the head
+  float along = clamp(vVertex.x, 0.0, vStreakLength);
+  float distanceToStreak = length(vVertex - vec2(along, 0.0));
+  float coverage = 1.0 - smoothstep(vRadius - 1.0, vRadius, distanceToStreak);
+
+  // Fade out towards the tail
+  float tailFade = vStreakLength > 0.0 ? mix(0.2, 1.0, along / vStreakLength) : 1.0;
+
+  float opacity = vOpacity * coverage * tailFade;
+  fragColor = vec4(vColor * opacity, opacity);
+}
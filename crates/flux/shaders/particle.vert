@@ -0,0 +1,46 @@
+precision highp float;
+
+in vec2 particleVertex;
+
+in vec2 iPosition;
+in vec2 iVelocityVector;
+in vec4 iColor;
+in float iAge;
+in float iLifetime;
+
+#include "common/projection.glsl"
+
+uniform float uParticleSize;
+uniform float uParticleSpeed;
+uniform float uParticleStreakLength;
+uniform float uAdjustAdvection;
+
+out vec2 vVertex;
+out vec3 vColor;
+out float vOpacity;
+out float vStreakLength;
+out float vRadius;
+
+void main() {
+  // Stretch the particle along its path over the last few moments
+  vec2 streak = uAdjustAdvection * uParticleSpeed * iVelocityVector * uParticleStreakLength;
+  float streakLength = length(streak);
+  vec2 xBasis = streakLength > 0.0 ? streak / streakLength : vec2(1.0, 0.0);
+  vec2 yBasis = vec2(-xBasis.y, xBasis.x);
+
+  // Pad the quad by the radius on all sides to fit the round caps
+  float radius = 0.5 * uParticleSize;
+  vec2 tail = iPosition - streak;
+  float x = (streakLength + 2.0 * radius) * particleVertex.x - radius;
+  float y = 2.0 * radius * particleVertex.y;
+  vec2 point = tail + xBasis * x + yBasis * y;
+
+  gl_Position = uProjection * uView * vec4(point, 0.0, 1.0);
+
+  float life = iAge / iLifetime;
+  vVertex = vec2(x, y);
+  vColor = iColor.rgb;
+  vOpacity = smoothstep(0.0, 0.1, life) * (1.0 - smoothstep(0.8, 1.0, life));
+  vStreakLength = streakLength;
+  vRadius = radius;
+}
@@ -1,8 +1,9 @@
-#define PI 3.1415926535897932384626433832795
-
 precision highp float;
 precision highp sampler2D;
 
+#include "common/constants.glsl"
+#include "common/mask.glsl"
+
 // static input
 in vec2 basepoint;
 
@@ -18,13 +19,30 @@ uniform float uSpringStiffness;
 uniform float uSpringVariance;
 uniform float uSpringMass;
 uniform float uSpringRestLength;
+uniform float uSpringDamping;
+uniform float uSpringCouplingStiffness;
 uniform float uLineFadeOutLength;
+uniform float uLineOpaqueLength;
+uniform float uLineMinWidth;
+uniform float uLineMaxWidth;
+uniform float uLineMaxLength;
+uniform float uColorResponse;
 uniform float uAdjustAdvection;
 uniform float uAdvectionDirection;
 uniform mediump vec4 uColorWheel[6];
 uniform mat4 uProjection;
 
 uniform sampler2D velocityTexture;
+uniform float uVelocityScale;
+uniform vec2 uVelocityOffset;
+
+// The endpoints of all the lines, laid out like the grid. Only bound when the
+// lines are coupled to their neighbors.
+uniform sampler2D lineStateTexture;
+uniform ivec2 uGridSize;
+
+uniform float uMaskOpacity;
+uniform float uMaskLineLength;
 
 // transform feedback output
 out vec2 vEndpointVector;
@@ -58,6 +76,19 @@ float random1f(in vec2 st) {
   return fract(sin(dot(st.xy, vec2(12.9898, 78.233))) * 43758.5453123);
 }
 
+// The average endpoint of the lines around this one
+vec2 getNeighborAverage() {
+  ivec2 cell = ivec2(gl_VertexID % uGridSize.x, gl_VertexID / uGridSize.x);
+  ivec2 lastCell = uGridSize - 1;
+
+  vec2 sum = texelFetch(lineStateTexture, clamp(cell + ivec2(-1, 0), ivec2(0), lastCell), 0).xy
+           + texelFetch(lineStateTexture, clamp(cell + ivec2(1, 0), ivec2(0), lastCell), 0).xy
+           + texelFetch(lineStateTexture, clamp(cell + ivec2(0, -1), ivec2(0), lastCell), 0).xy
+           + texelFetch(lineStateTexture, clamp(cell + ivec2(0, 1), ivec2(0), lastCell), 0).xy;
+
+  return sum / 4.0;
+}
+
 float easeInOutQuad(float t) {
   float p = 2.0 * t * t;
   return t < 0.5 ? p : -p + (4.0 * t) - 1.0;
@@ -66,7 +97,7 @@ float easeInOutQuad(float t) {
 void main() {
   // Velocity
   vec2 basepointInClipSpace = (uProjection * vec4(basepoint, 0.0, 1.0)).xy;
-  vec2 currentVelocityVector = texture(velocityTexture, basepointInClipSpace * 0.5 + 0.5).xy;
+  vec2 currentVelocityVector = uVelocityScale * texture(velocityTexture, basepointInClipSpace * 0.5 + 0.5 + uVelocityOffset).xy;
   vec2 deltaVelocity = currentVelocityVector - iVelocityVector;
   vVelocityVector = iVelocityVector + (deltaVelocity / uSpringMass) * deltaT;
 
@@ -87,10 +118,36 @@ void main() {
     currentLength - uSpringRestLength
   ) * direction * deltaT;
 
+  // Coupling springs pull the endpoint towards those of its neighbors
+  if (uSpringCouplingStiffness > 0.0) {
+    vVelocityVector += uAdvectionDirection * springForce(
+      uSpringCouplingStiffness,
+      uSpringMass * variance,
+      1.0
+    ) * (iEndpointVector - getNeighborAverage()) * deltaT;
+  }
+
+  // Damping. Decay exponentially, so that a large step can’t overshoot and
+  // flip the velocity.
+  vVelocityVector *= exp(-uSpringDamping / (uSpringMass * variance) * deltaT);
+
   // Advect forward
   vEndpointVector = iEndpointVector + uAdjustAdvection * uAdvectionDirection * vVelocityVector * deltaT;
   currentLength = length(vEndpointVector);
 
+  if (uLineMaxLength > 0.0 && currentLength > uLineMaxLength) {
+    vEndpointVector *= uLineMaxLength / currentLength;
+    currentLength = uLineMaxLength;
+  }
+
+  // Mask
+  float mask = getMask(basepoint);
+  float maskedLength = mix(currentLength, min(currentLength, mask), uMaskLineLength);
+  if (currentLength > 0.0) {
+    vEndpointVector *= maskedLength / currentLength;
+    currentLength = maskedLength;
+  }
+
   // Color
   float angle = mod(
     PI / 6.0 * currentLength + (PI + atan(iEndpointVector.y, iEndpointVector.x)),
@@ -99,7 +156,7 @@ void main() {
   vec4 newColor = vec4(getColor(uColorWheel, angle), 0.0);
   vec4 colorDiff = newColor - iColor;
   vColor = clamp(
-    iColor + colorDiff * deltaT,
+    iColor + colorDiff * min(uColorResponse * deltaT, 1.0),
     vec4(0.0),
     vec4(1.0)
   );
@@ -113,12 +170,13 @@ void main() {
 
   vLineWidth = clamp(
     iLineWidth + uAdjustAdvection * directionAlignment * length(vVelocityVector) * deltaT,
-    0.15,
-    1.0
+    uLineMinWidth,
+    uLineMaxWidth
   );
 
   // Opacity
   // This is only for the line. The endpoints have their own fade out curve.
   // TODO can we improve this?
-  vOpacity = easeInOutQuad(smoothstep(uLineFadeOutLength, 0.7, currentLength));
+  vOpacity = easeInOutQuad(smoothstep(uLineFadeOutLength, uLineOpaqueLength, currentLength));
+  vOpacity *= mix(1.0, mask, uMaskOpacity);
 }
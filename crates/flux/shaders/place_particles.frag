@@ -0,0 +1,7 @@
+precision mediump float;
+
+out vec4 fragColor;
+
+void main() {
+  fragColor = vec4(0.0);
+}
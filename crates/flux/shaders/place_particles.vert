@@ -0,0 +1,90 @@
+precision highp float;
+precision highp sampler2D;
+
+#include "common/constants.glsl"
+
+// dynamic input
+in vec2 iPosition;
+in vec2 iVelocityVector;
+in vec4 iColor;
+in float iAge;
+in float iLifetime;
+
+uniform float deltaT;
+uniform float uElapsedTime;
+uniform float uParticleLifetime;
+uniform float uParticleSpeed;
+uniform float uAdjustAdvection;
+uniform float uAdvectionDirection;
+uniform vec2 uBounds;
+uniform mediump vec4 uColorWheel[6];
+uniform mat4 uProjection;
+
+uniform sampler2D velocityTexture;
+
+// transform feedback output
+out vec2 vPosition;
+out vec2 vVelocityVector;
+out vec4 vColor;
+out float vAge;
+out float vLifetime;
+
+vec3 getColor(vec4 wheel[6], float angle) {
+  float slice = 2.0 * PI / 6.0;
+  float rawIndex = angle / slice;
+  float index = floor(rawIndex);
+  float nextIndex = mod(index + 1.0, 6.0);
+  float interpolate = fract(rawIndex);
+
+  vec3 currentColor = wheel[int(index)].rgb;
+  vec3 nextColor = wheel[int(nextIndex)].rgb;
+  return mix(currentColor, nextColor, interpolate);
+}
+
+float random1f(in vec2 st) {
+  return fract(sin(dot(st.xy, vec2(12.9898, 78.233))) * 43758.5453123);
+}
+
+void main() {
+  vec2 position = iPosition;
+  vec2 velocityVector = iVelocityVector;
+  vec4 color = iColor;
+  float age = iAge + deltaT;
+  float lifetime = iLifetime;
+
+  bool isOutOfBounds = any(greaterThan(abs(position), uBounds));
+
+  // Respawn somewhere random on the screen
+  if (age >= lifetime || isOutOfBounds) {
+    vec2 seed = vec2(float(gl_VertexID), fract(uElapsedTime));
+    position = uBounds * (2.0 * vec2(random1f(seed), random1f(seed.yx + 0.5)) - 1.0);
+    velocityVector = vec2(0.0);
+    color = vec4(0.0);
+    age = 0.0;
+    // Vary the lifetimes so that the particles don’t all respawn at once.
+    lifetime = uParticleLifetime * (0.5 + random1f(seed + 1.0));
+  }
+
+  // Velocity
+  vec2 positionInClipSpace = (uProjection * vec4(position, 0.0, 1.0)).xy;
+  vec2 currentVelocityVector = texture(velocityTexture, positionInClipSpace * 0.5 + 0.5).xy;
+  vVelocityVector = uAdvectionDirection * currentVelocityVector;
+
+  // Advect forward
+  vPosition = position + uAdjustAdvection * uParticleSpeed * vVelocityVector * deltaT;
+
+  // Color
+  float angle = mod(
+    PI / 6.0 * length(vVelocityVector) + (PI + atan(vVelocityVector.y, vVelocityVector.x)),
+    2.0 * PI
+  );
+  vec4 newColor = vec4(getColor(uColorWheel, angle), 0.0);
+  vColor = clamp(
+    color + (newColor - color) * deltaT,
+    vec4(0.0),
+    vec4(1.0)
+  );
+
+  vAge = age;
+  vLifetime = lifetime;
+}
@@ -0,0 +1,82 @@
+precision highp float;
+precision highp sampler2D;
+
+in vec2 streamlineVertex;
+in vec2 basepoint;
+
+in vec2 iEndpointVector;
+in vec2 iVelocityVector;
+in float iLineWidth;
+in vec4 iColor;
+in float iOpacity;
+
+#include "common/projection.glsl"
+#include "common/line_uniforms.glsl"
+
+uniform int uSegments;
+uniform float uAdvectionDirection;
+uniform sampler2D velocityTexture;
+uniform float uVelocityScale;
+uniform vec2 uVelocityOffset;
+// Maps the canvas onto the velocity texture. This differs from `uProjection`
+// when only part of a virtual canvas is drawn.
+uniform mat4 uFluidProjection;
+
+out vec2 vVertex;
+out vec3 vColor;
+out float vOpacity;
+
+vec2 sampleDirection(vec2 point, vec2 fallback) {
+  vec2 pointInClipSpace = (uFluidProjection * vec4(point, 0.0, 1.0)).xy;
+  vec2 velocity = uAdvectionDirection * uVelocityScale * texture(velocityTexture, pointInClipSpace * 0.5 + 0.5 + uVelocityOffset).xy;
+  float speed = length(velocity);
+  return speed > 0.0 ? velocity / speed : fallback;
+}
+
+void main() {
+  vec2 endpointVector = iEndpointVector * uLineLength;
+  float lineLength = length(endpointVector);
+  float stepLength = lineLength / float(uSegments);
+  vec2 lineDirection = lineLength > 0.0 ? endpointVector / lineLength : vec2(1.0, 0.0);
+
+  // Trace the velocity field from the basepoint, one segment at a time.
+  int segment = int(streamlineVertex.x * float(uSegments) + 0.5);
+  vec2 point = basepoint;
+  vec2 tracedPoint = basepoint;
+  vec2 direction = lineDirection;
+  vec2 tangent = lineDirection;
+
+  for (int i = 0; i < uSegments; i++) {
+    direction = sampleDirection(point, direction);
+    point += direction * stepLength;
+
+    if (i < segment) {
+      tracedPoint = point;
+      tangent = direction;
+    }
+  }
+
+  // Bend the traced curve so that it ends at the endpoint.
+  float t = streamlineVertex.x;
+  vec2 correction = (basepoint + endpointVector) - point;
+  vec2 curvePoint = tracedPoint + t * correction;
+  tangent = tangent * stepLength + correction / float(uSegments);
+  if (length(tangent) == 0.0) {
+    tangent = lineDirection;
+  }
+  vec2 normal = normalize(vec2(-tangent.y, tangent.x));
+
+  // Taper towards the basepoint
+  float width = mix(0.3, 1.0, t) * iLineWidth * uLineWidth;
+  vec2 vertex = curvePoint + normal * width * streamlineVertex.y;
+
+  // Rise towards the endpoint, like the straight lines do, so that the two
+  // meet.
+  float height = t * uEndpointHeight * uLineLength * length(iVelocityVector);
+
+  gl_Position = uProjection * uView * vec4(vertex, height, 1.0);
+
+  vVertex = streamlineVertex;
+  vColor = iColor.rgb;
+  vOpacity = iOpacity * mix(0.5, 1.0, t);
+}
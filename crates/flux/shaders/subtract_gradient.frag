@@ -1,16 +1,7 @@
 precision mediump float;
 precision mediump sampler2D;
 
-layout(std140) uniform FluidUniforms
-{
-  highp float deltaT;
-  highp float epsilon;
-  highp float halfEpsilon;
-  highp float dissipation;
-  highp vec2 uTexelSize;
-  lowp float pad1;
-  lowp float pad2;
-};
+#include "common/fluid_uniforms.glsl"
 
 uniform sampler2D velocityTexture;
 uniform sampler2D pressureTexture;
@@ -1,11 +1,7 @@
 in vec3 position;
 out vec2 textureCoord;
 
-layout(std140) uniform Projection
-{
-  mat4 uProjection;
-  mat4 uView;
-};
+#include "common/projection.glsl"
 
 void main() {
   vec4 newPosition = uView * vec4(position, 1.0);
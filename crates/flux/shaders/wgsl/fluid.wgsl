@@ -0,0 +1,131 @@
+// The fluid fields are stored as rows of vec2s, bottom row first, like the
+// textures in the OpenGL renderer.
+
+struct FluidUniforms {
+  timestep: f32;
+  epsilon: f32;
+  halfEpsilon: f32;
+  dissipation: f32;
+  texelSize: vec2<f32>;
+  gridSize: vec2<i32>;
+};
+
+struct JacobiUniforms {
+  alpha: f32;
+  rBeta: f32;
+};
+
+struct Field {
+  values: [[stride(8)]] array<vec2<f32>>;
+};
+
+[[group(0), binding(0)]] var<uniform> uniforms: FluidUniforms;
+[[group(0), binding(1)]] var<storage, read> inputA: Field;
+[[group(0), binding(2)]] var<storage, read> inputB: Field;
+[[group(0), binding(3)]] var<storage, read_write> outputField: Field;
+[[group(0), binding(4)]] var<uniform> jacobi: JacobiUniforms;
+
+// Cells outside the field are clamped to the edge.
+fn cellIndex(cell: vec2<i32>) -> u32 {
+  let clamped = clamp(cell, vec2<i32>(0, 0), uniforms.gridSize - vec2<i32>(1, 1));
+  return u32(clamped.y * uniforms.gridSize.x + clamped.x);
+}
+
+fn fetchA(cell: vec2<i32>) -> vec2<f32> {
+  return inputA.values[cellIndex(cell)];
+}
+
+fn fetchB(cell: vec2<i32>) -> vec2<f32> {
+  return inputB.values[cellIndex(cell)];
+}
+
+// Bilinear sampling, with the same texel centers as a texture.
+fn sampleA(coord: vec2<f32>) -> vec2<f32> {
+  let position = coord * vec2<f32>(uniforms.gridSize) - vec2<f32>(0.5, 0.5);
+  let cell = vec2<i32>(floor(position));
+  let t = fract(position);
+
+  let bottom = mix(fetchA(cell), fetchA(cell + vec2<i32>(1, 0)), vec2<f32>(t.x, t.x));
+  let top = mix(fetchA(cell + vec2<i32>(0, 1)), fetchA(cell + vec2<i32>(1, 1)), vec2<f32>(t.x, t.x));
+  return mix(bottom, top, vec2<f32>(t.y, t.y));
+}
+
+fn isInside(cell: vec2<i32>) -> bool {
+  return cell.x < uniforms.gridSize.x && cell.y < uniforms.gridSize.y;
+}
+
+fn textureCoord(cell: vec2<i32>) -> vec2<f32> {
+  return (vec2<f32>(cell) + vec2<f32>(0.5, 0.5)) * uniforms.texelSize;
+}
+
+// A: velocity
+[[stage(compute), workgroup_size(8, 8)]]
+fn advect([[builtin(global_invocation_id)]] id: vec3<u32>) {
+  let cell = vec2<i32>(id.xy);
+  if (!isInside(cell)) {
+    return;
+  }
+
+  let velocity = fetchA(cell);
+  let pastCoord = textureCoord(cell) - uniforms.epsilon * uniforms.timestep * velocity;
+  let decay = 1.0 + uniforms.dissipation * uniforms.timestep;
+  outputField.values[cellIndex(cell)] = sampleA(pastCoord) / decay;
+}
+
+// Used to diffuse the velocity and to solve for the pressure.
+// A: center, B: neighbors
+[[stage(compute), workgroup_size(8, 8)]]
+fn solveJacobi([[builtin(global_invocation_id)]] id: vec3<u32>) {
+  let cell = vec2<i32>(id.xy);
+  if (!isInside(cell)) {
+    return;
+  }
+
+  let L = fetchB(cell - vec2<i32>(1, 0));
+  let R = fetchB(cell + vec2<i32>(1, 0));
+  let T = fetchB(cell + vec2<i32>(0, 1));
+  let B = fetchB(cell - vec2<i32>(0, 1));
+  let center = fetchA(cell);
+
+  outputField.values[cellIndex(cell)] = jacobi.rBeta * (L + R + B + T + jacobi.alpha * center);
+}
+
+// A: velocity
+[[stage(compute), workgroup_size(8, 8)]]
+fn divergence([[builtin(global_invocation_id)]] id: vec3<u32>) {
+  let cell = vec2<i32>(id.xy);
+  if (!isInside(cell)) {
+    return;
+  }
+
+  var L = fetchA(cell - vec2<i32>(1, 0)).x;
+  var R = fetchA(cell + vec2<i32>(1, 0)).x;
+  var T = fetchA(cell + vec2<i32>(0, 1)).y;
+  var B = fetchA(cell - vec2<i32>(0, 1)).y;
+
+  let velocity = fetchA(cell);
+  if (cell.x == 0) { L = -velocity.x; }
+  if (cell.x == uniforms.gridSize.x - 1) { R = -velocity.x; }
+  if (cell.y == uniforms.gridSize.y - 1) { T = -velocity.y; }
+  if (cell.y == 0) { B = -velocity.y; }
+
+  let div = uniforms.halfEpsilon * (R - L + T - B);
+  outputField.values[cellIndex(cell)] = vec2<f32>(div, 0.0);
+}
+
+// A: velocity, B: pressure
+[[stage(compute), workgroup_size(8, 8)]]
+fn subtractGradient([[builtin(global_invocation_id)]] id: vec3<u32>) {
+  let cell = vec2<i32>(id.xy);
+  if (!isInside(cell)) {
+    return;
+  }
+
+  let L = fetchB(cell - vec2<i32>(1, 0)).x;
+  let R = fetchB(cell + vec2<i32>(1, 0)).x;
+  let T = fetchB(cell + vec2<i32>(0, 1)).x;
+  let B = fetchB(cell - vec2<i32>(0, 1)).x;
+
+  let velocity = fetchA(cell);
+  outputField.values[cellIndex(cell)] = velocity - uniforms.halfEpsilon * vec2<f32>(R - L, T - B);
+}
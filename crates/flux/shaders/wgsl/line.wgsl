@@ -0,0 +1,91 @@
+// Same as line.vert/.frag and endpoint.vert/.frag, without the mask and
+// sprites.
+
+struct DrawUniforms {
+  projection: mat4x4<f32>;
+  view: mat4x4<f32>;
+  lineWidth: f32;
+  lineLength: f32;
+  lineBeginOffset: f32;
+  lineFadeOutLength: f32;
+  endpointHeight: f32;
+};
+
+[[group(0), binding(0)]] var<uniform> uniforms: DrawUniforms;
+
+struct Instance {
+  [[location(1)]] basepoint: vec2<f32>;
+  [[location(2)]] endpoint: vec2<f32>;
+  [[location(3)]] velocity: vec2<f32>;
+  [[location(4)]] color: vec4<f32>;
+  [[location(5)]] width: f32;
+  [[location(6)]] opacity: f32;
+};
+
+struct LineOutput {
+  [[builtin(position)]] position: vec4<f32>;
+  [[location(0)]] vertex: vec2<f32>;
+  [[location(1)]] color: vec3<f32>;
+  [[location(2)]] opacity: f32;
+};
+
+[[stage(vertex)]]
+fn lineVertex([[location(0)]] lineVertex: vec2<f32>, instance: Instance) -> LineOutput {
+  let endpoint = instance.basepoint + instance.endpoint * uniforms.lineLength;
+
+  let xBasis = endpoint - instance.basepoint;
+  let yBasis = normalize(vec2<f32>(-xBasis.y, xBasis.x));
+  let point = instance.basepoint + xBasis * lineVertex.x + yBasis * (instance.width * uniforms.lineWidth) * lineVertex.y;
+  let height = lineVertex.x * uniforms.endpointHeight * uniforms.lineLength * length(instance.velocity);
+
+  var out: LineOutput;
+  out.position = uniforms.projection * uniforms.view * vec4<f32>(point, height, 1.0);
+  out.vertex = lineVertex;
+  out.color = instance.color.rgb;
+  out.opacity = instance.opacity;
+  return out;
+}
+
+[[stage(fragment)]]
+fn lineFragment(in: LineOutput) -> [[location(0)]] vec4<f32> {
+  let opacity = in.opacity * smoothStep(uniforms.lineBeginOffset, 1.0, in.vertex.x);
+  return vec4<f32>(in.color * opacity, opacity);
+}
+
+struct EndpointOutput {
+  [[builtin(position)]] position: vec4<f32>;
+  [[location(0)]] vertex: vec2<f32>;
+  [[location(1)]] color: vec3<f32>;
+  [[location(2)]] premultipliedLineColor: vec3<f32>;
+  [[location(3)]] opacity: f32;
+  [[location(4)]] perpendicularVector: vec2<f32>;
+};
+
+[[stage(vertex)]]
+fn endpointVertex([[location(0)]] vertex: vec2<f32>, instance: Instance) -> EndpointOutput {
+  let endpoint = instance.basepoint + instance.endpoint * uniforms.lineLength;
+  let pointSize = uniforms.lineWidth * instance.width;
+  let height = uniforms.endpointHeight * uniforms.lineLength * length(instance.velocity);
+  let position = vec3<f32>(endpoint + 0.5 * pointSize * vertex, height);
+
+  var out: EndpointOutput;
+  out.position = uniforms.projection * uniforms.view * vec4<f32>(position, 1.0);
+  out.vertex = vertex;
+  out.color = instance.color.rgb;
+  out.premultipliedLineColor = instance.color.rgb * instance.opacity;
+  out.opacity = smoothStep(uniforms.lineFadeOutLength, uniforms.lineFadeOutLength + 0.3, length(instance.endpoint));
+  out.perpendicularVector = vec2<f32>(instance.endpoint.y, -instance.endpoint.x);
+  return out;
+}
+
+[[stage(fragment)]]
+fn endpointFragment(in: EndpointOutput) -> [[location(0)]] vec4<f32> {
+  let sideOfEndpoint = in.perpendicularVector.x * in.vertex.y - in.perpendicularVector.y * in.vertex.x;
+
+  var color = in.color;
+  if (sideOfEndpoint < 0.0) {
+    color = in.color - in.premultipliedLineColor;
+  }
+
+  return vec4<f32>(color * in.opacity, in.opacity);
+}
@@ -0,0 +1,167 @@
+struct NoiseUniforms {
+  frequency: f32;
+  offset1: f32;
+  offset2: f32;
+  multiplier: f32;
+  texelSize: vec2<f32>;
+  blendThreshold: f32;
+  blendProgress: f32;
+  gridSize: vec2<i32>;
+};
+
+struct Field {
+  values: [[stride(8)]] array<vec2<f32>>;
+};
+
+[[group(0), binding(0)]] var<uniform> uniforms: NoiseUniforms;
+[[group(0), binding(1)]] var<storage, read> inputField: Field;
+[[group(0), binding(2)]] var<storage, read> noiseField: Field;
+[[group(0), binding(3)]] var<storage, read_write> outputField: Field;
+
+fn cellIndex(cell: vec2<i32>) -> u32 {
+  let clamped = clamp(cell, vec2<i32>(0, 0), uniforms.gridSize - vec2<i32>(1, 1));
+  return u32(clamped.y * uniforms.gridSize.x + clamped.x);
+}
+
+fn fetchNoise(cell: vec2<i32>) -> vec2<f32> {
+  return noiseField.values[cellIndex(cell)];
+}
+
+fn isInside(cell: vec2<i32>) -> bool {
+  return cell.x < uniforms.gridSize.x && cell.y < uniforms.gridSize.y;
+}
+
+fn mod289_3(x: vec3<f32>) -> vec3<f32> {
+  return x - floor(x * (1.0 / 289.0)) * 289.0;
+}
+
+fn mod289_4(x: vec4<f32>) -> vec4<f32> {
+  return x - floor(x * (1.0 / 289.0)) * 289.0;
+}
+
+fn permute(x: vec4<f32>) -> vec4<f32> {
+  return mod289_4(((x * 34.0) + vec4<f32>(1.0, 1.0, 1.0, 1.0)) * x);
+}
+
+fn taylorInvSqrt(r: vec4<f32>) -> vec4<f32> {
+  return vec4<f32>(1.79284291400159, 1.79284291400159, 1.79284291400159, 1.79284291400159) - 0.85373472095314 * r;
+}
+
+// Same as simplex_noise.frag
+fn snoise(v: vec3<f32>) -> f32 {
+  let C = vec2<f32>(1.0 / 6.0, 1.0 / 3.0);
+  let D = vec4<f32>(0.0, 0.5, 1.0, 2.0);
+
+  // First corner
+  var i = floor(v + dot(v, C.yyy));
+  let x0 = v - i + dot(i, C.xxx);
+
+  // Other corners
+  let g = step(x0.yzx, x0.xyz);
+  let l = vec3<f32>(1.0, 1.0, 1.0) - g;
+  let i1 = min(g.xyz, l.zxy);
+  let i2 = max(g.xyz, l.zxy);
+
+  let x1 = x0 - i1 + C.xxx;
+  let x2 = x0 - i2 + C.yyy;
+  let x3 = x0 - D.yyy;
+
+  // Permutations
+  i = mod289_3(i);
+  let p = permute(permute(permute(
+            i.z + vec4<f32>(0.0, i1.z, i2.z, 1.0))
+          + i.y + vec4<f32>(0.0, i1.y, i2.y, 1.0))
+          + i.x + vec4<f32>(0.0, i1.x, i2.x, 1.0));
+
+  // Gradients: 7x7 points over a square, mapped onto an octahedron.
+  let n_ = 0.142857142857; // 1.0 / 7.0
+  let ns = n_ * D.wyz - D.xzx;
+
+  let j = p - 49.0 * floor(p * ns.z * ns.z);
+
+  let x_ = floor(j * ns.z);
+  let y_ = floor(j - 7.0 * x_);
+
+  let x = x_ * ns.x + ns.yyyy;
+  let y = y_ * ns.x + ns.yyyy;
+  let h = vec4<f32>(1.0, 1.0, 1.0, 1.0) - abs(x) - abs(y);
+
+  let b0 = vec4<f32>(x.xy, y.xy);
+  let b1 = vec4<f32>(x.zw, y.zw);
+
+  let s0 = floor(b0) * 2.0 + vec4<f32>(1.0, 1.0, 1.0, 1.0);
+  let s1 = floor(b1) * 2.0 + vec4<f32>(1.0, 1.0, 1.0, 1.0);
+  let sh = -step(h, vec4<f32>(0.0, 0.0, 0.0, 0.0));
+
+  let a0 = b0.xzyw + s0.xzyw * sh.xxyy;
+  let a1 = b1.xzyw + s1.xzyw * sh.zzww;
+
+  // Normalise gradients
+  let norm = taylorInvSqrt(vec4<f32>(dot(a0.xy, a0.xy) + h.x * h.x, dot(a0.zw, a0.zw) + h.y * h.y, dot(a1.xy, a1.xy) + h.z * h.z, dot(a1.zw, a1.zw) + h.w * h.w));
+  let p0 = vec3<f32>(a0.xy, h.x) * norm.x;
+  let p1 = vec3<f32>(a0.zw, h.y) * norm.y;
+  let p2 = vec3<f32>(a1.xy, h.z) * norm.z;
+  let p3 = vec3<f32>(a1.zw, h.w) * norm.w;
+
+  // Mix final noise value
+  var m = max(vec4<f32>(0.6, 0.6, 0.6, 0.6) - vec4<f32>(dot(x0, x0), dot(x1, x1), dot(x2, x2), dot(x3, x3)), vec4<f32>(0.0, 0.0, 0.0, 0.0));
+  m = m * m;
+  return 42.0 * dot(m * m, vec4<f32>(dot(p0, x0), dot(p1, x1), dot(p2, x2), dot(p3, x3)));
+}
+
+[[stage(compute), workgroup_size(8, 8)]]
+fn generate([[builtin(global_invocation_id)]] id: vec3<u32>) {
+  let cell = vec2<i32>(id.xy);
+  if (!isInside(cell)) {
+    return;
+  }
+
+  var st = (vec2<f32>(cell) + vec2<f32>(0.5, 0.5)) * uniforms.texelSize;
+  st.x = st.x * f32(uniforms.gridSize.x) / f32(uniforms.gridSize.y);
+
+  let sx = snoise(vec3<f32>(st * uniforms.frequency, uniforms.offset1));
+  let sy = snoise(vec3<f32>(st * uniforms.frequency, uniforms.offset2));
+
+  outputField.values[cellIndex(cell)] = vec2<f32>(sx, sy);
+}
+
+// Add noise to a field with curl
+[[stage(compute), workgroup_size(8, 8)]]
+fn blendWithCurl([[builtin(global_invocation_id)]] id: vec3<u32>) {
+  let cell = vec2<i32>(id.xy);
+  if (!isInside(cell)) {
+    return;
+  }
+
+  let L = fetchNoise(cell - vec2<i32>(1, 0)).y;
+  let R = fetchNoise(cell + vec2<i32>(1, 0)).y;
+  let T = fetchNoise(cell + vec2<i32>(0, 1)).x;
+  let B = fetchNoise(cell - vec2<i32>(0, 1)).x;
+  var force = vec2<f32>(abs(T) - abs(B), abs(L) - abs(R));
+  force = force / (length(force) + 0.0001);
+
+  if (length(force) < uniforms.blendThreshold) {
+    force = vec2<f32>(0.0, 0.0);
+  }
+
+  let index = cellIndex(cell);
+  outputField.values[index] = inputField.values[index] + uniforms.blendProgress * uniforms.multiplier * force;
+}
+
+[[stage(compute), workgroup_size(8, 8)]]
+fn blendWithWiggle([[builtin(global_invocation_id)]] id: vec3<u32>) {
+  let cell = vec2<i32>(id.xy);
+  if (!isInside(cell)) {
+    return;
+  }
+
+  let index = cellIndex(cell);
+  let noise = noiseField.values[index].x;
+  let inputValue = inputField.values[index];
+
+  let direction = normalize(inputValue);
+  let clockwise = vec2<f32>(direction.y, -direction.x);
+  let force = clockwise * noise;
+
+  outputField.values[index] = inputValue + uniforms.blendProgress * uniforms.multiplier * force;
+}
@@ -0,0 +1,194 @@
+// Same as place_lines.vert, without the mask.
+
+let PI: f32 = 3.1415926535897932384626433832795;
+
+struct PlaceLinesUniforms {
+  projection: mat4x4<f32>;
+  colorWheel: array<vec4<f32>, 6>;
+  velocityOffset: vec2<f32>;
+  gridSize: vec2<i32>;
+  fluidSize: vec2<i32>;
+  timestep: f32;
+  springStiffness: f32;
+  springVariance: f32;
+  springMass: f32;
+  springRestLength: f32;
+  springDamping: f32;
+  springCouplingStiffness: f32;
+  lineFadeOutLength: f32;
+  lineOpaqueLength: f32;
+  lineMinWidth: f32;
+  lineMaxWidth: f32;
+  lineMaxLength: f32;
+  colorResponse: f32;
+  adjustAdvection: f32;
+  advectionDirection: f32;
+  velocityScale: f32;
+};
+
+struct LineState {
+  endpoint: vec2<f32>;
+  velocity: vec2<f32>;
+  color: vec4<f32>;
+  width: f32;
+  opacity: f32;
+};
+
+struct Lines {
+  lines: [[stride(48)]] array<LineState>;
+};
+
+struct Points {
+  points: [[stride(8)]] array<vec2<f32>>;
+};
+
+[[group(0), binding(0)]] var<uniform> uniforms: PlaceLinesUniforms;
+[[group(0), binding(1)]] var<storage, read> basepoints: Points;
+[[group(0), binding(2)]] var<storage, read> velocityField: Points;
+[[group(0), binding(3)]] var<storage, read> inputLines: Lines;
+[[group(0), binding(4)]] var<storage, read_write> outputLines: Lines;
+
+fn fetchVelocity(cell: vec2<i32>) -> vec2<f32> {
+  let clamped = clamp(cell, vec2<i32>(0, 0), uniforms.fluidSize - vec2<i32>(1, 1));
+  return velocityField.points[clamped.y * uniforms.fluidSize.x + clamped.x];
+}
+
+// Bilinear sampling, with the same texel centers as a texture.
+fn sampleVelocity(coord: vec2<f32>) -> vec2<f32> {
+  let position = coord * vec2<f32>(uniforms.fluidSize) - vec2<f32>(0.5, 0.5);
+  let cell = vec2<i32>(floor(position));
+  let t = fract(position);
+
+  let bottom = mix(fetchVelocity(cell), fetchVelocity(cell + vec2<i32>(1, 0)), vec2<f32>(t.x, t.x));
+  let top = mix(fetchVelocity(cell + vec2<i32>(0, 1)), fetchVelocity(cell + vec2<i32>(1, 1)), vec2<f32>(t.x, t.x));
+  return mix(bottom, top, vec2<f32>(t.y, t.y));
+}
+
+fn getColor(angle: f32) -> vec3<f32> {
+  let slice = 2.0 * PI / 6.0;
+  let rawIndex = angle / slice;
+  let index = floor(rawIndex);
+  let nextIndex = (index + 1.0) % 6.0;
+  let interpolate = fract(rawIndex);
+
+  let currentColor = uniforms.colorWheel[i32(index)].rgb;
+  let nextColor = uniforms.colorWheel[i32(nextIndex)].rgb;
+  return mix(currentColor, nextColor, vec3<f32>(interpolate, interpolate, interpolate));
+}
+
+fn springForce(stiffness: f32, mass: f32, displacement: f32) -> f32 {
+  return (-stiffness * displacement) / mass;
+}
+
+fn random1f(st: vec2<f32>) -> f32 {
+  return fract(sin(dot(st, vec2<f32>(12.9898, 78.233))) * 43758.5453123);
+}
+
+// The average endpoint of the lines around this one
+fn getNeighborAverage(index: i32) -> vec2<f32> {
+  let cell = vec2<i32>(index % uniforms.gridSize.x, index / uniforms.gridSize.x);
+  let lastCell = uniforms.gridSize - vec2<i32>(1, 1);
+
+  let left = clamp(cell + vec2<i32>(-1, 0), vec2<i32>(0, 0), lastCell);
+  let right = clamp(cell + vec2<i32>(1, 0), vec2<i32>(0, 0), lastCell);
+  let bottom = clamp(cell + vec2<i32>(0, -1), vec2<i32>(0, 0), lastCell);
+  let top = clamp(cell + vec2<i32>(0, 1), vec2<i32>(0, 0), lastCell);
+
+  let sum = inputLines.lines[left.y * uniforms.gridSize.x + left.x].endpoint
+          + inputLines.lines[right.y * uniforms.gridSize.x + right.x].endpoint
+          + inputLines.lines[bottom.y * uniforms.gridSize.x + bottom.x].endpoint
+          + inputLines.lines[top.y * uniforms.gridSize.x + top.x].endpoint;
+
+  return sum / 4.0;
+}
+
+fn easeInOutQuad(t: f32) -> f32 {
+  let p = 2.0 * t * t;
+  return select(-p + (4.0 * t) - 1.0, p, t < 0.5);
+}
+
+fn safeNormalize(v: vec2<f32>) -> vec2<f32> {
+  let len = length(v);
+  if (len == 0.0) {
+    return vec2<f32>(0.0, 0.0);
+  }
+  return v / len;
+}
+
+[[stage(compute), workgroup_size(64)]]
+fn main([[builtin(global_invocation_id)]] id: vec3<u32>) {
+  let index = i32(id.x);
+  if (index >= uniforms.gridSize.x * uniforms.gridSize.y) {
+    return;
+  }
+
+  let deltaT = uniforms.timestep;
+  let basepoint = basepoints.points[index];
+  let line = inputLines.lines[index];
+
+  // Velocity
+  let basepointInClipSpace = (uniforms.projection * vec4<f32>(basepoint, 0.0, 1.0)).xy;
+  let currentVelocityVector = uniforms.velocityScale * sampleVelocity(basepointInClipSpace * 0.5 + vec2<f32>(0.5, 0.5) + uniforms.velocityOffset);
+  let deltaVelocity = currentVelocityVector - line.velocity;
+  var velocity = line.velocity + (deltaVelocity / uniforms.springMass) * deltaT;
+
+  // Spring forces
+  let variance = 1.0 + uniforms.springVariance * random1f(basepoint);
+  var currentLength = length(line.endpoint);
+  let direction = safeNormalize(line.endpoint);
+
+  // Main spring
+  velocity = velocity + uniforms.advectionDirection * springForce(
+    uniforms.springStiffness,
+    uniforms.springMass * variance,
+    currentLength - uniforms.springRestLength
+  ) * direction * deltaT;
+
+  // Coupling springs pull the endpoint towards those of its neighbors
+  if (uniforms.springCouplingStiffness > 0.0) {
+    velocity = velocity + uniforms.advectionDirection * springForce(
+      uniforms.springCouplingStiffness,
+      uniforms.springMass * variance,
+      1.0
+    ) * (line.endpoint - getNeighborAverage(index)) * deltaT;
+  }
+
+  // Damping. Decay exponentially, so that a large step can’t overshoot and
+  // flip the velocity.
+  velocity = velocity * exp(-uniforms.springDamping / (uniforms.springMass * variance) * deltaT);
+
+  // Advect forward
+  var endpoint = line.endpoint + uniforms.adjustAdvection * uniforms.advectionDirection * velocity * deltaT;
+  currentLength = length(endpoint);
+
+  if (uniforms.lineMaxLength > 0.0 && currentLength > uniforms.lineMaxLength) {
+    endpoint = endpoint * (uniforms.lineMaxLength / currentLength);
+    currentLength = uniforms.lineMaxLength;
+  }
+
+  // Color
+  let angle = (PI / 6.0 * currentLength + (PI + atan2(line.endpoint.y, line.endpoint.x))) % (2.0 * PI);
+  let newColor = vec4<f32>(getColor(angle), 0.0);
+  let colorDiff = newColor - line.color;
+  let color = clamp(
+    line.color + colorDiff * min(uniforms.colorResponse * deltaT, 1.0),
+    vec4<f32>(0.0, 0.0, 0.0, 0.0),
+    vec4<f32>(1.0, 1.0, 1.0, 1.0)
+  );
+
+  // Width
+  let velocityDirection = safeNormalize(uniforms.advectionDirection * velocity);
+  let lineDirection = safeNormalize(endpoint);
+  let directionAlignment = clamp(dot(lineDirection, velocityDirection), -1.0, 1.0);
+
+  let width = clamp(
+    line.width + uniforms.adjustAdvection * directionAlignment * length(velocity) * deltaT,
+    uniforms.lineMinWidth,
+    uniforms.lineMaxWidth
+  );
+
+  // Opacity
+  let opacity = easeInOutQuad(smoothStep(uniforms.lineFadeOutLength, uniforms.lineOpaqueLength, currentLength));
+
+  outputLines.lines[index] = LineState(endpoint, velocity, color, width, opacity);
+}
@@ -0,0 +1,22 @@
+use crate::settings::Settings;
+use crate::view::Transform2D;
+
+use std::rc::Rc;
+
+// What the apps need from a renderer, whichever graphics API it runs on. The
+// OpenGL renderer is `Flux`. The experimental wgpu one is `webgpu::Flux`,
+// behind the `experimental-wgpu` feature.
+pub trait Backend {
+    // What a frame is drawn to. The OpenGL renderer draws to whichever
+    // framebuffer is bound, so it doesn’t need one.
+    type Target: ?Sized;
+    type Error;
+
+    fn update(&mut self, settings: &Rc<Settings>) -> Result<(), Self::Error>;
+
+    fn resize(&mut self, logical_width: u32, logical_height: u32) -> Result<(), Self::Error>;
+
+    fn set_view(&mut self, view: Transform2D) -> ();
+
+    fn animate(&mut self, timestamp: f32, target: &Self::Target) -> ();
+}
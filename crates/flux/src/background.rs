@@ -0,0 +1,271 @@
+use crate::{data, render, settings};
+use render::{
+    Buffer, Context, Framebuffer, Profiler, TextureOptions, Uniform, UniformValue,
+    VertexArrayObject, VertexBufferLayout,
+};
+use settings::{Background, Settings};
+
+use glow::HasContext;
+use std::rc::Rc;
+
+static BACKGROUND_VERT_SHADER: &'static str =
+    include_str!(concat!(env!("OUT_DIR"), "/shaders/background.vert"));
+static BACKGROUND_FRAG_SHADER: &'static str =
+    include_str!(concat!(env!("OUT_DIR"), "/shaders/background.frag"));
+
+// Keep in sync with background.frag
+const SOLID: i32 = 0;
+const LINEAR_GRADIENT: i32 = 1;
+const RADIAL_GRADIENT: i32 = 2;
+const IMAGE: i32 = 3;
+
+pub struct BackgroundDrawer {
+    context: Context,
+    profiler: Profiler,
+    background: Background,
+
+    physical_width: u32,
+    physical_height: u32,
+    pixel_ratio: f64,
+
+    image: Option<Framebuffer>,
+
+    plane_buffer: VertexArrayObject,
+    draw_background_pass: render::Program,
+}
+
+impl BackgroundDrawer {
+    pub fn new(
+        context: &Context,
+        profiler: &Profiler,
+        logical_width: u32,
+        logical_height: u32,
+        pixel_ratio: f64,
+        settings: &Rc<Settings>,
+    ) -> Result<Self, render::Problem> {
+        let plane_vertices = Buffer::from_f32(
+            &context,
+            &data::PLANE_VERTICES,
+            glow::ARRAY_BUFFER,
+            glow::STATIC_DRAW,
+        )?;
+        let plane_indices = Buffer::from_u16(
+            &context,
+            &data::PLANE_INDICES,
+            glow::ELEMENT_ARRAY_BUFFER,
+            glow::STATIC_DRAW,
+        )?;
+
+        let draw_background_program =
+            render::Program::new(&context, (BACKGROUND_VERT_SHADER, BACKGROUND_FRAG_SHADER))?;
+
+        let plane_buffer = VertexArrayObject::new(
+            &context,
+            &draw_background_program,
+            &[(
+                &plane_vertices,
+                VertexBufferLayout {
+                    name: "position",
+                    size: 3,
+                    type_: glow::FLOAT,
+                    ..Default::default()
+                },
+            )],
+            Some(&plane_indices),
+        )?;
+
+        draw_background_program.set_uniform(&Uniform {
+            name: "imageTexture",
+            value: UniformValue::Texture2D(0),
+        });
+
+        let mut background_drawer = Self {
+            context: Rc::clone(context),
+            profiler: profiler.clone(),
+            background: settings.background.clone(),
+
+            physical_width: 0,
+            physical_height: 0,
+            pixel_ratio,
+
+            image: None,
+
+            plane_buffer,
+            draw_background_pass: draw_background_program,
+        };
+
+        background_drawer.resize(logical_width, logical_height);
+        background_drawer.update(settings);
+
+        Ok(background_drawer)
+    }
+
+    pub fn update(&mut self, settings: &Rc<Settings>) -> () {
+        self.background = settings.background.clone();
+
+        match self.background {
+            Background::Solid(color) => {
+                self.draw_background_pass.set_uniforms(&[
+                    &Uniform {
+                        name: "uBackgroundType",
+                        value: UniformValue::SignedInt(SOLID),
+                    },
+                    &Uniform {
+                        name: "uColor1",
+                        value: UniformValue::Vec4(&color),
+                    },
+                ]);
+            }
+
+            Background::LinearGradient { from, to, angle } => {
+                let angle = angle.to_radians();
+                self.draw_background_pass.set_uniforms(&[
+                    &Uniform {
+                        name: "uBackgroundType",
+                        value: UniformValue::SignedInt(LINEAR_GRADIENT),
+                    },
+                    &Uniform {
+                        name: "uColor1",
+                        value: UniformValue::Vec4(&from),
+                    },
+                    &Uniform {
+                        name: "uColor2",
+                        value: UniformValue::Vec4(&to),
+                    },
+                    &Uniform {
+                        name: "uGradientDirection",
+                        value: UniformValue::Vec2(&[angle.cos(), angle.sin()]),
+                    },
+                ]);
+            }
+
+            Background::RadialGradient { inner, outer } => {
+                self.draw_background_pass.set_uniforms(&[
+                    &Uniform {
+                        name: "uBackgroundType",
+                        value: UniformValue::SignedInt(RADIAL_GRADIENT),
+                    },
+                    &Uniform {
+                        name: "uColor1",
+                        value: UniformValue::Vec4(&inner),
+                    },
+                    &Uniform {
+                        name: "uColor2",
+                        value: UniformValue::Vec4(&outer),
+                    },
+                ]);
+            }
+
+            Background::Image => {
+                self.draw_background_pass.set_uniform(&Uniform {
+                    name: "uBackgroundType",
+                    value: UniformValue::SignedInt(IMAGE),
+                });
+            }
+
+            Background::Transparent => (),
+        }
+    }
+
+    pub fn resize(&mut self, logical_width: u32, logical_height: u32) -> () {
+        self.physical_width = (f64::from(logical_width) * self.pixel_ratio) as u32;
+        self.physical_height = (f64::from(logical_height) * self.pixel_ratio) as u32;
+
+        let (width, height) = (self.physical_width as f32, self.physical_height as f32);
+        let aspect_ratio = if width > height {
+            [width / height, 1.0]
+        } else {
+            [1.0, height / width]
+        };
+
+        self.draw_background_pass.set_uniform(&Uniform {
+            name: "uAspectRatio",
+            value: UniformValue::Vec2(&aspect_ratio),
+        });
+        self.update_image_scale();
+    }
+
+    // Expects tightly packed, straight alpha RGBA pixels, top row first.
+    pub fn set_image(
+        &mut self,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+    ) -> Result<(), render::Problem> {
+        let image = Framebuffer::new(
+            &self.context,
+            width,
+            height,
+            TextureOptions {
+                mag_filter: glow::LINEAR,
+                min_filter: glow::LINEAR,
+                format: glow::RGBA8,
+                ..Default::default()
+            },
+        )?
+        .with_u8_data(pixels)?;
+
+        self.image = Some(image);
+        self.update_image_scale();
+
+        Ok(())
+    }
+
+    // Scale the image to cover the canvas, cropping whatever doesn’t fit.
+    fn update_image_scale(&self) -> () {
+        if let Some(image) = &self.image {
+            let canvas_aspect = self.physical_width as f32 / self.physical_height as f32;
+            let image_aspect = image.width as f32 / image.height as f32;
+
+            let image_scale = if canvas_aspect > image_aspect {
+                [1.0, image_aspect / canvas_aspect]
+            } else {
+                [canvas_aspect / image_aspect, 1.0]
+            };
+
+            self.draw_background_pass.set_uniform(&Uniform {
+                name: "uImageScale",
+                value: UniformValue::Vec2(&image_scale),
+            });
+        }
+    }
+
+    pub fn draw(&self) -> () {
+        let _pass = self.profiler.pass("background/draw");
+
+        unsafe {
+            self.context.clear_color(0.0, 0.0, 0.0, 0.0);
+            self.context.clear(glow::COLOR_BUFFER_BIT);
+        }
+
+        let image = match (&self.background, &self.image) {
+            (Background::Transparent, _) => return,
+            // There’s nothing to draw until we get an image.
+            (Background::Image, None) => return,
+            (Background::Image, Some(image)) => Some(image),
+            _ => None,
+        };
+
+        unsafe {
+            self.context.viewport(
+                0,
+                0,
+                self.physical_width as i32,
+                self.physical_height as i32,
+            );
+            self.context.disable(glow::BLEND);
+
+            self.draw_background_pass.use_program();
+            self.context.bind_vertex_array(Some(self.plane_buffer.id));
+
+            if let Some(image) = image {
+                self.context.active_texture(glow::TEXTURE0);
+                self.context
+                    .bind_texture(glow::TEXTURE_2D, Some(image.texture));
+            }
+
+            self.context
+                .draw_elements(glow::TRIANGLES, 6, glow::UNSIGNED_SHORT, 0);
+        }
+    }
+}
@@ -0,0 +1,487 @@
+use crate::{data, drawer, render, settings};
+use drawer::{disable_blend_mode, enable_blend_mode};
+use render::{
+    Buffer, Context, Framebuffer, Profiler, TextureOptions, Uniform, UniformValue,
+    VertexArrayObject, VertexBufferLayout,
+};
+use settings::{BlendMode, Settings};
+
+use glow::HasContext;
+use std::rc::Rc;
+
+static BLOOM_VERT_SHADER: &'static str =
+    include_str!(concat!(env!("OUT_DIR"), "/shaders/bloom.vert"));
+static BLOOM_PREFILTER_FRAG_SHADER: &'static str =
+    include_str!(concat!(env!("OUT_DIR"), "/shaders/bloom_prefilter.frag"));
+static BLOOM_BLUR_FRAG_SHADER: &'static str =
+    include_str!(concat!(env!("OUT_DIR"), "/shaders/bloom_blur.frag"));
+static BLOOM_COMBINE_FRAG_SHADER: &'static str =
+    include_str!(concat!(env!("OUT_DIR"), "/shaders/bloom_combine.frag"));
+static BLOOM_SOURCE_FRAG_SHADER: &'static str =
+    include_str!(concat!(env!("OUT_DIR"), "/shaders/bloom_source.frag"));
+
+// The maximum number of levels in the blur pyramid. Each level is half the
+// size of the previous one.
+const MAX_LEVELS: usize = 5;
+
+struct BloomLevel {
+    // The target of the horizontal blur
+    blur: Framebuffer,
+    // The target of the vertical blur, and the output of this level
+    texture: Framebuffer,
+}
+
+struct BloomBuffers {
+    // The frame, drawn without the background
+    source: Framebuffer,
+    // The frame is drawn here first when it’s multisampled, so that the lines
+    // are antialiased before they’re drawn over the background.
+    multisampled: Option<render::MsaaPass>,
+    // The bright parts of the frame, at half resolution
+    prefiltered: Framebuffer,
+    levels: Vec<BloomLevel>,
+}
+
+pub struct Bloom {
+    context: Context,
+    profiler: Profiler,
+    settings: Rc<Settings>,
+
+    physical_width: u32,
+    physical_height: u32,
+    pixel_ratio: f64,
+    // The number of samples used to antialias the frame
+    samples: u32,
+
+    // Only allocated when bloom is enabled.
+    buffers: Option<BloomBuffers>,
+
+    prefilter_buffer: VertexArrayObject,
+    blur_buffer: VertexArrayObject,
+    combine_buffer: VertexArrayObject,
+    source_buffer: VertexArrayObject,
+
+    prefilter_pass: render::Program,
+    blur_pass: render::Program,
+    combine_pass: render::Program,
+    source_pass: render::Program,
+}
+
+impl Bloom {
+    pub fn new(
+        context: &Context,
+        profiler: &Profiler,
+        logical_width: u32,
+        logical_height: u32,
+        pixel_ratio: f64,
+        settings: &Rc<Settings>,
+    ) -> Result<Self, render::Problem> {
+        let plane_vertices = Buffer::from_f32(
+            &context,
+            &data::PLANE_VERTICES,
+            glow::ARRAY_BUFFER,
+            glow::STATIC_DRAW,
+        )?;
+        let plane_indices = Buffer::from_u16(
+            &context,
+            &data::PLANE_INDICES,
+            glow::ELEMENT_ARRAY_BUFFER,
+            glow::STATIC_DRAW,
+        )?;
+
+        let prefilter_program =
+            render::Program::new(&context, (BLOOM_VERT_SHADER, BLOOM_PREFILTER_FRAG_SHADER))?;
+        let blur_program =
+            render::Program::new(&context, (BLOOM_VERT_SHADER, BLOOM_BLUR_FRAG_SHADER))?;
+        let combine_program =
+            render::Program::new(&context, (BLOOM_VERT_SHADER, BLOOM_COMBINE_FRAG_SHADER))?;
+        let source_program =
+            render::Program::new(&context, (BLOOM_VERT_SHADER, BLOOM_SOURCE_FRAG_SHADER))?;
+
+        let prefilter_buffer = VertexArrayObject::new(
+            &context,
+            &prefilter_program,
+            &[(
+                &plane_vertices,
+                VertexBufferLayout {
+                    name: "position",
+                    size: 3,
+                    type_: glow::FLOAT,
+                    ..Default::default()
+                },
+            )],
+            Some(&plane_indices),
+        )?;
+        let blur_buffer = VertexArrayObject::new(
+            &context,
+            &blur_program,
+            &[(
+                &plane_vertices,
+                VertexBufferLayout {
+                    name: "position",
+                    size: 3,
+                    type_: glow::FLOAT,
+                    ..Default::default()
+                },
+            )],
+            Some(&plane_indices),
+        )?;
+        let combine_buffer = VertexArrayObject::new(
+            &context,
+            &combine_program,
+            &[(
+                &plane_vertices,
+                VertexBufferLayout {
+                    name: "position",
+                    size: 3,
+                    type_: glow::FLOAT,
+                    ..Default::default()
+                },
+            )],
+            Some(&plane_indices),
+        )?;
+        let source_buffer = VertexArrayObject::new(
+            &context,
+            &source_program,
+            &[(
+                &plane_vertices,
+                VertexBufferLayout {
+                    name: "position",
+                    size: 3,
+                    type_: glow::FLOAT,
+                    ..Default::default()
+                },
+            )],
+            Some(&plane_indices),
+        )?;
+
+        for program in [
+            &prefilter_program,
+            &blur_program,
+            &combine_program,
+            &source_program,
+        ] {
+            program.set_uniform(&Uniform {
+                name: "inputTexture",
+                value: UniformValue::Texture2D(0),
+            });
+        }
+
+        let mut bloom = Self {
+            context: Rc::clone(context),
+            profiler: profiler.clone(),
+            settings: Rc::clone(settings),
+
+            physical_width: 0,
+            physical_height: 0,
+            pixel_ratio,
+            samples: 0,
+
+            buffers: None,
+
+            prefilter_buffer,
+            blur_buffer,
+            combine_buffer,
+            source_buffer,
+
+            prefilter_pass: prefilter_program,
+            blur_pass: blur_program,
+            combine_pass: combine_program,
+            source_pass: source_program,
+        };
+
+        bloom.resize(logical_width, logical_height)?;
+        bloom.update(settings)?;
+
+        Ok(bloom)
+    }
+
+    pub fn update(&mut self, settings: &Rc<Settings>) -> Result<(), render::Problem> {
+        self.settings = Rc::clone(settings);
+
+        if self.is_enabled() != self.buffers.is_some() {
+            self.buffers = self.new_buffers()?;
+        }
+
+        self.prefilter_pass.set_uniform(&Uniform {
+            name: "uThreshold",
+            value: UniformValue::Float(settings.bloom_threshold),
+        });
+
+        Ok(())
+    }
+
+    pub fn resize(
+        &mut self,
+        logical_width: u32,
+        logical_height: u32,
+    ) -> Result<(), render::Problem> {
+        self.physical_width = (f64::from(logical_width) * self.pixel_ratio) as u32;
+        self.physical_height = (f64::from(logical_height) * self.pixel_ratio) as u32;
+        self.buffers = self.new_buffers()?;
+
+        Ok(())
+    }
+
+    // Match the antialiasing of the frame the capture is drawn onto.
+    pub fn set_samples(&mut self, samples: u32) -> Result<(), render::Problem> {
+        if samples != self.samples {
+            self.samples = samples;
+            self.buffers = self.new_buffers()?;
+        }
+
+        Ok(())
+    }
+
+    // Lines multiplied onto a cleared buffer leave nothing to glow.
+    pub fn is_enabled(&self) -> bool {
+        self.settings.bloom_intensity > 0.0 && self.settings.blend_mode != BlendMode::Multiply
+    }
+
+    // Draw the frame, without the background, once. It’s both blurred into the
+    // glow and drawn over the background with `draw_capture`.
+    pub fn capture<T>(&self, draw_call: T) -> ()
+    where
+        T: Fn() -> (),
+    {
+        let _pass = self.profiler.pass("bloom/capture");
+
+        let buffers = match &self.buffers {
+            Some(buffers) => buffers,
+            None => return,
+        };
+
+        let clear_and_draw = || unsafe {
+            self.context.clear_color(0.0, 0.0, 0.0, 0.0);
+            self.context.clear(glow::COLOR_BUFFER_BIT);
+
+            draw_call();
+        };
+
+        match &buffers.multisampled {
+            Some(multisampled) => {
+                multisampled.draw_to(clear_and_draw);
+                multisampled.resolve_into(&buffers.source);
+            }
+            None => buffers.source.draw_to(&self.context, clear_and_draw),
+        }
+    }
+
+    // Draw the captured frame over the current framebuffer.
+    pub fn draw_capture(&self) -> () {
+        let _pass = self.profiler.pass("bloom/draw_capture");
+
+        let buffers = match &self.buffers {
+            Some(buffers) => buffers,
+            None => return,
+        };
+
+        unsafe {
+            self.context.viewport(
+                0,
+                0,
+                self.physical_width as i32,
+                self.physical_height as i32,
+            );
+
+            enable_blend_mode(&self.context, &self.settings.blend_mode);
+        }
+
+        self.draw_plane(&self.source_pass, &self.source_buffer, &buffers.source);
+
+        unsafe {
+            disable_blend_mode(&self.context);
+        }
+    }
+
+    // Extract the bright parts of the captured frame and blur them across
+    // the pyramid.
+    pub fn blur(&self) -> () {
+        let _pass = self.profiler.pass("bloom/blur");
+
+        let buffers = match &self.buffers {
+            Some(buffers) => buffers,
+            None => return,
+        };
+
+        unsafe {
+            self.context.disable(glow::BLEND);
+        }
+
+        self.prefilter_pass.set_uniform(&Uniform {
+            name: "uTexelSize",
+            value: UniformValue::Vec2(&[
+                1.0 / buffers.source.width as f32,
+                1.0 / buffers.source.height as f32,
+            ]),
+        });
+        buffers.prefiltered.draw_to(&self.context, || {
+            self.draw_plane(
+                &self.prefilter_pass,
+                &self.prefilter_buffer,
+                &buffers.source,
+            );
+        });
+
+        let radius = self.settings.bloom_radius;
+        let mut input = &buffers.prefiltered;
+
+        for level in buffers.levels.iter() {
+            self.blur_pass.set_uniform(&Uniform {
+                name: "uDirection",
+                value: UniformValue::Vec2(&[radius / input.width as f32, 0.0]),
+            });
+            level.blur.draw_to(&self.context, || {
+                self.draw_plane(&self.blur_pass, &self.blur_buffer, input);
+            });
+
+            self.blur_pass.set_uniform(&Uniform {
+                name: "uDirection",
+                value: UniformValue::Vec2(&[0.0, radius / level.blur.height as f32]),
+            });
+            level.texture.draw_to(&self.context, || {
+                self.draw_plane(&self.blur_pass, &self.blur_buffer, &level.blur);
+            });
+
+            input = &level.texture;
+        }
+
+        // Add each level onto the next larger one, working our way back up the
+        // pyramid.
+        unsafe {
+            self.context.enable(glow::BLEND);
+            self.context.blend_func(glow::ONE, glow::ONE);
+        }
+
+        self.combine_pass.set_uniform(&Uniform {
+            name: "uIntensity",
+            value: UniformValue::Float(1.0),
+        });
+        for pair in buffers.levels.windows(2).rev() {
+            pair[0].texture.draw_to(&self.context, || {
+                self.draw_plane(&self.combine_pass, &self.combine_buffer, &pair[1].texture);
+            });
+        }
+
+        unsafe {
+            self.context.disable(glow::BLEND);
+        }
+    }
+
+    // Add the glow on top of the current framebuffer.
+    pub fn draw(&self) -> () {
+        let _pass = self.profiler.pass("bloom/draw");
+
+        let levels = match &self.buffers {
+            Some(buffers) if !buffers.levels.is_empty() => &buffers.levels,
+            _ => return,
+        };
+
+        unsafe {
+            self.context.viewport(
+                0,
+                0,
+                self.physical_width as i32,
+                self.physical_height as i32,
+            );
+
+            self.context.enable(glow::BLEND);
+            self.context.blend_func(glow::ONE, glow::ONE);
+        }
+
+        // The top level holds the sum of all the levels, so average them out.
+        self.combine_pass.set_uniform(&Uniform {
+            name: "uIntensity",
+            value: UniformValue::Float(self.settings.bloom_intensity / levels.len() as f32),
+        });
+        self.draw_plane(&self.combine_pass, &self.combine_buffer, &levels[0].texture);
+
+        unsafe {
+            self.context.disable(glow::BLEND);
+        }
+    }
+
+    fn draw_plane(
+        &self,
+        program: &render::Program,
+        vertex_buffer: &VertexArrayObject,
+        texture: &Framebuffer,
+    ) -> () {
+        program.use_program();
+
+        unsafe {
+            self.context.bind_vertex_array(Some(vertex_buffer.id));
+
+            self.context.active_texture(glow::TEXTURE0);
+            self.context
+                .bind_texture(glow::TEXTURE_2D, Some(texture.texture));
+
+            self.context
+                .draw_elements(glow::TRIANGLES, 6, glow::UNSIGNED_SHORT, 0);
+        }
+    }
+
+    fn new_buffers(&self) -> Result<Option<BloomBuffers>, render::Problem> {
+        if !self.is_enabled() {
+            return Ok(None);
+        }
+
+        let (width, height) = (self.physical_width, self.physical_height);
+        let source = new_bloom_buffer(&self.context, width, height)?;
+        let multisampled = if self.samples > 0 {
+            Some(render::MsaaPass::new(
+                &self.context,
+                source.width,
+                source.height,
+                self.samples,
+                glow::RGBA16F,
+            )?)
+        } else {
+            None
+        };
+        let prefiltered = new_bloom_buffer(&self.context, width / 2, height / 2)?;
+
+        let mut levels = Vec::with_capacity(MAX_LEVELS);
+        let (mut level_width, mut level_height) = (width / 2, height / 2);
+
+        while levels.len() < MAX_LEVELS && level_width >= 2 && level_height >= 2 {
+            levels.push(BloomLevel {
+                blur: new_bloom_buffer(&self.context, level_width, level_height)?,
+                texture: new_bloom_buffer(&self.context, level_width, level_height)?,
+            });
+
+            level_width /= 2;
+            level_height /= 2;
+        }
+
+        Ok(Some(BloomBuffers {
+            source,
+            multisampled,
+            prefiltered,
+            levels,
+        }))
+    }
+}
+
+// Half floats keep the highlights from additively blended lines, which would
+// otherwise be clipped at 1.0.
+fn new_bloom_buffer(
+    context: &Context,
+    width: u32,
+    height: u32,
+) -> Result<Framebuffer, render::Problem> {
+    let (width, height) = (width.max(1), height.max(1));
+
+    Framebuffer::new(
+        context,
+        width,
+        height,
+        TextureOptions {
+            mag_filter: glow::LINEAR,
+            min_filter: glow::LINEAR,
+            format: glow::RGBA16F,
+            ..Default::default()
+        },
+    )?
+    .with_f32_data(&vec![0.0; (4 * width * height) as usize])
+}
@@ -1,8 +1,12 @@
-use crate::{data, render, settings};
+#[cfg(feature = "hot-reload")]
+use crate::hot_reload::{reload_program, ShaderError};
+use crate::{render, settings, view};
 use render::{
-    Buffer, Context, Framebuffer, Uniform, UniformValue, VertexArrayObject, VertexBufferLayout,
+    Buffer, Context, Framebuffer, Profiler, Uniform, UniformValue, VertexArrayObject,
+    VertexBufferLayout,
 };
-use settings::Settings;
+use settings::{BlendMode, Camera, EndpointShape, Settings};
+use view::{Transform2D, VirtualCanvas};
 
 extern crate nalgebra_glm as glm;
 use bytemuck::{Pod, Zeroable};
@@ -14,21 +18,25 @@ static LINE_VERT_SHADER: &'static str =
     include_str!(concat!(env!("OUT_DIR"), "/shaders/line.vert"));
 static LINE_FRAG_SHADER: &'static str =
     include_str!(concat!(env!("OUT_DIR"), "/shaders/line.frag"));
+static STREAMLINE_VERT_SHADER: &'static str =
+    include_str!(concat!(env!("OUT_DIR"), "/shaders/streamline.vert"));
 static ENDPOINT_VERT_SHADER: &'static str =
     include_str!(concat!(env!("OUT_DIR"), "/shaders/endpoint.vert"));
 static ENDPOINT_FRAG_SHADER: &'static str =
     include_str!(concat!(env!("OUT_DIR"), "/shaders/endpoint.frag"));
-static TEXTURE_VERT_SHADER: &'static str =
-    include_str!(concat!(env!("OUT_DIR"), "/shaders/texture.vert"));
-static TEXTURE_FRAG_SHADER: &'static str =
-    include_str!(concat!(env!("OUT_DIR"), "/shaders/texture.frag"));
 static PLACE_LINES_VERT_SHADER: &'static str =
     include_str!(concat!(env!("OUT_DIR"), "/shaders/place_lines.vert"));
 static PLACE_LINES_FRAG_SHADER: &'static str =
     include_str!(concat!(env!("OUT_DIR"), "/shaders/place_lines.frag"));
+static LINE_STATE_VERT_SHADER: &'static str =
+    include_str!(concat!(env!("OUT_DIR"), "/shaders/line_state.vert"));
+static LINE_STATE_FRAG_SHADER: &'static str =
+    include_str!(concat!(env!("OUT_DIR"), "/shaders/line_state.frag"));
 
+// A quad from the basepoint (x = 0) to the endpoint (x = 1). The particles are
+// drawn with it too.
 #[rustfmt::skip]
-const LINE_VERTICES: [f32; 12] = [
+pub(crate) const LINE_VERTICES: [f32; 12] = [
     0.0, -0.5,
     1.0, -0.5,
     1.0, 0.5,
@@ -47,11 +55,24 @@ struct LineState {
     opacity: f32,
 }
 
+// The state of a line after the last simulation step. Positions are in
+// unscaled logical pixels, relative to the center of the canvas, with y
+// pointing up. The view and `view_scale` aren’t applied.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Line {
+    pub basepoint: [f32; 2],
+    pub endpoint: [f32; 2],
+    pub color: [f32; 3],
+    // In logical pixels
+    pub width: f32,
+    pub opacity: f32,
+}
+
 #[repr(C)]
 #[derive(Clone, Copy, Pod, Zeroable)]
-struct Projection {
-    projection: [f32; 16],
-    view: [f32; 16],
+pub(crate) struct Projection {
+    pub(crate) projection: [f32; 16],
+    pub(crate) view: [f32; 16],
 }
 
 #[repr(C)]
@@ -61,6 +82,9 @@ struct LineUniforms {
     line_length: f32,
     line_begin_offset: f32,
     line_fade_out_length: f32,
+    endpoint_height: f32,
+    // Pad the block to a multiple of vec4
+    _padding: [f32; 3],
 }
 
 impl LineUniforms {
@@ -70,23 +94,33 @@ impl LineUniforms {
             line_length: (f64::from(settings.line_length) * pixel_ratio) as f32,
             line_begin_offset: settings.line_begin_offset,
             line_fade_out_length: settings.line_fade_out_length,
+            endpoint_height: settings.endpoint_height,
+            _padding: [0.0; 3],
         }
     }
 }
 
+// A grid of lines. The main grid and each layer get their own, and draw into
+// the frame set up by the `FrameDrawer`.
 pub struct Drawer {
     context: Context,
+    profiler: Profiler,
     settings: Rc<Settings>,
 
     physical_width: u32,
     physical_height: u32,
     pixel_ratio: f64,
 
-    pub grid_width: u32,
-    pub grid_height: u32,
-    pub grid_spacing: u32,
+    logical_width: u32,
+    logical_height: u32,
+    // Set when this drawer only renders a part of a larger canvas
+    virtual_canvas: Option<VirtualCanvas>,
+
+    grid: Grid,
     pub line_count: u32,
 
+    view: Transform2D,
+
     basepoint_buffer: Buffer,
     line_state_buffer: Buffer,
     transform_feedback_buffer: glow::TransformFeedback,
@@ -95,37 +129,58 @@ pub struct Drawer {
 
     place_lines_buffer: VertexArrayObject,
     draw_lines_buffer: VertexArrayObject,
+    draw_streamlines_buffer: VertexArrayObject,
     draw_endpoints_buffer: VertexArrayObject,
-    draw_texture_buffer: VertexArrayObject,
+    line_state_texture_buffer: VertexArrayObject,
+
+    // A copy of the endpoints laid out like the grid, so that each line can
+    // look up its neighbors. Only allocated when the lines are coupled.
+    line_state_texture: Option<Framebuffer>,
+
+    streamline_vertices: Buffer,
+    streamline_segments: u32,
+
+    endpoint_shape: EndpointShape,
+    endpoint_vertices: Buffer,
+    endpoint_vertex_count: u32,
+    // Used with `EndpointShape::Sprite`
+    // Shared with the layers
+    endpoint_sprite: Option<Rc<Framebuffer>>,
+
+    // A grayscale mask over the basepoints, which fades out or shortens the
+    // lines.
+    mask: Option<Rc<Framebuffer>>,
+    mask_transform: Transform2D,
 
     view_buffer: Buffer,
     line_uniforms: Buffer,
 
     place_lines_pass: render::Program,
     draw_lines_pass: render::Program,
+    draw_streamlines_pass: render::Program,
     draw_endpoints_pass: render::Program,
-    draw_texture_pass: render::Program,
-    antialiasing_pass: render::MsaaPass,
+    line_state_texture_pass: render::Program,
 }
 
 impl Drawer {
     pub fn new(
         context: &Context,
+        profiler: &Profiler,
         logical_width: u32,
         logical_height: u32,
         pixel_ratio: f64,
         settings: &Rc<Settings>,
     ) -> Result<Self, render::Problem> {
-        let ((physical_width, physical_height), (grid_width, grid_height), grid_spacing) =
-            compute_grid_size(
-                logical_width,
-                logical_height,
-                pixel_ratio,
-                settings.grid_spacing,
-            );
+        let physical_width = (f64::from(logical_width) * pixel_ratio) as u32;
+        let physical_height = (f64::from(logical_height) * pixel_ratio) as u32;
+        let grid = Grid::new(
+            VirtualCanvas::single(logical_width, logical_height),
+            settings.grid_spacing,
+            grid_margin(settings),
+        );
 
-        let line_count = (grid_width / grid_spacing) * (grid_height / grid_spacing);
-        let line_state = new_line_state(grid_width, grid_height, grid_spacing);
+        let line_count = grid.line_count();
+        let line_state = new_line_state(&grid);
         let line_state_buffer = Buffer::from_f32(
             &context,
             &bytemuck::cast_slice(&line_state),
@@ -146,28 +201,23 @@ impl Drawer {
         )?;
         let basepoint_buffer = Buffer::from_f32(
             &context,
-            &new_basepoints(grid_width, grid_height, grid_spacing, pixel_ratio),
+            &new_basepoints(&grid, pixel_ratio),
             glow::ARRAY_BUFFER,
             glow::STATIC_DRAW,
         )?;
-        let endpoint_vertices = Buffer::from_f32(
+        // The vertex shader divides the line into this many segments.
+        let streamline_segments = settings.streamline_segments.max(1);
+        let streamline_vertices = Buffer::from_f32(
             &context,
-            &new_endpoint(16),
+            &new_streamline(streamline_segments),
             glow::ARRAY_BUFFER,
             glow::STATIC_DRAW,
         )?;
-        let plane_vertices = Buffer::from_f32(
-            &context,
-            &data::PLANE_VERTICES,
-            glow::ARRAY_BUFFER,
-            glow::STATIC_DRAW,
-        )?;
-        let plane_indices = Buffer::from_u16(
-            &context,
-            &data::PLANE_INDICES,
-            glow::ELEMENT_ARRAY_BUFFER,
-            glow::STATIC_DRAW,
-        )?;
+        let endpoint_shape = settings.endpoint_shape;
+        let endpoint = new_endpoint(endpoint_shape);
+        let endpoint_vertex_count = (endpoint.len() / 2) as u32;
+        let endpoint_vertices =
+            Buffer::from_f32(&context, &endpoint, glow::ARRAY_BUFFER, glow::STATIC_DRAW)?;
 
         // Programs
 
@@ -188,14 +238,17 @@ impl Drawer {
         )?;
         let draw_lines_program =
             render::Program::new(&context, (LINE_VERT_SHADER, LINE_FRAG_SHADER))?;
+        let draw_streamlines_program =
+            render::Program::new(&context, (STREAMLINE_VERT_SHADER, LINE_FRAG_SHADER))?;
         let draw_endpoints_program =
             render::Program::new(&context, (ENDPOINT_VERT_SHADER, ENDPOINT_FRAG_SHADER))?;
-        let draw_texture_program =
-            render::Program::new(&context, (TEXTURE_VERT_SHADER, TEXTURE_FRAG_SHADER))?;
+        let line_state_texture_program =
+            render::Program::new(&context, (LINE_STATE_VERT_SHADER, LINE_STATE_FRAG_SHADER))?;
 
         // Vertex buffers
 
         let place_lines_buffer = VertexArrayObject::empty(context)?;
+        let line_state_texture_buffer = VertexArrayObject::empty(context)?;
         let draw_lines_buffer = VertexArrayObject::new(
             context,
             &draw_lines_program,
@@ -210,6 +263,7 @@ impl Drawer {
             )],
             None,
         )?;
+        let draw_streamlines_buffer = VertexArrayObject::empty(context)?;
         let draw_endpoints_buffer = VertexArrayObject::new(
             context,
             &draw_endpoints_program,
@@ -229,10 +283,8 @@ impl Drawer {
 
         let projection_matrix = new_projection_matrix(physical_width, physical_height);
 
-        let view_matrix = glm::scale(
-            &glm::identity(),
-            &glm::vec3(settings.view_scale, settings.view_scale, 1.0),
-        );
+        let view = Transform2D::default();
+        let view_matrix = view.to_matrix(settings.view_scale, pixel_ratio);
 
         let projection = Projection {
             projection: projection_matrix.as_slice().try_into().unwrap(),
@@ -267,6 +319,26 @@ impl Drawer {
                 name: "uLineFadeOutLength",
                 value: UniformValue::Float(settings.line_fade_out_length),
             },
+            &Uniform {
+                name: "uLineOpaqueLength",
+                value: UniformValue::Float(settings.line_opaque_length),
+            },
+            &Uniform {
+                name: "uLineMinWidth",
+                value: UniformValue::Float(settings.line_min_width),
+            },
+            &Uniform {
+                name: "uLineMaxWidth",
+                value: UniformValue::Float(settings.line_max_width),
+            },
+            &Uniform {
+                name: "uLineMaxLength",
+                value: UniformValue::Float(settings.line_max_length),
+            },
+            &Uniform {
+                name: "uColorResponse",
+                value: UniformValue::Float(settings.line_color_response),
+            },
             &Uniform {
                 name: "uSpringStiffness",
                 value: UniformValue::Float(settings.spring_stiffness),
@@ -283,6 +355,14 @@ impl Drawer {
                 name: "uSpringRestLength",
                 value: UniformValue::Float(settings.spring_rest_length),
             },
+            &Uniform {
+                name: "uSpringDamping",
+                value: UniformValue::Float(settings.spring_damping),
+            },
+            &Uniform {
+                name: "uSpringCouplingStiffness",
+                value: UniformValue::Float(settings.spring_coupling_stiffness),
+            },
             &Uniform {
                 name: "uAdvectionDirection",
                 value: UniformValue::Float(settings.advection_direction),
@@ -301,50 +381,100 @@ impl Drawer {
             },
         ]);
 
+        for program in [&place_lines_program, &draw_streamlines_program] {
+            program.set_uniforms(&[
+                &Uniform {
+                    name: "uVelocityScale",
+                    value: UniformValue::Float(settings.velocity_scale),
+                },
+                &Uniform {
+                    name: "uVelocityOffset",
+                    value: UniformValue::Vec2(&settings.velocity_offset),
+                },
+            ]);
+        }
+
         draw_lines_program.set_uniform_block("Projection", 0);
         draw_lines_program.set_uniform_block("LineUniforms", 1);
+        draw_streamlines_program.set_uniform_block("Projection", 0);
+        draw_streamlines_program.set_uniform_block("LineUniforms", 1);
+        draw_streamlines_program.set_uniforms(&[
+            &Uniform {
+                name: "velocityTexture",
+                value: UniformValue::Texture2D(0),
+            },
+            &Uniform {
+                name: "uFluidProjection",
+                value: UniformValue::Mat4(&projection_matrix.as_slice()),
+            },
+            &Uniform {
+                name: "uSegments",
+                value: UniformValue::SignedInt(streamline_segments as i32),
+            },
+            &Uniform {
+                name: "uAdvectionDirection",
+                value: UniformValue::Float(settings.advection_direction),
+            },
+        ]);
         draw_endpoints_program.set_uniform_block("Projection", 0);
         draw_endpoints_program.set_uniform_block("LineUniforms", 1);
-
-        // Vertex buffers
-
-        let draw_texture_buffer = VertexArrayObject::new(
-            &context,
-            &draw_texture_program,
-            &[(
-                &plane_vertices,
-                VertexBufferLayout {
-                    name: "position",
-                    size: 3,
-                    type_: glow::FLOAT,
-                    ..Default::default()
+        for program in [&place_lines_program, &draw_endpoints_program] {
+            program.set_uniforms(&[
+                &Uniform {
+                    name: "maskTexture",
+                    value: UniformValue::Texture2D(1),
                 },
-            )],
-            Some(&plane_indices),
-        )?;
-        draw_texture_program.set_uniform_block("Projection", 0);
+                &Uniform {
+                    name: "uHasMask",
+                    value: UniformValue::SignedInt(0),
+                },
+                &Uniform {
+                    name: "uMaskOpacity",
+                    value: UniformValue::Float(settings.mask_opacity),
+                },
+            ]);
+        }
+        place_lines_program.set_uniforms(&[
+            &Uniform {
+                name: "uMaskLineLength",
+                value: UniformValue::Float(settings.mask_line_length),
+            },
+            &Uniform {
+                name: "lineStateTexture",
+                value: UniformValue::Texture2D(2),
+            },
+        ]);
+        draw_endpoints_program.set_uniforms(&[
+            &Uniform {
+                name: "spriteTexture",
+                value: UniformValue::Texture2D(0),
+            },
+            &Uniform {
+                name: "uUseSprite",
+                value: UniformValue::SignedInt((endpoint_shape == EndpointShape::Sprite) as i32),
+            },
+        ]);
 
-        let antialiasing_samples = 0;
-        let antialiasing_pass = render::MsaaPass::new(
-            context,
-            physical_width,
-            physical_height,
-            antialiasing_samples,
-        )?;
+        let line_state_texture = new_line_state_texture(context, &grid, settings)?;
 
         let drawer = Self {
             context: Rc::clone(context),
+            profiler: profiler.clone(),
             settings: Rc::clone(settings),
 
             physical_width,
             physical_height,
             pixel_ratio,
 
-            grid_width,
-            grid_height,
-            grid_spacing,
+            logical_width,
+            logical_height,
+            virtual_canvas: None,
+
+            grid,
             line_count,
 
+            view,
+
             basepoint_buffer,
             line_state_buffer,
             line_state_feedback_buffer: Buffer::from_f32(
@@ -357,25 +487,55 @@ impl Drawer {
 
             place_lines_buffer,
             draw_lines_buffer,
+            draw_streamlines_buffer,
             draw_endpoints_buffer,
-            draw_texture_buffer,
+            line_state_texture_buffer,
+
+            line_state_texture,
+
+            streamline_vertices,
+            streamline_segments,
+
+            endpoint_shape,
+            endpoint_vertices,
+            endpoint_vertex_count,
+            endpoint_sprite: None,
+
+            mask: None,
+            mask_transform: Transform2D::default(),
 
             view_buffer,
             line_uniforms,
 
             place_lines_pass: place_lines_program,
             draw_lines_pass: draw_lines_program,
+            draw_streamlines_pass: draw_streamlines_program,
             draw_endpoints_pass: draw_endpoints_program,
-            draw_texture_pass: draw_texture_program,
-            antialiasing_pass,
+            line_state_texture_pass: line_state_texture_program,
         };
 
+        drawer.update_projection();
         drawer.update_line_buffers()?;
 
         Ok(drawer)
     }
 
-    pub fn update(&mut self, settings: &Rc<Settings>) -> () {
+    pub fn update(&mut self, settings: &Rc<Settings>) -> Result<(), render::Problem> {
+        let has_new_grid = settings.grid_spacing != self.settings.grid_spacing
+            || grid_margin(settings) != grid_margin(&self.settings);
+        self.settings = Rc::clone(settings);
+
+        if has_new_grid {
+            self.update_grid()?;
+        }
+        self.update_projection();
+        self.update_view();
+
+        let has_coupling = settings.spring_coupling_stiffness > 0.0;
+        if has_coupling != self.line_state_texture.is_some() {
+            self.line_state_texture = new_line_state_texture(&self.context, &self.grid, settings)?;
+        }
+
         unsafe {
             self.context
                 .bind_buffer(glow::UNIFORM_BUFFER, Some(self.line_uniforms.id));
@@ -390,13 +550,85 @@ impl Drawer {
             self.context.bind_buffer(glow::UNIFORM_BUFFER, None);
         }
 
+        for program in [&self.place_lines_pass, &self.draw_streamlines_pass] {
+            program.set_uniforms(&[
+                &Uniform {
+                    name: "uVelocityScale",
+                    value: UniformValue::Float(settings.velocity_scale),
+                },
+                &Uniform {
+                    name: "uVelocityOffset",
+                    value: UniformValue::Vec2(&settings.velocity_offset),
+                },
+            ]);
+        }
+
+        if settings.streamline_segments.max(1) != self.streamline_segments {
+            self.streamline_segments = settings.streamline_segments.max(1);
+            self.streamline_vertices = Buffer::from_f32(
+                &self.context,
+                &new_streamline(self.streamline_segments),
+                glow::ARRAY_BUFFER,
+                glow::STATIC_DRAW,
+            )?;
+            self.update_line_buffers()?;
+        }
+
+        if settings.endpoint_shape != self.endpoint_shape {
+            self.update_endpoint_shape(settings.endpoint_shape)?;
+        }
+
+        self.draw_streamlines_pass.set_uniforms(&[
+            &Uniform {
+                name: "uSegments",
+                value: UniformValue::SignedInt(self.streamline_segments as i32),
+            },
+            &Uniform {
+                name: "uAdvectionDirection",
+                value: UniformValue::Float(settings.advection_direction),
+            },
+        ]);
+
+        self.draw_endpoints_pass.set_uniform(&Uniform {
+            name: "uMaskOpacity",
+            value: UniformValue::Float(settings.mask_opacity),
+        });
+
         // Workaround for iOS
         let color_wheel = settings::color_wheel_from_scheme(&settings.color_scheme);
         self.place_lines_pass.set_uniforms(&[
+            &Uniform {
+                name: "uMaskOpacity",
+                value: UniformValue::Float(settings.mask_opacity),
+            },
+            &Uniform {
+                name: "uMaskLineLength",
+                value: UniformValue::Float(settings.mask_line_length),
+            },
             &Uniform {
                 name: "uLineFadeOutLength",
                 value: UniformValue::Float(settings.line_fade_out_length),
             },
+            &Uniform {
+                name: "uLineOpaqueLength",
+                value: UniformValue::Float(settings.line_opaque_length),
+            },
+            &Uniform {
+                name: "uLineMinWidth",
+                value: UniformValue::Float(settings.line_min_width),
+            },
+            &Uniform {
+                name: "uLineMaxWidth",
+                value: UniformValue::Float(settings.line_max_width),
+            },
+            &Uniform {
+                name: "uLineMaxLength",
+                value: UniformValue::Float(settings.line_max_length),
+            },
+            &Uniform {
+                name: "uColorResponse",
+                value: UniformValue::Float(settings.line_color_response),
+            },
             &Uniform {
                 name: "uSpringStiffness",
                 value: UniformValue::Float(settings.spring_stiffness),
@@ -413,6 +645,14 @@ impl Drawer {
                 name: "uSpringRestLength",
                 value: UniformValue::Float(settings.spring_rest_length),
             },
+            &Uniform {
+                name: "uSpringDamping",
+                value: UniformValue::Float(settings.spring_damping),
+            },
+            &Uniform {
+                name: "uSpringCouplingStiffness",
+                value: UniformValue::Float(settings.spring_coupling_stiffness),
+            },
             &Uniform {
                 name: "uAdvectionDirection",
                 value: UniformValue::Float(settings.advection_direction),
@@ -426,6 +666,174 @@ impl Drawer {
                 value: UniformValue::Vec4Array(&color_wheel),
             },
         ]);
+
+        Ok(())
+    }
+
+    fn update_endpoint_shape(&mut self, shape: EndpointShape) -> Result<(), render::Problem> {
+        let endpoint = new_endpoint(shape);
+        self.endpoint_vertex_count = (endpoint.len() / 2) as u32;
+        self.endpoint_shape = shape;
+
+        if self.endpoint_vertex_count > 0 {
+            self.endpoint_vertices = Buffer::from_f32(
+                &self.context,
+                &endpoint,
+                glow::ARRAY_BUFFER,
+                glow::STATIC_DRAW,
+            )?;
+            self.draw_endpoints_buffer = VertexArrayObject::new(
+                &self.context,
+                &self.draw_endpoints_pass,
+                &[(
+                    &self.endpoint_vertices,
+                    VertexBufferLayout {
+                        name: "vertex",
+                        size: 2,
+                        type_: glow::FLOAT,
+                        ..Default::default()
+                    },
+                )],
+                None,
+            )?;
+            self.update_line_buffers()?;
+        }
+
+        self.draw_endpoints_pass.set_uniform(&Uniform {
+            name: "uUseSprite",
+            value: UniformValue::SignedInt((shape == EndpointShape::Sprite) as i32),
+        });
+
+        Ok(())
+    }
+
+    // Expects tightly packed, straight alpha RGBA pixels, top row first.
+    pub fn set_endpoint_sprite(
+        &mut self,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+    ) -> Result<(), render::Problem> {
+        let sprite = Framebuffer::new(
+            &self.context,
+            width,
+            height,
+            render::TextureOptions {
+                mag_filter: glow::LINEAR,
+                min_filter: glow::LINEAR,
+                format: glow::RGBA8,
+                ..Default::default()
+            },
+        )?
+        .with_u8_data(pixels)?;
+
+        self.endpoint_sprite = Some(Rc::new(sprite));
+
+        Ok(())
+    }
+
+    // Expects one byte per pixel, top row first.
+    pub fn set_mask(
+        &mut self,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+    ) -> Result<(), render::Problem> {
+        let mask = Framebuffer::new(
+            &self.context,
+            width,
+            height,
+            render::TextureOptions {
+                mag_filter: glow::LINEAR,
+                min_filter: glow::LINEAR,
+                format: glow::R8,
+                ..Default::default()
+            },
+        )?
+        .with_u8_data(pixels)?;
+
+        self.mask = Some(Rc::new(mask));
+        self.update_mask_matrix();
+
+        Ok(())
+    }
+
+    pub fn clear_mask(&mut self) -> () {
+        self.mask = None;
+        self.update_mask_matrix();
+    }
+
+    // Move the mask around the canvas. At the default transform, the mask is
+    // centered and scaled to fit the canvas.
+    pub fn set_mask_transform(&mut self, transform: Transform2D) -> () {
+        self.mask_transform = transform;
+        self.update_mask_matrix();
+    }
+
+    // Use the same mask, mask transform and endpoint sprite as `other`. The
+    // layers share these with the main grid.
+    pub fn share_images_with(&mut self, other: &Drawer) -> () {
+        self.mask = other.mask.clone();
+        self.mask_transform = other.mask_transform;
+        self.endpoint_sprite = other.endpoint_sprite.clone();
+        self.update_mask_matrix();
+    }
+
+    fn update_mask_matrix(&self) -> () {
+        let mask = match &self.mask {
+            Some(mask) => mask,
+            None => {
+                for program in [&self.place_lines_pass, &self.draw_endpoints_pass] {
+                    program.set_uniform(&Uniform {
+                        name: "uHasMask",
+                        value: UniformValue::SignedInt(0),
+                    });
+                }
+                return;
+            }
+        };
+
+        let canvas = self.grid.canvas;
+        let canvas_width = (f64::from(canvas.width) * self.pixel_ratio) as f32;
+        let canvas_height = (f64::from(canvas.height) * self.pixel_ratio) as f32;
+        let mask_aspect = mask.width as f32 / mask.height as f32;
+        let (fit_width, fit_height) = if mask_aspect > canvas_width / canvas_height {
+            (canvas_width, canvas_width / mask_aspect)
+        } else {
+            (canvas_height * mask_aspect, canvas_height)
+        };
+
+        // Place the mask on the canvas, then invert that to go from the
+        // basepoints to the mask.
+        let placement = self.mask_transform.to_matrix(1.0, self.pixel_ratio);
+        let placement = glm::scale(
+            &placement,
+            &glm::vec3(fit_width / 2.0, fit_height / 2.0, 1.0),
+        );
+        let mask_matrix = glm::inverse(&placement);
+
+        for program in [&self.place_lines_pass, &self.draw_endpoints_pass] {
+            program.set_uniforms(&[
+                &Uniform {
+                    name: "uHasMask",
+                    value: UniformValue::SignedInt(1),
+                },
+                &Uniform {
+                    name: "uMaskMatrix",
+                    value: UniformValue::Mat4(mask_matrix.as_slice()),
+                },
+            ]);
+        }
+    }
+
+    // Draw only a part of a larger canvas. Pass `None` to draw the whole
+    // canvas again.
+    pub fn set_virtual_canvas(
+        &mut self,
+        virtual_canvas: Option<VirtualCanvas>,
+    ) -> Result<(), render::Problem> {
+        self.virtual_canvas = virtual_canvas;
+        self.resize(self.logical_width, self.logical_height)
     }
 
     pub fn resize(
@@ -433,25 +841,38 @@ impl Drawer {
         logical_width: u32,
         logical_height: u32,
     ) -> Result<(), render::Problem> {
-        let ((physical_width, physical_height), (grid_width, grid_height), grid_spacing) =
-            compute_grid_size(
-                logical_width,
-                logical_height,
-                self.pixel_ratio,
-                self.settings.grid_spacing,
-            );
+        self.logical_width = logical_width;
+        self.logical_height = logical_height;
+        self.physical_width = (f64::from(logical_width) * self.pixel_ratio) as u32;
+        self.physical_height = (f64::from(logical_height) * self.pixel_ratio) as u32;
 
-        self.physical_width = physical_width;
-        self.physical_height = physical_height;
-        self.grid_width = physical_width;
-        self.grid_height = physical_height;
+        self.update_grid()
+    }
+
+    // Lay the lines out again for the current canvas and grid settings. The
+    // lines that are still on the grid keep their state.
+    fn update_grid(&mut self) -> Result<(), render::Problem> {
+        let canvas = self
+            .virtual_canvas
+            .unwrap_or_else(|| VirtualCanvas::single(self.logical_width, self.logical_height));
+        let grid = Grid::new(
+            canvas,
+            self.settings.grid_spacing,
+            grid_margin(&self.settings),
+        );
 
-        self.update_projection(&new_projection_matrix(physical_width, physical_height));
-        self.antialiasing_pass
-            .resize(physical_width, physical_height);
+        // Read back the current lines, so that we can carry them over to the
+        // new grid.
+        let old_line_state = self.read_line_state();
+        let old_grid = self.grid;
+        self.grid = grid;
 
-        self.line_count = (grid_width / grid_spacing) * (grid_height / grid_spacing);
-        let basepoints = new_basepoints(grid_width, grid_height, grid_spacing, self.pixel_ratio);
+        self.update_projection();
+        self.update_mask_matrix();
+        self.line_state_texture = new_line_state_texture(&self.context, &grid, &self.settings)?;
+
+        self.line_count = grid.line_count();
+        let basepoints = new_basepoints(&grid, self.pixel_ratio);
         self.basepoint_buffer = Buffer::from_f32(
             &self.context,
             &basepoints,
@@ -459,7 +880,7 @@ impl Drawer {
             glow::STATIC_DRAW,
         )?;
 
-        let line_state = new_line_state(grid_width, grid_height, grid_spacing);
+        let line_state = remap_line_state(&old_line_state, &old_grid, &grid);
         self.line_state_buffer = Buffer::from_f32(
             &self.context,
             &bytemuck::cast_slice(&line_state),
@@ -480,6 +901,29 @@ impl Drawer {
     }
 
     fn update_line_buffers(&self) -> Result<(), render::Problem> {
+        let grid_size = [self.grid.columns as i32, self.grid.rows as i32];
+        for program in [&self.place_lines_pass, &self.line_state_texture_pass] {
+            program.set_uniform(&Uniform {
+                name: "uGridSize",
+                value: UniformValue::IVec2(&grid_size),
+            });
+        }
+        self.line_state_texture_buffer.update(
+            &self.line_state_texture_pass,
+            &[(
+                &self.line_state_buffer,
+                VertexBufferLayout {
+                    name: "iEndpointVector",
+                    size: 2,
+                    type_: glow::FLOAT,
+                    stride: 10 * 4,
+                    offset: 0 * 4,
+                    divisor: 0,
+                },
+            )],
+            None,
+        )?;
+
         self.place_lines_buffer.update(
             &self.place_lines_pass,
             &[
@@ -620,14 +1064,118 @@ impl Drawer {
         ];
         self.draw_lines_buffer
             .update(&self.draw_lines_pass, &line_state_attribs, None)?;
+        self.draw_streamlines_buffer.update(
+            &self.draw_streamlines_pass,
+            &[(
+                &self.streamline_vertices,
+                VertexBufferLayout {
+                    name: "streamlineVertex",
+                    size: 2,
+                    type_: glow::FLOAT,
+                    ..Default::default()
+                },
+            )],
+            None,
+        )?;
+        self.draw_streamlines_buffer.update(
+            &self.draw_streamlines_pass,
+            &line_state_attribs,
+            None,
+        )?;
         self.draw_endpoints_buffer
             .update(&self.draw_endpoints_pass, &line_state_attribs, None)?;
 
         Ok(())
     }
 
-    fn update_projection(&self, projection: &glm::TMat4<f32>) {
-        let projection: [f32; 16] = projection.as_slice().try_into().unwrap();
+    pub fn virtual_canvas(&self) -> Option<VirtualCanvas> {
+        self.virtual_canvas
+    }
+
+    pub fn canvas(&self) -> VirtualCanvas {
+        self.grid.canvas
+    }
+
+    // Stalls the pipeline while the line state is read back.
+    pub fn line_snapshot(&self) -> Vec<Line> {
+        let line_state = self.read_line_state();
+        let line_length = self.settings.line_length;
+        let line_width = self.settings.line_width;
+
+        (0..self.grid.rows)
+            .flat_map(|row| (0..self.grid.columns).map(move |column| (column, row)))
+            .zip(line_state.iter())
+            .map(|((column, row), state)| {
+                let (x, y) = self.grid.basepoint(column, row);
+
+                Line {
+                    basepoint: [x, y],
+                    endpoint: [
+                        x + state.endpoint[0] * line_length,
+                        y + state.endpoint[1] * line_length,
+                    ],
+                    color: [state.color[0], state.color[1], state.color[2]],
+                    width: state.width * line_width,
+                    opacity: state.opacity,
+                }
+            })
+            .collect()
+    }
+
+    fn read_line_state(&self) -> Vec<LineState> {
+        let mut line_state = vec![LineState::zeroed(); self.line_count as usize];
+
+        unsafe {
+            self.context
+                .bind_buffer(glow::ARRAY_BUFFER, Some(self.line_state_buffer.id));
+            self.context.get_buffer_sub_data(
+                glow::ARRAY_BUFFER,
+                0,
+                bytemuck::cast_slice_mut(&mut line_state),
+            );
+            self.context.bind_buffer(glow::ARRAY_BUFFER, None);
+        }
+
+        line_state
+    }
+
+    pub fn set_view(&mut self, view: Transform2D) -> () {
+        self.view = view;
+        self.update_view();
+    }
+
+    fn update_view(&self) -> () {
+        let view = self
+            .view
+            .to_matrix(self.settings.view_scale, self.pixel_ratio);
+        let view: [f32; 16] = view.as_slice().try_into().unwrap();
+
+        unsafe {
+            self.context
+                .bind_buffer(glow::UNIFORM_BUFFER, Some(self.view_buffer.id));
+            self.context.buffer_sub_data_u8_slice(
+                glow::UNIFORM_BUFFER,
+                std::mem::size_of::<[f32; 16]>() as i32,
+                &bytemuck::cast_slice(&view),
+            );
+            self.context.bind_buffer(glow::UNIFORM_BUFFER, None);
+        }
+    }
+
+    fn update_projection(&self) -> () {
+        let canvas = self.grid.canvas;
+        let fluid_projection: [f32; 16] = new_projection_matrix(
+            (f64::from(canvas.width) * self.pixel_ratio) as u32,
+            (f64::from(canvas.height) * self.pixel_ratio) as u32,
+        )
+        .as_slice()
+        .try_into()
+        .unwrap();
+        let projection: [f32; 16] =
+            new_camera_projection_matrix(&canvas, self.pixel_ratio, self.settings.camera)
+                .as_slice()
+                .try_into()
+                .unwrap();
 
         unsafe {
             self.context
@@ -643,11 +1191,21 @@ impl Drawer {
         // Workaround for iOS
         self.place_lines_pass.set_uniform(&Uniform {
             name: "uProjection",
-            value: UniformValue::Mat4(&projection),
+            value: UniformValue::Mat4(&fluid_projection),
+        });
+        self.draw_streamlines_pass.set_uniform(&Uniform {
+            name: "uFluidProjection",
+            value: UniformValue::Mat4(&fluid_projection),
         });
     }
 
     pub fn place_lines(&self, timestep: f32, texture: &Framebuffer) -> () {
+        let _pass = self.profiler.pass("drawer/place_lines");
+
+        if let Some(line_state_texture) = &self.line_state_texture {
+            self.copy_line_state_to_texture(line_state_texture);
+        }
+
         unsafe {
             self.context.viewport(
                 0,
@@ -671,6 +1229,18 @@ impl Drawer {
             self.context
                 .bind_texture(glow::TEXTURE_2D, Some(texture.texture));
 
+            if let Some(mask) = &self.mask {
+                self.context.active_texture(glow::TEXTURE1);
+                self.context
+                    .bind_texture(glow::TEXTURE_2D, Some(mask.texture));
+            }
+
+            if let Some(line_state_texture) = &self.line_state_texture {
+                self.context.active_texture(glow::TEXTURE2);
+                self.context
+                    .bind_texture(glow::TEXTURE_2D, Some(line_state_texture.texture));
+            }
+
             self.context.bind_transform_feedback(
                 glow::TRANSFORM_FEEDBACK,
                 Some(self.transform_feedback_buffer),
@@ -713,7 +1283,23 @@ impl Drawer {
         }
     }
 
-    pub fn draw_lines(&self) -> () {
+    fn copy_line_state_to_texture(&self, line_state_texture: &Framebuffer) -> () {
+        let _pass = self.profiler.pass("drawer/line_state_texture");
+
+        line_state_texture.draw_to(&self.context, || unsafe {
+            self.context.disable(glow::BLEND);
+
+            self.line_state_texture_pass.use_program();
+            self.context
+                .bind_vertex_array(Some(self.line_state_texture_buffer.id));
+            self.context
+                .draw_arrays(glow::POINTS, 0, self.line_count as i32);
+        });
+    }
+
+    pub fn draw_lines(&self, blend_mode: BlendMode) -> () {
+        let _pass = self.profiler.pass("drawer/draw_lines");
+
         unsafe {
             self.context.viewport(
                 0,
@@ -722,8 +1308,7 @@ impl Drawer {
                 self.physical_height as i32,
             );
 
-            self.context.enable(glow::BLEND);
-            self.context.blend_func(glow::SRC_ALPHA, glow::ONE);
+            enable_blend_mode(&self.context, &blend_mode);
 
             self.draw_lines_pass.use_program();
             self.context
@@ -737,11 +1322,14 @@ impl Drawer {
             self.context
                 .draw_arrays_instanced(glow::TRIANGLES, 0, 6, self.line_count as i32);
 
-            self.context.disable(glow::BLEND);
+            disable_blend_mode(&self.context);
         }
     }
 
-    pub fn draw_endpoints(&self) -> () {
+    // Curved lines that follow the velocity field from the basepoint
+    pub fn draw_streamlines(&self, velocity_texture: &Framebuffer, blend_mode: BlendMode) -> () {
+        let _pass = self.profiler.pass("drawer/draw_streamlines");
+
         unsafe {
             self.context.viewport(
                 0,
@@ -750,27 +1338,43 @@ impl Drawer {
                 self.physical_height as i32,
             );
 
-            self.context.enable(glow::BLEND);
-            self.context.blend_func(glow::SRC_ALPHA, glow::ONE);
+            enable_blend_mode(&self.context, &blend_mode);
 
-            self.draw_endpoints_pass.use_program();
+            self.draw_streamlines_pass.use_program();
             self.context
-                .bind_vertex_array(Some(self.draw_endpoints_buffer.id));
+                .bind_vertex_array(Some(self.draw_streamlines_buffer.id));
 
             self.context
                 .bind_buffer_base(glow::UNIFORM_BUFFER, 0, Some(self.view_buffer.id));
             self.context
                 .bind_buffer_base(glow::UNIFORM_BUFFER, 1, Some(self.line_uniforms.id));
 
+            self.context.active_texture(glow::TEXTURE0);
             self.context
-                .draw_arrays_instanced(glow::TRIANGLE_FAN, 0, 18, self.line_count as i32);
+                .bind_texture(glow::TEXTURE_2D, Some(velocity_texture.texture));
 
-            self.context.disable(glow::BLEND);
+            self.context.draw_arrays_instanced(
+                glow::TRIANGLE_STRIP,
+                0,
+                2 * (self.streamline_segments as i32 + 1),
+                self.line_count as i32,
+            );
+
+            disable_blend_mode(&self.context);
         }
     }
 
-    #[allow(dead_code)]
-    pub fn draw_texture(&self, texture: &Framebuffer) -> () {
+    pub fn draw_endpoints(&self, blend_mode: BlendMode) -> () {
+        let _pass = self.profiler.pass("drawer/draw_endpoints");
+
+        let sprite = match (self.endpoint_shape, &self.endpoint_sprite) {
+            (EndpointShape::Hidden, _) => return,
+            // There’s nothing to draw until we get a sprite.
+            (EndpointShape::Sprite, None) => return,
+            (EndpointShape::Sprite, Some(sprite)) => Some(sprite),
+            _ => None,
+        };
+
         unsafe {
             self.context.viewport(
                 0,
@@ -779,55 +1383,199 @@ impl Drawer {
                 self.physical_height as i32,
             );
 
-            self.draw_texture_pass.use_program();
+            enable_blend_mode(&self.context, &blend_mode);
 
+            self.draw_endpoints_pass.use_program();
             self.context
-                .bind_buffer_base(glow::UNIFORM_BUFFER, 0, Some(self.view_buffer.id));
+                .bind_vertex_array(Some(self.draw_endpoints_buffer.id));
 
             self.context
-                .bind_vertex_array(Some(self.draw_texture_buffer.id));
-
-            self.context.active_texture(glow::TEXTURE0);
+                .bind_buffer_base(glow::UNIFORM_BUFFER, 0, Some(self.view_buffer.id));
             self.context
-                .bind_texture(glow::TEXTURE_2D, Some(texture.texture));
+                .bind_buffer_base(glow::UNIFORM_BUFFER, 1, Some(self.line_uniforms.id));
 
-            self.context
-                .draw_elements(glow::TRIANGLES, 6, glow::UNSIGNED_SHORT, 0);
+            if let Some(sprite) = sprite {
+                self.context.active_texture(glow::TEXTURE0);
+                self.context
+                    .bind_texture(glow::TEXTURE_2D, Some(sprite.texture));
+            }
+
+            if let Some(mask) = &self.mask {
+                self.context.active_texture(glow::TEXTURE1);
+                self.context
+                    .bind_texture(glow::TEXTURE_2D, Some(mask.texture));
+            }
+
+            self.context.draw_arrays_instanced(
+                glow::TRIANGLE_FAN,
+                0,
+                self.endpoint_vertex_count as i32,
+                self.line_count as i32,
+            );
+
+            disable_blend_mode(&self.context);
+        }
+    }
+
+    // Rebuild the programs whose shaders are in `changed`.
+    #[cfg(feature = "hot-reload")]
+    pub fn reload_shaders(&mut self, changed: &[String], errors: &mut Vec<ShaderError>) -> () {
+        let programs = [
+            (
+                &mut self.place_lines_pass,
+                ("place_lines.vert", "place_lines.frag"),
+            ),
+            (&mut self.draw_lines_pass, ("line.vert", "line.frag")),
+            (
+                &mut self.draw_streamlines_pass,
+                ("streamline.vert", "line.frag"),
+            ),
+            (
+                &mut self.draw_endpoints_pass,
+                ("endpoint.vert", "endpoint.frag"),
+            ),
+            (
+                &mut self.line_state_texture_pass,
+                ("line_state.vert", "line_state.frag"),
+            ),
+        ];
+
+        for (program, shaders) in programs {
+            reload_program(program, shaders, changed, errors);
         }
     }
+}
+
+// The basepoints of the lines are laid out on a regular grid over the whole
+// (virtual) canvas. Only the part of the grid inside the viewport is used.
+#[derive(Clone, Copy)]
+pub(crate) struct Grid {
+    pub(crate) canvas: VirtualCanvas,
+    spacing: u32,
+    // The first column and row inside the viewport. Rows are counted from the
+    // bottom of the canvas.
+    first_column: u32,
+    first_row: u32,
+    pub(crate) columns: u32,
+    pub(crate) rows: u32,
+}
+
+// A line can reach at most `line_length` past its basepoint.
+pub(crate) fn grid_margin(settings: &Settings) -> u32 {
+    settings.line_length.ceil() as u32
+}
+
+impl Grid {
+    // Lines with basepoints just outside the viewport can still reach into it,
+    // so we include any basepoints within `margin` of the viewport.
+    pub(crate) fn new(canvas: VirtualCanvas, wanted_grid_spacing: u32, margin: u32) -> Self {
+        let mut spacing = wanted_grid_spacing;
+        if u32::min(canvas.width, canvas.height) < 500 {
+            spacing /= 2;
+        }
+
+        let viewport = canvas.viewport;
+        let bottom = canvas.height.saturating_sub(viewport.y + viewport.height);
+
+        // Each column or row belongs to the viewport its basepoint falls in.
+        let first_line = |start: u32| (start + spacing - 1) / spacing;
+        let (first_column, last_column) = (
+            first_line(viewport.x.saturating_sub(margin)),
+            first_line(viewport.x + viewport.width + margin).min(canvas.width / spacing),
+        );
+        let (first_row, last_row) = (
+            first_line(bottom.saturating_sub(margin)),
+            first_line(bottom + viewport.height + margin).min(canvas.height / spacing),
+        );
+
+        Self {
+            canvas,
+            spacing,
+            first_column,
+            first_row,
+            columns: last_column.saturating_sub(first_column),
+            rows: last_row.saturating_sub(first_row),
+        }
+    }
+
+    pub(crate) fn line_count(&self) -> u32 {
+        self.columns * self.rows
+    }
+
+    // The position of a basepoint in logical pixels, relative to the center of
+    // the canvas.
+    fn basepoint(&self, column: u32, row: u32) -> (f32, f32) {
+        (
+            ((self.first_column + column) * self.spacing) as f32 - (self.canvas.width as f32) / 2.0,
+            ((self.first_row + row) * self.spacing) as f32 - (self.canvas.height as f32) / 2.0,
+        )
+    }
+
+    // The column and row of the basepoint nearest to a position, as given by
+    // `basepoint`.
+    fn nearest(&self, (x, y): (f32, f32)) -> (u32, u32) {
+        let nearest_line = |position: f32, size: u32, first: u32, count: u32| -> u32 {
+            let line = ((position + (size as f32) / 2.0) / (self.spacing as f32)).round();
+            let line = (line.max(0.0) as u32).saturating_sub(first);
+            line.min(count.saturating_sub(1))
+        };
 
-    pub fn with_antialiasing<T>(&self, draw_call: T) -> ()
-    where
-        T: Fn() -> (),
-    {
-        self.antialiasing_pass.draw_to(draw_call);
+        (
+            nearest_line(x, self.canvas.width, self.first_column, self.columns),
+            nearest_line(y, self.canvas.height, self.first_row, self.rows),
+        )
     }
 }
 
-fn compute_grid_size(
-    logical_width: u32,
-    logical_height: u32,
-    pixel_ratio: f64,
-    wanted_grid_spacing: u32,
-) -> ((u32, u32), (u32, u32), u32) {
-    let physical_width = (f64::from(logical_width) * pixel_ratio) as u32;
-    let physical_height = (f64::from(logical_height) * pixel_ratio) as u32;
+// Blend the premultiplied line colors with `blend_mode`. Alpha is always
+// blended over, whatever the mode, so that the lines still composite correctly
+// over a transparent background.
+pub(crate) unsafe fn enable_blend_mode(context: &Context, blend_mode: &BlendMode) -> () {
+    context.enable(glow::BLEND);
+
+    let (source, destination, equation) = match blend_mode {
+        BlendMode::Additive => (glow::ONE, glow::ONE, glow::FUNC_ADD),
+        BlendMode::AlphaOver => (glow::ONE, glow::ONE_MINUS_SRC_ALPHA, glow::FUNC_ADD),
+        BlendMode::Screen => (glow::ONE, glow::ONE_MINUS_SRC_COLOR, glow::FUNC_ADD),
+        // Assumes an opaque background
+        BlendMode::Multiply => (glow::DST_COLOR, glow::ONE_MINUS_SRC_ALPHA, glow::FUNC_ADD),
+        // The factors are ignored
+        BlendMode::Max => (glow::ONE, glow::ONE, glow::MAX),
+    };
+
+    context.blend_equation(equation);
+    context.blend_func_separate(source, destination, glow::ONE, glow::ONE_MINUS_SRC_ALPHA);
+}
 
-    let (grid_width, grid_height) = (logical_width, logical_height);
+pub(crate) unsafe fn disable_blend_mode(context: &Context) -> () {
+    context.blend_equation(glow::FUNC_ADD);
+    context.disable(glow::BLEND);
+}
 
-    let mut grid_spacing = wanted_grid_spacing;
-    if u32::min(logical_width, logical_height) < 500 {
-        grid_spacing /= 2;
+fn new_line_state_texture(
+    context: &Context,
+    grid: &Grid,
+    settings: &Rc<Settings>,
+) -> Result<Option<Framebuffer>, render::Problem> {
+    if settings.spring_coupling_stiffness <= 0.0 || grid.line_count() == 0 {
+        return Ok(None);
     }
 
-    (
-        (physical_width, physical_height),
-        (grid_width, grid_height),
-        grid_spacing,
-    )
+    let line_state_texture = Framebuffer::new(
+        context,
+        grid.columns,
+        grid.rows,
+        render::TextureOptions {
+            format: glow::RG32F,
+            ..Default::default()
+        },
+    )?
+    .with_f32_data(&vec![0.0; (2 * grid.line_count()) as usize])?;
+
+    Ok(Some(line_state_texture))
 }
 
-fn new_projection_matrix(width: u32, height: u32) -> glm::TMat4<f32> {
+pub fn new_projection_matrix(width: u32, height: u32) -> glm::TMat4<f32> {
     let half_width = (width as f32) / 2.0;
     let half_height = (height as f32) / 2.0;
 
@@ -841,51 +1589,177 @@ fn new_projection_matrix(width: u32, height: u32) -> glm::TMat4<f32> {
     )
 }
 
-// World space coordinates: zero-centered, width x height
-fn new_basepoints(width: u32, height: u32, grid_spacing: u32, pixel_ratio: f64) -> Vec<f32> {
-    let half_width = (f64::from(width) * pixel_ratio / 2.0) as f32;
-    let half_height = (f64::from(height) * pixel_ratio / 2.0) as f32;
+// Project the part of the canvas inside the viewport onto the screen.
+fn new_viewport_projection_matrix(canvas: &VirtualCanvas, pixel_ratio: f64) -> glm::TMat4<f32> {
+    let viewport = canvas.viewport;
+    let to_physical = |logical: f32| (f64::from(logical) * pixel_ratio) as f32;
+
+    let left = viewport.x as f32 - (canvas.width as f32) / 2.0;
+    let top = (canvas.height as f32) / 2.0 - viewport.y as f32;
+    // Leave room for endpoints lifted off the canvas
+    let depth = to_physical((canvas.width + canvas.height) as f32);
+
+    glm::ortho(
+        to_physical(left),
+        to_physical(left + viewport.width as f32),
+        to_physical(top - viewport.height as f32),
+        to_physical(top),
+        -depth,
+        depth,
+    )
+}
 
-    let rows = height / grid_spacing;
-    let cols = width / grid_spacing;
-    let mut data = Vec::with_capacity((rows * cols * 2) as usize);
+pub(crate) fn new_camera_projection_matrix(
+    canvas: &VirtualCanvas,
+    pixel_ratio: f64,
+    camera: Camera,
+) -> glm::TMat4<f32> {
+    let (field_of_view, tilt, distance) = match camera {
+        Camera::Orthographic => return new_viewport_projection_matrix(canvas, pixel_ratio),
+        Camera::Perspective {
+            field_of_view,
+            tilt,
+            distance,
+        } => (field_of_view, tilt, distance),
+    };
+
+    let viewport = canvas.viewport;
+    let to_physical = |logical: f32| (f64::from(logical) * pixel_ratio) as f32;
+
+    // The distance at which the whole canvas fits the field of view
+    let half_height = to_physical(canvas.height as f32 / 2.0);
+    let fit_distance = half_height / (field_of_view.clamp(1.0, 179.0).to_radians() / 2.0).tan();
+    let camera_distance = distance.max(0.01) * fit_distance;
+
+    // Build an off-center frustum that matches the viewport on the canvas
+    // plane, at the fit distance.
+    let near = 0.01 * camera_distance;
+    let far = 10.0 * (camera_distance + half_height);
+    let to_near = |at_fit_distance: f32| to_physical(at_fit_distance) * near / fit_distance;
+
+    let left = viewport.x as f32 - (canvas.width as f32) / 2.0;
+    let top = (canvas.height as f32) / 2.0 - viewport.y as f32;
+
+    let frustum = new_frustum_matrix(
+        to_near(left),
+        to_near(left + viewport.width as f32),
+        to_near(top - viewport.height as f32),
+        to_near(top),
+        near,
+        far,
+    );
+
+    let camera_matrix = glm::translate(&glm::identity(), &glm::vec3(0.0, 0.0, -camera_distance));
+    let camera_matrix = glm::rotate_x(&camera_matrix, -tilt.to_radians());
+
+    frustum * camera_matrix
+}
+
+// Same as `glFrustum`
+fn new_frustum_matrix(
+    left: f32,
+    right: f32,
+    bottom: f32,
+    top: f32,
+    near: f32,
+    far: f32,
+) -> glm::TMat4<f32> {
+    glm::mat4(
+        2.0 * near / (right - left),
+        0.0,
+        (right + left) / (right - left),
+        0.0,
+        0.0,
+        2.0 * near / (top - bottom),
+        (top + bottom) / (top - bottom),
+        0.0,
+        0.0,
+        0.0,
+        -(far + near) / (far - near),
+        -2.0 * far * near / (far - near),
+        0.0,
+        0.0,
+        -1.0,
+        0.0,
+    )
+}
 
-    for v in 0..rows {
-        for u in 0..cols {
-            let x: f32 = (f64::from(u * grid_spacing) * pixel_ratio) as f32;
-            let y: f32 = (f64::from(v * grid_spacing) * pixel_ratio) as f32;
+// World space coordinates: zero-centered, width x height
+pub(crate) fn new_basepoints(grid: &Grid, pixel_ratio: f64) -> Vec<f32> {
+    let mut data = Vec::with_capacity((grid.line_count() * 2) as usize);
+
+    for row in 0..grid.rows {
+        for column in 0..grid.columns {
+            let (x, y) = grid.basepoint(column, row);
 
-            data.push(x - half_width);
-            data.push(y - half_height);
+            data.push((f64::from(x) * pixel_ratio) as f32);
+            data.push((f64::from(y) * pixel_ratio) as f32);
         }
     }
 
     data
 }
 
-// World space coordinates: zero-centered, width x height
-fn new_line_state(width: u32, height: u32, grid_spacing: u32) -> Vec<LineState> {
-    let rows = height / grid_spacing;
-    let cols = width / grid_spacing;
-    let mut data =
-        Vec::with_capacity(std::mem::size_of::<LineState>() / 4 * (rows * cols) as usize);
-
-    for _ in 0..rows {
-        for _ in 0..cols {
-            data.push(LineState {
-                endpoint: [0.0, 0.0],
-                velocity: [0.0, 0.0],
-                color: [0.0, 0.0, 0.0, 0.0],
-                width: 0.1,
-                opacity: 0.0,
-            });
+// Give each line on the new grid the state of the nearest line on the old one.
+fn remap_line_state(old_line_state: &[LineState], old_grid: &Grid, grid: &Grid) -> Vec<LineState> {
+    if old_grid.line_count() == 0 || old_line_state.len() != old_grid.line_count() as usize {
+        return new_line_state(grid);
+    }
+
+    let mut data = Vec::with_capacity(grid.line_count() as usize);
+
+    for row in 0..grid.rows {
+        for column in 0..grid.columns {
+            let (old_column, old_row) = old_grid.nearest(grid.basepoint(column, row));
+            data.push(old_line_state[(old_row * old_grid.columns + old_column) as usize]);
         }
     }
 
     data
 }
 
-fn new_endpoint(resolution: u32) -> Vec<f32> {
+fn new_line_state(grid: &Grid) -> Vec<LineState> {
+    vec![
+        LineState {
+            endpoint: [0.0, 0.0],
+            velocity: [0.0, 0.0],
+            color: [0.0, 0.0, 0.0, 0.0],
+            width: 0.1,
+            opacity: 0.0,
+        };
+        grid.line_count() as usize
+    ]
+}
+
+// A triangle strip running from the basepoint (x = 0) to the endpoint (x = 1)
+fn new_streamline(segments: u32) -> Vec<f32> {
+    let mut vertices = Vec::with_capacity((4 * (segments + 1)) as usize);
+
+    for segment in 0..=segments {
+        let x = (segment as f32) / (segments as f32);
+        vertices.extend_from_slice(&[x, -0.5, x, 0.5]);
+    }
+
+    vertices
+}
+
+// A triangle fan around the endpoint, within the unit circle or square.
+pub(crate) fn new_endpoint(shape: EndpointShape) -> Vec<f32> {
+    match shape {
+        EndpointShape::Circle { resolution } => new_circle(resolution.max(3)),
+        EndpointShape::Square | EndpointShape::Sprite => {
+            vec![
+                0.0, 0.0, 1.0, -1.0, 1.0, 1.0, -1.0, 1.0, -1.0, -1.0, 1.0, -1.0,
+            ]
+        }
+        EndpointShape::Diamond => {
+            vec![0.0, 0.0, 1.0, 0.0, 0.0, 1.0, -1.0, 0.0, 0.0, -1.0, 1.0, 0.0]
+        }
+        EndpointShape::Hidden => Vec::new(),
+    }
+}
+
+fn new_circle(resolution: u32) -> Vec<f32> {
     let mut segments = Vec::with_capacity((resolution * 2 + 1) as usize);
 
     segments.push(0.0);
@@ -899,3 +1773,140 @@ fn new_endpoint(resolution: u32) -> Vec<f32> {
 
     segments
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Canvases under 500 pixels halve the spacing, so most tests stay above that.
+    fn grid(width: u32, height: u32, spacing: u32) -> Grid {
+        Grid::new(VirtualCanvas::single(width, height), spacing, 0)
+    }
+
+    // Tag each line with its index, so that we can tell where it ends up.
+    fn tagged_line_state(grid: &Grid) -> Vec<LineState> {
+        (0..grid.line_count())
+            .map(|index| LineState {
+                width: index as f32,
+                ..LineState::zeroed()
+            })
+            .collect()
+    }
+
+    fn tags(line_state: &[LineState]) -> Vec<u32> {
+        line_state.iter().map(|state| state.width as u32).collect()
+    }
+
+    #[test]
+    fn finds_the_nearest_basepoint() {
+        let grid = grid(1000, 800, 100);
+
+        for row in 0..grid.rows {
+            for column in 0..grid.columns {
+                let (x, y) = grid.basepoint(column, row);
+                assert_eq!(grid.nearest((x, y)), (column, row));
+                assert_eq!(grid.nearest((x + 40.0, y - 40.0)), (column, row));
+            }
+        }
+    }
+
+    #[test]
+    fn clamps_the_nearest_basepoint_to_the_grid() {
+        let grid = grid(1000, 800, 100);
+
+        assert_eq!(grid.nearest((-5000.0, -5000.0)), (0, 0));
+        assert_eq!(
+            grid.nearest((5000.0, 5000.0)),
+            (grid.columns - 1, grid.rows - 1)
+        );
+    }
+
+    #[test]
+    fn remaps_to_the_same_grid() {
+        let grid = grid(1000, 800, 100);
+        let line_state = tagged_line_state(&grid);
+
+        let remapped = remap_line_state(&line_state, &grid, &grid);
+
+        assert_eq!(tags(&remapped), tags(&line_state));
+    }
+
+    #[test]
+    fn remaps_to_a_larger_grid() {
+        let old_grid = grid(1000, 800, 100);
+        let new_grid = grid(1200, 1000, 100);
+        let line_state = tagged_line_state(&old_grid);
+
+        let remapped = remap_line_state(&line_state, &old_grid, &new_grid);
+
+        assert_eq!(remapped.len(), new_grid.line_count() as usize);
+        // The canvas grows by a column and a row on each side, so the old
+        // basepoints are shifted by one.
+        for row in 0..old_grid.rows {
+            for column in 0..old_grid.columns {
+                let index = ((row + 1) * new_grid.columns + column + 1) as usize;
+                assert_eq!(
+                    remapped[index].width as u32,
+                    row * old_grid.columns + column
+                );
+            }
+        }
+        // The new lines take the state of the nearest old one.
+        assert_eq!(remapped[0].width as u32, 0);
+    }
+
+    #[test]
+    fn remaps_to_a_smaller_grid() {
+        let old_grid = grid(1000, 800, 100);
+        let new_grid = grid(800, 600, 100);
+        let line_state = tagged_line_state(&old_grid);
+
+        let remapped = remap_line_state(&line_state, &old_grid, &new_grid);
+
+        assert_eq!(remapped.len(), new_grid.line_count() as usize);
+        for row in 0..new_grid.rows {
+            for column in 0..new_grid.columns {
+                let index = (row * new_grid.columns + column) as usize;
+                assert_eq!(
+                    remapped[index].width as u32,
+                    (row + 1) * old_grid.columns + column + 1
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn keeps_the_state_of_a_line_whose_basepoint_stays_put() {
+        let old_grid = grid(1000, 800, 100);
+        let new_grid = grid(1000, 800, 50);
+        let mut line_state = new_line_state(&old_grid);
+        line_state[(2 * old_grid.columns + 3) as usize] = LineState {
+            endpoint: [0.5, -0.25],
+            velocity: [1.0, 2.0],
+            color: [0.1, 0.2, 0.3, 1.0],
+            width: 0.8,
+            opacity: 0.6,
+        };
+
+        let remapped = remap_line_state(&line_state, &old_grid, &new_grid);
+
+        // Every other basepoint on the finer grid is one of the old ones.
+        let state = remapped[(4 * new_grid.columns + 6) as usize];
+        assert_eq!(state.endpoint, [0.5, -0.25]);
+        assert_eq!(state.velocity, [1.0, 2.0]);
+        assert_eq!(state.color, [0.1, 0.2, 0.3, 1.0]);
+        assert_eq!(state.width, 0.8);
+        assert_eq!(state.opacity, 0.6);
+    }
+
+    #[test]
+    fn starts_afresh_without_an_old_grid() {
+        let old_grid = grid(0, 0, 100);
+        let new_grid = grid(1000, 800, 100);
+
+        let remapped = remap_line_state(&[], &old_grid, &new_grid);
+
+        assert_eq!(remapped.len(), new_grid.line_count() as usize);
+        assert!(remapped.iter().all(|state| state.endpoint == [0.0, 0.0]));
+    }
+}
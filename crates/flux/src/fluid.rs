@@ -1,7 +1,9 @@
+#[cfg(feature = "hot-reload")]
+use crate::hot_reload::{reload_program, ShaderError};
 use crate::{data, render, settings};
 use render::{
-    Buffer, Context, DoubleFramebuffer, Framebuffer, TextureOptions, Uniform, UniformValue,
-    VertexArrayObject,
+    Buffer, Context, DoubleFramebuffer, Framebuffer, Profiler, TextureOptions, Uniform,
+    UniformValue, VertexArrayObject,
 };
 use settings::Settings;
 
@@ -35,6 +37,7 @@ struct Uniforms {
 
 pub struct Fluid {
     context: Context,
+    profiler: Profiler,
     settings: Rc<Settings>,
 
     texel_size: [f32; 2],
@@ -55,7 +58,11 @@ pub struct Fluid {
 }
 
 impl Fluid {
-    pub fn new(context: &Context, settings: &Rc<Settings>) -> Result<Self, render::Problem> {
+    pub fn new(
+        context: &Context,
+        profiler: &Profiler,
+        settings: &Rc<Settings>,
+    ) -> Result<Self, render::Problem> {
         let grid_size: f32 = 1.0;
         let grid_width = settings.fluid_width;
         let grid_height = settings.fluid_height;
@@ -141,7 +148,7 @@ impl Fluid {
             &context,
             &bytemuck::cast_slice(&[uniforms]),
             glow::ARRAY_BUFFER,
-            glow::STATIC_DRAW,
+            glow::DYNAMIC_DRAW,
         )?;
 
         advection_program.set_uniform_block("FluidUniforms", 0);
@@ -213,6 +220,7 @@ impl Fluid {
 
         Ok(Self {
             context: Rc::clone(context),
+            profiler: profiler.clone(),
             settings: Rc::clone(settings),
 
             texel_size,
@@ -279,6 +287,8 @@ impl Fluid {
     }
 
     pub fn advect(&self) -> () {
+        let _pass = self.profiler.pass("fluid/advect");
+
         self.velocity_textures
             .draw_to(&self.context, |velocity_texture| unsafe {
                 self.advection_pass.use_program();
@@ -296,6 +306,8 @@ impl Fluid {
     }
 
     pub fn diffuse(&self, timestep: f32) -> () {
+        let _pass = self.profiler.pass("fluid/diffuse");
+
         let center_factor = self.grid_size.powf(2.0) / (self.settings.viscosity * timestep);
         let stencil_factor = 1.0 / (4.0 + center_factor);
 
@@ -327,6 +339,8 @@ impl Fluid {
     }
 
     pub fn calculate_divergence(&self) -> () {
+        let _pass = self.profiler.pass("fluid/divergence");
+
         self.divergence_texture.draw_to(&self.context, || unsafe {
             self.divergence_pass.use_program();
 
@@ -342,6 +356,8 @@ impl Fluid {
     }
 
     pub fn solve_pressure(&self) -> () {
+        let _pass = self.profiler.pass("fluid/solve_pressure");
+
         let alpha = -self.grid_size * self.grid_size;
         let r_beta = 0.25;
 
@@ -378,6 +394,8 @@ impl Fluid {
     }
 
     pub fn subtract_gradient(&self) -> () {
+        let _pass = self.profiler.pass("fluid/subtract_gradient");
+
         self.subtract_gradient_pass.use_program();
 
         self.velocity_textures
@@ -396,6 +414,39 @@ impl Fluid {
             });
     }
 
+    // Rebuild the programs whose shaders are in `changed`.
+    #[cfg(feature = "hot-reload")]
+    pub fn reload_shaders(&mut self, changed: &[String], errors: &mut Vec<ShaderError>) -> () {
+        reload_program(
+            &mut self.advection_pass,
+            ("fluid.vert", "advection.frag"),
+            changed,
+            errors,
+        );
+        reload_program(
+            &mut self.divergence_pass,
+            ("fluid.vert", "divergence.frag"),
+            changed,
+            errors,
+        );
+        reload_program(
+            &mut self.subtract_gradient_pass,
+            ("fluid.vert", "subtract_gradient.frag"),
+            changed,
+            errors,
+        );
+
+        // The diffusion pass shares the pressure program
+        if reload_program(
+            &mut self.pressure_pass,
+            ("fluid.vert", "solve_pressure.frag"),
+            changed,
+            errors,
+        ) {
+            self.diffusion_pass = self.pressure_pass.clone();
+        }
+    }
+
     #[allow(dead_code)]
     pub fn get_velocity(&self) -> Ref<Framebuffer> {
         self.velocity_textures.current()
@@ -0,0 +1,596 @@
+#[cfg(feature = "hot-reload")]
+use crate::hot_reload::{reload_program, ShaderError};
+use crate::{data, drawer, render, settings};
+use drawer::{disable_blend_mode, enable_blend_mode, Projection};
+use render::{
+    Buffer, Context, Framebuffer, Profiler, Uniform, UniformValue, VertexArrayObject,
+    VertexBufferLayout,
+};
+use settings::{Antialiasing, BlendMode, Settings, TrailBlendMode};
+
+extern crate nalgebra_glm as glm;
+use glow::HasContext;
+use std::rc::Rc;
+
+static TEXTURE_VERT_SHADER: &'static str =
+    include_str!(concat!(env!("OUT_DIR"), "/shaders/texture.vert"));
+static TEXTURE_FRAG_SHADER: &'static str =
+    include_str!(concat!(env!("OUT_DIR"), "/shaders/texture.frag"));
+static TRAILS_VERT_SHADER: &'static str =
+    include_str!(concat!(env!("OUT_DIR"), "/shaders/trails.vert"));
+static FADE_TRAILS_FRAG_SHADER: &'static str =
+    include_str!(concat!(env!("OUT_DIR"), "/shaders/fade_trails.frag"));
+static DRAW_TRAILS_FRAG_SHADER: &'static str =
+    include_str!(concat!(env!("OUT_DIR"), "/shaders/draw_trails.frag"));
+static FXAA_FRAG_SHADER: &'static str =
+    include_str!(concat!(env!("OUT_DIR"), "/shaders/fxaa.frag"));
+
+// The passes that work on the whole frame rather than on a grid of lines: the
+// line trails and the antialiasing. The main grid and the layers share these.
+pub struct FrameDrawer {
+    context: Context,
+    profiler: Profiler,
+    settings: Rc<Settings>,
+
+    physical_width: u32,
+    physical_height: u32,
+    pixel_ratio: f64,
+
+    draw_texture_buffer: VertexArrayObject,
+    fade_trails_buffer: VertexArrayObject,
+    draw_trails_buffer: VertexArrayObject,
+    draw_fxaa_buffer: VertexArrayObject,
+
+    // An accumulation buffer for the line trails. Only allocated when trails
+    // are enabled.
+    trail_buffer: Option<Framebuffer>,
+
+    // The antialiasing in use, which may differ from the settings if the
+    // context doesn’t support the requested number of samples.
+    antialiasing: Antialiasing,
+    // The frame is rendered here before the FXAA pass. Only allocated when
+    // FXAA is enabled.
+    fxaa_buffer: Option<Framebuffer>,
+
+    // An identity projection for `draw_texture`
+    view_buffer: Buffer,
+
+    draw_texture_pass: render::Program,
+    fade_trails_pass: render::Program,
+    draw_trails_pass: render::Program,
+    fxaa_pass: render::Program,
+    antialiasing_pass: render::MsaaPass,
+}
+
+impl FrameDrawer {
+    pub fn new(
+        context: &Context,
+        profiler: &Profiler,
+        logical_width: u32,
+        logical_height: u32,
+        pixel_ratio: f64,
+        settings: &Rc<Settings>,
+    ) -> Result<Self, render::Problem> {
+        let physical_width = (f64::from(logical_width) * pixel_ratio) as u32;
+        let physical_height = (f64::from(logical_height) * pixel_ratio) as u32;
+
+        let plane_vertices = Buffer::from_f32(
+            &context,
+            &data::PLANE_VERTICES,
+            glow::ARRAY_BUFFER,
+            glow::STATIC_DRAW,
+        )?;
+        let plane_indices = Buffer::from_u16(
+            &context,
+            &data::PLANE_INDICES,
+            glow::ELEMENT_ARRAY_BUFFER,
+            glow::STATIC_DRAW,
+        )?;
+
+        // Programs
+
+        let draw_texture_program =
+            render::Program::new(&context, (TEXTURE_VERT_SHADER, TEXTURE_FRAG_SHADER))?;
+        let fade_trails_program =
+            render::Program::new(&context, (TRAILS_VERT_SHADER, FADE_TRAILS_FRAG_SHADER))?;
+        let draw_trails_program =
+            render::Program::new(&context, (TRAILS_VERT_SHADER, DRAW_TRAILS_FRAG_SHADER))?;
+        let fxaa_program = render::Program::new(&context, (TRAILS_VERT_SHADER, FXAA_FRAG_SHADER))?;
+
+        // Vertex buffers
+
+        let draw_texture_buffer = VertexArrayObject::new(
+            &context,
+            &draw_texture_program,
+            &[(
+                &plane_vertices,
+                VertexBufferLayout {
+                    name: "position",
+                    size: 3,
+                    type_: glow::FLOAT,
+                    ..Default::default()
+                },
+            )],
+            Some(&plane_indices),
+        )?;
+        let fade_trails_buffer = VertexArrayObject::new(
+            &context,
+            &fade_trails_program,
+            &[(
+                &plane_vertices,
+                VertexBufferLayout {
+                    name: "position",
+                    size: 3,
+                    type_: glow::FLOAT,
+                    ..Default::default()
+                },
+            )],
+            Some(&plane_indices),
+        )?;
+        let draw_trails_buffer = VertexArrayObject::new(
+            &context,
+            &draw_trails_program,
+            &[(
+                &plane_vertices,
+                VertexBufferLayout {
+                    name: "position",
+                    size: 3,
+                    type_: glow::FLOAT,
+                    ..Default::default()
+                },
+            )],
+            Some(&plane_indices),
+        )?;
+        let draw_fxaa_buffer = VertexArrayObject::new(
+            &context,
+            &fxaa_program,
+            &[(
+                &plane_vertices,
+                VertexBufferLayout {
+                    name: "position",
+                    size: 3,
+                    type_: glow::FLOAT,
+                    ..Default::default()
+                },
+            )],
+            Some(&plane_indices),
+        )?;
+
+        // Uniforms
+
+        let identity: [f32; 16] = glm::identity::<f32, 4>().as_slice().try_into().unwrap();
+        let projection = Projection {
+            projection: identity,
+            view: identity,
+        };
+        let view_buffer = Buffer::from_f32(
+            &context,
+            &bytemuck::cast_slice(&[projection]),
+            glow::ARRAY_BUFFER,
+            glow::STATIC_DRAW,
+        )?;
+
+        draw_texture_program.set_uniform_block("Projection", 0);
+        draw_trails_program.set_uniform(&Uniform {
+            name: "trailTexture",
+            value: UniformValue::Texture2D(0),
+        });
+        fxaa_program.set_uniforms(&[
+            &Uniform {
+                name: "inputTexture",
+                value: UniformValue::Texture2D(0),
+            },
+            &Uniform {
+                name: "uTexelSize",
+                value: UniformValue::Vec2(&[
+                    1.0 / physical_width as f32,
+                    1.0 / physical_height as f32,
+                ]),
+            },
+        ]);
+
+        let trail_buffer = new_trail_buffer(context, physical_width, physical_height, settings)?;
+
+        let antialiasing = clamp_antialiasing(
+            settings.antialiasing,
+            render::MsaaPass::max_samples(context),
+        );
+        let antialiasing_pass = render::MsaaPass::new(
+            context,
+            physical_width,
+            physical_height,
+            antialiasing.samples(),
+            glow::RGBA8,
+        )?;
+        let fxaa_buffer = new_fxaa_buffer(context, physical_width, physical_height, antialiasing)?;
+
+        Ok(Self {
+            context: Rc::clone(context),
+            profiler: profiler.clone(),
+            settings: Rc::clone(settings),
+
+            physical_width,
+            physical_height,
+            pixel_ratio,
+
+            draw_texture_buffer,
+            fade_trails_buffer,
+            draw_trails_buffer,
+            draw_fxaa_buffer,
+
+            trail_buffer,
+
+            antialiasing,
+            fxaa_buffer,
+
+            view_buffer,
+
+            draw_texture_pass: draw_texture_program,
+            fade_trails_pass: fade_trails_program,
+            draw_trails_pass: draw_trails_program,
+            fxaa_pass: fxaa_program,
+            antialiasing_pass,
+        })
+    }
+
+    // Returns the antialiasing actually applied, which may be a fallback if
+    // the context doesn’t support the requested number of samples.
+    pub fn update(&mut self, settings: &Rc<Settings>) -> Result<Antialiasing, render::Problem> {
+        self.settings = Rc::clone(settings);
+
+        self.update_antialiasing()?;
+
+        if self.has_trails() != self.trail_buffer.is_some() {
+            self.trail_buffer = new_trail_buffer(
+                &self.context,
+                self.physical_width,
+                self.physical_height,
+                settings,
+            )?;
+        }
+
+        Ok(self.antialiasing)
+    }
+
+    fn update_antialiasing(&mut self) -> Result<(), render::Problem> {
+        let antialiasing = clamp_antialiasing(
+            self.settings.antialiasing,
+            render::MsaaPass::max_samples(&self.context),
+        );
+
+        if antialiasing.samples() != self.antialiasing_pass.samples() {
+            self.antialiasing_pass.set_samples(antialiasing.samples())?;
+        }
+
+        if (antialiasing == Antialiasing::Fxaa) != self.fxaa_buffer.is_some() {
+            self.fxaa_buffer = new_fxaa_buffer(
+                &self.context,
+                self.physical_width,
+                self.physical_height,
+                antialiasing,
+            )?;
+        }
+
+        self.antialiasing = antialiasing;
+
+        Ok(())
+    }
+
+    pub fn resize(
+        &mut self,
+        logical_width: u32,
+        logical_height: u32,
+    ) -> Result<(), render::Problem> {
+        let physical_width = (f64::from(logical_width) * self.pixel_ratio) as u32;
+        let physical_height = (f64::from(logical_height) * self.pixel_ratio) as u32;
+        self.physical_width = physical_width;
+        self.physical_height = physical_height;
+
+        self.antialiasing_pass
+            .resize(physical_width, physical_height);
+        self.fxaa_buffer = new_fxaa_buffer(
+            &self.context,
+            physical_width,
+            physical_height,
+            self.antialiasing,
+        )?;
+        self.fxaa_pass.set_uniform(&Uniform {
+            name: "uTexelSize",
+            value: UniformValue::Vec2(&[1.0 / physical_width as f32, 1.0 / physical_height as f32]),
+        });
+        self.trail_buffer = new_trail_buffer(
+            &self.context,
+            physical_width,
+            physical_height,
+            &self.settings,
+        )?;
+
+        Ok(())
+    }
+
+    #[allow(dead_code)]
+    pub fn draw_texture(&self, texture: &Framebuffer) -> () {
+        let _pass = self.profiler.pass("frame/draw_texture");
+
+        unsafe {
+            self.context.viewport(
+                0,
+                0,
+                self.physical_width as i32,
+                self.physical_height as i32,
+            );
+
+            self.draw_texture_pass.use_program();
+
+            self.context
+                .bind_buffer_base(glow::UNIFORM_BUFFER, 0, Some(self.view_buffer.id));
+
+            self.context
+                .bind_vertex_array(Some(self.draw_texture_buffer.id));
+
+            self.context.active_texture(glow::TEXTURE0);
+            self.context
+                .bind_texture(glow::TEXTURE_2D, Some(texture.texture));
+
+            self.context
+                .draw_elements(glow::TRIANGLES, 6, glow::UNSIGNED_SHORT, 0);
+        }
+    }
+
+    // Rebuild the programs whose shaders are in `changed`.
+    #[cfg(feature = "hot-reload")]
+    pub fn reload_shaders(&mut self, changed: &[String], errors: &mut Vec<ShaderError>) -> () {
+        let programs = [
+            (
+                &mut self.draw_texture_pass,
+                ("texture.vert", "texture.frag"),
+            ),
+            (
+                &mut self.fade_trails_pass,
+                ("trails.vert", "fade_trails.frag"),
+            ),
+            (
+                &mut self.draw_trails_pass,
+                ("trails.vert", "draw_trails.frag"),
+            ),
+            (&mut self.fxaa_pass, ("trails.vert", "fxaa.frag")),
+        ];
+
+        for (program, shaders) in programs {
+            reload_program(program, shaders, changed, errors);
+        }
+    }
+
+    pub fn antialiasing(&self) -> Antialiasing {
+        self.antialiasing
+    }
+
+    pub fn has_trails(&self) -> bool {
+        self.settings.trail_decay > 0.0
+    }
+
+    // Fade out the existing trails and draw the current frame on top.
+    pub fn accumulate_trails<T>(&self, timestep: f32, draw_call: T) -> ()
+    where
+        T: Fn() -> (),
+    {
+        let _pass = self.profiler.pass("frame/accumulate_trails");
+
+        let trail_buffer = match &self.trail_buffer {
+            Some(trail_buffer) => trail_buffer,
+            None => return,
+        };
+
+        trail_buffer.draw_to(&self.context, || unsafe {
+            self.context.enable(glow::BLEND);
+            self.context.blend_func(glow::ZERO, glow::SRC_COLOR);
+
+            self.fade_trails_pass.set_uniform(&Uniform {
+                name: "uFadeFactor",
+                value: UniformValue::Float((-self.settings.trail_decay * timestep).exp()),
+            });
+            self.context
+                .bind_vertex_array(Some(self.fade_trails_buffer.id));
+            self.context
+                .draw_elements(glow::TRIANGLES, 6, glow::UNSIGNED_SHORT, 0);
+
+            // The lines should be drawn with `line_blend_mode`.
+            draw_call();
+        });
+    }
+
+    // Lines drawn into the trail buffer blend with the trail blend mode. The
+    // trails are then blended onto the frame with the regular blend mode. The
+    // layers draw into the same frame, so they use this blend mode too.
+    pub fn line_blend_mode(&self) -> BlendMode {
+        if !self.has_trails() {
+            return self.settings.blend_mode;
+        }
+
+        match self.settings.trail_blend_mode {
+            TrailBlendMode::Additive => BlendMode::Additive,
+            TrailBlendMode::Lighten => BlendMode::Max,
+        }
+    }
+
+    pub fn draw_trails(&self) -> () {
+        let _pass = self.profiler.pass("frame/draw_trails");
+
+        let trail_buffer = match &self.trail_buffer {
+            Some(trail_buffer) => trail_buffer,
+            None => return,
+        };
+
+        unsafe {
+            self.context.viewport(
+                0,
+                0,
+                self.physical_width as i32,
+                self.physical_height as i32,
+            );
+
+            enable_blend_mode(&self.context, &self.settings.blend_mode);
+
+            self.draw_trails_pass.use_program();
+            self.context
+                .bind_vertex_array(Some(self.draw_trails_buffer.id));
+
+            self.context.active_texture(glow::TEXTURE0);
+            self.context
+                .bind_texture(glow::TEXTURE_2D, Some(trail_buffer.texture));
+
+            self.context
+                .draw_elements(glow::TRIANGLES, 6, glow::UNSIGNED_SHORT, 0);
+
+            disable_blend_mode(&self.context);
+        }
+    }
+
+    pub fn with_antialiasing<T>(&self, draw_call: T) -> ()
+    where
+        T: Fn() -> (),
+    {
+        match (self.antialiasing, &self.fxaa_buffer) {
+            (Antialiasing::Off, _) => draw_call(),
+            (Antialiasing::Fxaa, Some(fxaa_buffer)) => {
+                fxaa_buffer.draw_to(&self.context, draw_call);
+                self.draw_fxaa(fxaa_buffer);
+            }
+            _ => {
+                self.antialiasing_pass.draw_to(draw_call);
+
+                let _pass = self.profiler.pass("frame/msaa_resolve");
+                self.antialiasing_pass.resolve();
+            }
+        }
+    }
+
+    fn draw_fxaa(&self, fxaa_buffer: &Framebuffer) -> () {
+        let _pass = self.profiler.pass("frame/fxaa");
+
+        unsafe {
+            self.context.viewport(
+                0,
+                0,
+                self.physical_width as i32,
+                self.physical_height as i32,
+            );
+            self.context.disable(glow::BLEND);
+
+            self.fxaa_pass.use_program();
+            self.context
+                .bind_vertex_array(Some(self.draw_fxaa_buffer.id));
+
+            self.context.active_texture(glow::TEXTURE0);
+            self.context
+                .bind_texture(glow::TEXTURE_2D, Some(fxaa_buffer.texture));
+
+            self.context
+                .draw_elements(glow::TRIANGLES, 6, glow::UNSIGNED_SHORT, 0);
+        }
+    }
+}
+
+fn new_trail_buffer(
+    context: &Context,
+    width: u32,
+    height: u32,
+    settings: &Rc<Settings>,
+) -> Result<Option<Framebuffer>, render::Problem> {
+    if settings.trail_decay <= 0.0 {
+        return Ok(None);
+    }
+
+    // Half floats are enough to let the trails fade out smoothly without
+    // getting stuck on the last few values, as 8-bit colors would.
+    let trail_buffer = Framebuffer::new(
+        context,
+        width,
+        height,
+        render::TextureOptions {
+            format: glow::RGBA16F,
+            ..Default::default()
+        },
+    )?
+    .with_f32_data(&vec![0.0; (4 * width * height) as usize])?;
+
+    Ok(Some(trail_buffer))
+}
+
+// Fall back to the most samples the context supports, or to FXAA if it can’t
+// multisample at all.
+fn clamp_antialiasing(antialiasing: Antialiasing, max_samples: u32) -> Antialiasing {
+    if antialiasing.samples() <= max_samples {
+        return antialiasing;
+    }
+
+    [Antialiasing::Msaa4, Antialiasing::Msaa2]
+        .iter()
+        .find(|fallback| fallback.samples() <= max_samples)
+        .copied()
+        .unwrap_or(Antialiasing::Fxaa)
+}
+
+fn new_fxaa_buffer(
+    context: &Context,
+    width: u32,
+    height: u32,
+    antialiasing: Antialiasing,
+) -> Result<Option<Framebuffer>, render::Problem> {
+    if antialiasing != Antialiasing::Fxaa {
+        return Ok(None);
+    }
+
+    let fxaa_buffer = Framebuffer::new(
+        context,
+        width,
+        height,
+        render::TextureOptions {
+            mag_filter: glow::LINEAR,
+            min_filter: glow::LINEAR,
+            format: glow::RGBA8,
+            ..Default::default()
+        },
+    )?
+    .with_u8_data(&vec![0; (4 * width * height) as usize])?;
+
+    Ok(Some(fxaa_buffer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn falls_back_to_the_supported_antialiasing() {
+        use Antialiasing::*;
+
+        // (requested, max samples, applied)
+        let cases = [
+            (Msaa8, 0, Fxaa),
+            (Msaa8, 1, Fxaa),
+            (Msaa8, 2, Msaa2),
+            (Msaa8, 4, Msaa4),
+            (Msaa8, 8, Msaa8),
+            (Msaa4, 0, Fxaa),
+            (Msaa4, 2, Msaa2),
+            (Msaa4, 8, Msaa4),
+            (Msaa2, 1, Fxaa),
+            (Msaa2, 4, Msaa2),
+            (Fxaa, 0, Fxaa),
+            (Fxaa, 8, Fxaa),
+            (Off, 0, Off),
+            (Off, 8, Off),
+        ];
+
+        for (requested, max_samples, applied) in cases {
+            assert_eq!(
+                clamp_antialiasing(requested, max_samples),
+                applied,
+                "{:?} with at most {} samples",
+                requested,
+                max_samples
+            );
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use crate::preprocessor::{self, Shader, Target};
+use crate::render::Program;
+
+use rustc_hash::FxHashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+// The shader sources this crate was built from
+static SHADER_DIRECTORY: &'static str = concat!(env!("CARGO_MANIFEST_DIR"), "/shaders");
+
+// Watches the shader sources for changes. There’s no file system events here,
+// so call `changed_files` every so often to poll the modification times.
+pub struct ShaderWatcher {
+    directory: PathBuf,
+    modified: FxHashMap<String, SystemTime>,
+}
+
+impl ShaderWatcher {
+    pub fn new() -> io::Result<Self> {
+        let mut watcher = Self {
+            directory: PathBuf::from(SHADER_DIRECTORY),
+            modified: FxHashMap::default(),
+        };
+        watcher.changed_files()?;
+
+        Ok(watcher)
+    }
+
+    // The names of the shaders that changed since the last call, relative to
+    // the shader directory, such as "advection.frag" or
+    // "common/fluid_uniforms.glsl".
+    pub fn changed_files(&mut self) -> io::Result<Vec<String>> {
+        let mut changed = Vec::new();
+        let mut directories = vec![(self.directory.clone(), String::new())];
+
+        while let Some((directory, prefix)) = directories.pop() {
+            for entry in fs::read_dir(&directory)? {
+                let entry = entry?;
+                let metadata = entry.metadata()?;
+                let name = prefix.clone() + &entry.file_name().to_string_lossy();
+
+                if metadata.is_dir() {
+                    directories.push((entry.path(), name + "/"));
+                    continue;
+                }
+
+                let modified = metadata.modified()?;
+                if self.modified.insert(name.clone(), modified) != Some(modified) {
+                    changed.push(name);
+                }
+            }
+        }
+
+        Ok(changed)
+    }
+}
+
+// A program that failed to reload. The old program is still in use.
+#[derive(Debug)]
+pub struct ShaderError {
+    pub shaders: (&'static str, &'static str),
+    pub problem: String,
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}, {}: {}",
+            self.shaders.0, self.shaders.1, self.problem
+        )
+    }
+}
+
+// Read a shader from disk and preprocess it, like build.rs does.
+fn read_shader(name: &str) -> Result<Shader, String> {
+    preprocessor::preprocess(Path::new(SHADER_DIRECTORY), name, Target::current())
+        .map_err(|error| error.to_string())
+}
+
+// Rebuild a program if either of its shaders, or anything they include, is in
+// `changed`. Returns whether the program was rebuilt.
+pub(crate) fn reload_program(
+    program: &mut Program,
+    shaders: (&'static str, &'static str),
+    changed: &[String],
+    errors: &mut Vec<ShaderError>,
+) -> bool {
+    let result = read_shader(shaders.0).and_then(|vertex| Ok((vertex, read_shader(shaders.1)?)));
+
+    // Report a shader that can no longer be read, even if the change was
+    // somewhere else.
+    let (vertex, fragment) = match result {
+        Ok(sources) => sources,
+        Err(problem) => {
+            errors.push(ShaderError { shaders, problem });
+            return false;
+        }
+    };
+
+    let is_affected = vertex
+        .files
+        .iter()
+        .chain(fragment.files.iter())
+        .any(|file| changed.contains(file));
+    if !is_affected {
+        return false;
+    }
+
+    match program.reload((&vertex.source, &fragment.source)) {
+        Ok(()) => true,
+        Err(problem) => {
+            errors.push(ShaderError {
+                shaders,
+                problem: problem.to_string(),
+            });
+            false
+        }
+    }
+}
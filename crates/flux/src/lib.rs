@@ -1,42 +1,127 @@
+mod backend;
+mod background;
+mod bloom;
 mod data;
 mod drawer;
 mod fluid;
+mod frame;
+#[cfg(feature = "hot-reload")]
+mod hot_reload;
 mod noise;
+mod particles;
+mod preprocessor;
 mod render;
 pub mod settings;
+mod svg;
+pub mod view;
+#[cfg(feature = "experimental-wgpu")]
+pub mod webgpu;
 
+use background::BackgroundDrawer;
+use bloom::Bloom;
 use drawer::Drawer;
 use fluid::Fluid;
+use frame::FrameDrawer;
 use glow::HasContext;
 use noise::NoiseInjector;
-use settings::Settings;
+use particles::Particles;
+use settings::{Antialiasing, Background, LineStyle, RenderMode, Settings};
+use view::{Transform2D, ViewTransition, VirtualCanvas};
 
+use std::iter;
 use std::rc::Rc;
 
+pub use backend::Backend;
+pub use drawer::Line;
+#[cfg(feature = "hot-reload")]
+pub use hot_reload::{ShaderError, ShaderWatcher};
+#[cfg(feature = "debug")]
+pub use render::set_log_sink;
+pub use render::GpuTiming;
+
 pub struct Flux {
     fluid: Fluid,
+    background_drawer: BackgroundDrawer,
     drawer: Drawer,
+    // Extra grids of lines, drawn behind the main one
+    layers: Vec<Drawer>,
+    frame_drawer: FrameDrawer,
+    bloom: Bloom,
+    // Only allocated while the render mode is `RenderMode::Particles`
+    particles: Option<Particles>,
     noise_injector: NoiseInjector,
     settings: Rc<Settings>,
+    profiler: render::Profiler,
+
+    view: Transform2D,
+    view_transition: Option<ViewTransition>,
 
     context: render::Context,
+    pixel_ratio: f64,
     elapsed_time: f32,
     last_timestamp: f32,
     frame_time: f32,
     fluid_frame_time: f32,
+    line_time: f32,
+    line_frame_time: f32,
     max_frame_time: f32,
 }
 
 impl Flux {
-    pub fn update(&mut self, settings: &Rc<Settings>) -> () {
+    // Returns the antialiasing actually applied. This falls back to fewer
+    // samples, or to FXAA, if the context doesn’t support the requested
+    // number of samples.
+    pub fn update(&mut self, settings: &Rc<Settings>) -> Result<Antialiasing, Problem> {
+        if self.drawer.virtual_canvas().is_some() {
+            check_virtual_canvas(settings)?;
+        }
+
         self.settings = Rc::clone(settings);
+        self.line_frame_time = self.settings.line_frame_time();
 
         self.fluid.update(&self.settings);
-        self.drawer.update(&self.settings);
+        self.background_drawer.update(&self.settings);
+        self.drawer
+            .update(&self.settings)
+            .map_err(Problem::CannotRender)?;
+        if self.layers.len() == self.settings.layers.len() {
+            for (layer, layer_settings) in self.layers.iter_mut().zip(self.settings.layers.iter()) {
+                layer
+                    .update(&Rc::new(self.settings.for_layer(layer_settings)))
+                    .map_err(Problem::CannotRender)?;
+            }
+        } else {
+            self.layers = new_layers(
+                &self.context,
+                &self.profiler,
+                &self.drawer,
+                self.view,
+                self.pixel_ratio,
+                &self.settings,
+            )
+            .map_err(Problem::CannotRender)?;
+        }
+        let antialiasing = self
+            .frame_drawer
+            .update(&self.settings)
+            .map_err(Problem::CannotRender)?;
+        self.bloom
+            .update(&self.settings)
+            .map_err(Problem::CannotRender)?;
+        self.bloom
+            .set_samples(antialiasing.samples())
+            .map_err(Problem::CannotRender)?;
+        self.update_particles()?;
         self.noise_injector
             .update_channel(0, &self.settings.noise_channel_1);
         self.noise_injector
             .update_channel(1, &self.settings.noise_channel_2);
+
+        Ok(antialiasing)
+    }
+
+    pub fn antialiasing(&self) -> Antialiasing {
+        self.frame_drawer.antialiasing()
     }
 
     pub fn new(
@@ -46,11 +131,25 @@ impl Flux {
         pixel_ratio: f64,
         settings: &Rc<Settings>,
     ) -> Result<Flux, Problem> {
+        render::enable_debug_output(&context);
+        let profiler = render::Profiler::new(&context);
+
         let fluid_frame_time = 1.0 / settings.fluid_simulation_frame_rate;
-        let fluid = Fluid::new(&context, &settings).map_err(Problem::CannotRender)?;
+        let fluid = Fluid::new(&context, &profiler, &settings).map_err(Problem::CannotRender)?;
+
+        let background_drawer = BackgroundDrawer::new(
+            &context,
+            &profiler,
+            logical_width,
+            logical_height,
+            pixel_ratio,
+            &settings,
+        )
+        .map_err(Problem::CannotRender)?;
 
         let drawer = Drawer::new(
             &context,
+            &profiler,
             logical_width,
             logical_height,
             pixel_ratio,
@@ -58,9 +157,46 @@ impl Flux {
         )
         .map_err(Problem::CannotRender)?;
 
-        let mut noise_injector =
-            NoiseInjector::new(&context, settings.fluid_width, settings.fluid_height)
-                .map_err(Problem::CannotRender)?;
+        let layers = new_layers(
+            &context,
+            &profiler,
+            &drawer,
+            Transform2D::default(),
+            pixel_ratio,
+            &settings,
+        )
+        .map_err(Problem::CannotRender)?;
+
+        let frame_drawer = FrameDrawer::new(
+            &context,
+            &profiler,
+            logical_width,
+            logical_height,
+            pixel_ratio,
+            &settings,
+        )
+        .map_err(Problem::CannotRender)?;
+
+        let mut bloom = Bloom::new(
+            &context,
+            &profiler,
+            logical_width,
+            logical_height,
+            pixel_ratio,
+            &settings,
+        )
+        .map_err(Problem::CannotRender)?;
+        bloom
+            .set_samples(frame_drawer.antialiasing().samples())
+            .map_err(Problem::CannotRender)?;
+
+        let mut noise_injector = NoiseInjector::new(
+            &context,
+            &profiler,
+            settings.fluid_width,
+            settings.fluid_height,
+        )
+        .map_err(Problem::CannotRender)?;
 
         noise_injector
             .add_noise(settings.noise_channel_1.clone())
@@ -75,23 +211,254 @@ impl Flux {
             context.flush();
         }
 
-        Ok(Flux {
+        let mut flux = Flux {
             fluid,
+            background_drawer,
             drawer,
+            layers,
+            frame_drawer,
+            bloom,
+            particles: None,
             noise_injector,
             settings: Rc::clone(settings),
+            profiler,
+
+            view: Transform2D::default(),
+            view_transition: None,
 
             context: Rc::clone(context),
+            pixel_ratio,
             elapsed_time: 0.0,
             last_timestamp: 0.0,
             frame_time: 0.0,
             fluid_frame_time,
+            line_time: 0.0,
+            line_frame_time: settings.line_frame_time(),
             max_frame_time: 1.0 / 10.0,
-        })
+        };
+        flux.update_particles()?;
+
+        Ok(flux)
+    }
+
+    fn update_particles(&mut self) -> Result<(), Problem> {
+        match (&self.settings.render_mode, &mut self.particles) {
+            (RenderMode::Particles, Some(particles)) => particles
+                .update(&self.settings)
+                .map_err(Problem::CannotRender)?,
+            (RenderMode::Particles, None) => {
+                let viewport = self.drawer.canvas().viewport;
+                let mut particles = Particles::new(
+                    &self.context,
+                    &self.profiler,
+                    viewport.width,
+                    viewport.height,
+                    self.pixel_ratio,
+                    &self.settings,
+                )
+                .map_err(Problem::CannotRender)?;
+                particles.set_view(self.view);
+                self.particles = Some(particles);
+            }
+            (RenderMode::Lines, _) => self.particles = None,
+        }
+
+        Ok(())
+    }
+
+    pub fn resize(&mut self, logical_width: u32, logical_height: u32) -> Result<(), Problem> {
+        self.background_drawer.resize(logical_width, logical_height);
+        self.drawer
+            .resize(logical_width, logical_height)
+            .map_err(Problem::CannotRender)?;
+        for layer in self.layers.iter_mut() {
+            layer
+                .resize(logical_width, logical_height)
+                .map_err(Problem::CannotRender)?;
+        }
+        self.frame_drawer
+            .resize(logical_width, logical_height)
+            .map_err(Problem::CannotRender)?;
+        self.bloom
+            .resize(logical_width, logical_height)
+            .map_err(Problem::CannotRender)?;
+        if let Some(particles) = &mut self.particles {
+            particles.resize(logical_width, logical_height);
+        }
+
+        Ok(())
+    }
+
+    // Used with `Background::Image`. Expects RGBA pixels with straight alpha,
+    // top row first.
+    pub fn set_background_image(
+        &mut self,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+    ) -> Result<(), Problem> {
+        self.background_drawer
+            .set_image(width, height, pixels)
+            .map_err(Problem::CannotRender)
+    }
+
+    // Used with `EndpointShape::Sprite`. Expects RGBA pixels with straight
+    // alpha, top row first.
+    pub fn set_endpoint_sprite(
+        &mut self,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+    ) -> Result<(), Problem> {
+        self.drawer
+            .set_endpoint_sprite(width, height, pixels)
+            .map_err(Problem::CannotRender)?;
+        self.share_images_with_layers();
+
+        Ok(())
     }
 
-    pub fn resize(&mut self, logical_width: u32, logical_height: u32) {
-        self.drawer.resize(logical_width, logical_height).unwrap(); // fix
+    // A grayscale mask that fades out or shortens the lines, depending on the
+    // `mask_opacity` and `mask_line_length` settings. Expects one byte per
+    // pixel, top row first.
+    pub fn set_mask(&mut self, width: u32, height: u32, pixels: &[u8]) -> Result<(), Problem> {
+        self.drawer
+            .set_mask(width, height, pixels)
+            .map_err(Problem::CannotRender)?;
+        self.share_images_with_layers();
+
+        Ok(())
+    }
+
+    pub fn clear_mask(&mut self) -> () {
+        self.drawer.clear_mask();
+        self.share_images_with_layers();
+    }
+
+    // The translation is in logical pixels. At the default transform, the mask
+    // is centered and scaled to fit the canvas.
+    pub fn set_mask_transform(&mut self, transform: Transform2D) -> () {
+        self.drawer.set_mask_transform(transform);
+        self.share_images_with_layers();
+    }
+
+    fn share_images_with_layers(&mut self) -> () {
+        for layer in self.layers.iter_mut() {
+            layer.share_images_with(&self.drawer);
+        }
+    }
+
+    // The position, color, width and opacity of every line, read back from
+    // the GPU. This is slow, so don’t call it every frame. The lines of the
+    // layers come first, from back to front. The positions are on the canvas,
+    // before the view is applied; see `Line`.
+    pub fn line_snapshot(&self) -> Vec<Line> {
+        self.layers
+            .iter()
+            .chain(iter::once(&self.drawer))
+            .flat_map(|drawer| drawer.line_snapshot())
+            .collect()
+    }
+
+    // Write the lines, as they were last drawn, as an SVG document over the
+    // configured background. Background images aren’t exported, and frames
+    // drawn with a perspective camera can’t be.
+    pub fn export_svg(&self, writer: &mut impl std::io::Write) -> std::io::Result<()> {
+        // Each layer is zoomed by its own view scale. Back to front.
+        let grids: Vec<svg::Grid> = self
+            .layers
+            .iter()
+            .zip(self.settings.layers.iter())
+            .map(|(layer, layer_settings)| (layer, layer_settings.view_scale))
+            .chain(iter::once((&self.drawer, self.settings.view_scale)))
+            .map(|(drawer, view_scale)| svg::Grid {
+                view: self.view.to_matrix(view_scale, 1.0),
+                lines: drawer.line_snapshot(),
+            })
+            .collect();
+
+        svg::write_frame(writer, &self.drawer.canvas(), &self.settings, &grids)
+    }
+
+    // Draw only this node’s part of a canvas spread across several displays.
+    // The fluid is still simulated over the whole canvas, so nodes running with
+    // the same settings and timestamps line up. Only the lines are split
+    // across the canvas, so particles, bloom and backgrounds other than a solid
+    // color are rejected, here and in later updates.
+    pub fn set_virtual_canvas(
+        &mut self,
+        virtual_canvas: Option<VirtualCanvas>,
+    ) -> Result<(), Problem> {
+        if virtual_canvas.is_some() {
+            check_virtual_canvas(&self.settings)?;
+        }
+
+        for layer in self.layers.iter_mut() {
+            layer
+                .set_virtual_canvas(virtual_canvas)
+                .map_err(Problem::CannotRender)?;
+        }
+
+        self.drawer
+            .set_virtual_canvas(virtual_canvas)
+            .map_err(Problem::CannotRender)
+    }
+
+    // Rebuild the programs that use any of the `changed` shaders, as reported
+    // by a `ShaderWatcher`. Programs that fail to build are left as they were.
+    #[cfg(feature = "hot-reload")]
+    pub fn reload_shaders(&mut self, changed: &[String]) -> Vec<ShaderError> {
+        let mut errors = Vec::new();
+
+        self.fluid.reload_shaders(changed, &mut errors);
+        self.noise_injector.reload_shaders(changed, &mut errors);
+        for drawer in self.layers.iter_mut().chain(iter::once(&mut self.drawer)) {
+            drawer.reload_shaders(changed, &mut errors);
+        }
+        self.frame_drawer.reload_shaders(changed, &mut errors);
+
+        errors
+    }
+
+    // Time each pass on the GPU. Returns false if the context doesn’t support
+    // timer queries, in which case there won’t be any timings.
+    pub fn set_gpu_profiling(&mut self, is_enabled: bool) -> bool {
+        self.profiler.set_enabled(is_enabled);
+        self.profiler.is_supported()
+    }
+
+    // The time spent in each pass, averaged over the last second or so of
+    // frames. The results lag a few frames behind. A pass drawn inside another
+    // one, like the lines drawn into the trails or the bloom, is counted as
+    // part of the outer pass, so nothing is counted twice.
+    pub fn gpu_timings(&self) -> Vec<GpuTiming> {
+        self.profiler.timings()
+    }
+
+    // Scales that aren’t positive are clamped to a small positive value.
+    pub fn set_view(&mut self, view: Transform2D) -> () {
+        self.view_transition = None;
+        self.apply_view(view.with_valid_scale());
+    }
+
+    // Ease from the current view to a new one over `duration` seconds.
+    pub fn transition_view(&mut self, view: Transform2D, duration: f32) -> () {
+        self.view_transition = Some(ViewTransition::new(
+            self.view,
+            view.with_valid_scale(),
+            duration,
+        ));
+    }
+
+    fn apply_view(&mut self, view: Transform2D) -> () {
+        self.view = view;
+        self.drawer.set_view(view);
+        for layer in self.layers.iter_mut() {
+            layer.set_view(view);
+        }
+        if let Some(particles) = &mut self.particles {
+            particles.set_view(view);
+        }
     }
 
     pub fn animate(&mut self, timestamp: f32) {
@@ -117,24 +484,168 @@ impl Flux {
             self.frame_time -= self.fluid_frame_time;
         }
 
-        // TODO: the line animation is still dependent on the client’s fps. Is
-        // this worth fixing?
-        self.drawer
-            .place_lines(timestep, &self.fluid.get_velocity());
+        if let Some(transition) = &mut self.view_transition {
+            let view = transition.advance(timestep);
+            if transition.is_finished() {
+                self.view_transition = None;
+            }
+            self.apply_view(view);
+        }
+
+        // Step the lines at a fixed rate, so that the springs behave the same
+        // regardless of the client’s fps.
+        self.line_time += timestep;
+        while self.line_time >= self.line_frame_time {
+            match &mut self.particles {
+                None => {
+                    for drawer in self.layers.iter().chain(iter::once(&self.drawer)) {
+                        drawer.place_lines(self.line_frame_time, &self.fluid.get_velocity());
+                    }
+                }
+                Some(particles) => {
+                    particles.place_particles(self.line_frame_time, &self.fluid.get_velocity())
+                }
+            }
 
-        self.drawer.with_antialiasing(|| unsafe {
-            self.context.clear_color(0.0, 0.0, 0.0, 1.0);
-            self.context.clear(glow::COLOR_BUFFER_BIT);
+            self.line_time -= self.line_frame_time;
+        }
+
+        let draw_frame = || match &self.particles {
+            None => {
+                // The layers are drawn into the same frame, or trail buffer,
+                // as the main grid, so they blend the same way.
+                let blend_mode = self.frame_drawer.line_blend_mode();
+
+                // Back to front
+                for drawer in self.layers.iter().chain(iter::once(&self.drawer)) {
+                    match self.settings.line_style {
+                        LineStyle::Straight => drawer.draw_lines(blend_mode),
+                        LineStyle::Streamline => {
+                            drawer.draw_streamlines(&self.fluid.get_velocity(), blend_mode)
+                        }
+                    }
+                    drawer.draw_endpoints(blend_mode);
+                }
+            }
+            Some(particles) => particles.draw_particles(),
+        };
+
+        if self.frame_drawer.has_trails() {
+            self.frame_drawer.accumulate_trails(timestep, draw_frame);
+        }
+
+        let draw_foreground = || {
+            if self.frame_drawer.has_trails() {
+                self.frame_drawer.draw_trails();
+            } else {
+                draw_frame();
+            }
+        };
+
+        // With bloom, the foreground is drawn once into the bloom’s capture,
+        // which is then reused for the frame itself.
+        if self.bloom.is_enabled() {
+            self.bloom.capture(draw_foreground);
+            self.bloom.blur();
+        }
+
+        self.frame_drawer.with_antialiasing(|| {
+            self.background_drawer.draw();
 
             // Debugging
-            // self.drawer.draw_texture(self.noise_injector.get_noise_channel(0).unwrap());
-            // self.drawer.draw_texture(self.noise_injector.get_noise_channel(1).unwrap());
-            // self.drawer.draw_texture(&self.fluid.get_velocity());
-            // self.drawer.draw_texture(&self.fluid.get_pressure());
+            // self.frame_drawer.draw_texture(self.noise_injector.get_noise_channel(0).unwrap());
+            // self.frame_drawer.draw_texture(self.noise_injector.get_noise_channel(1).unwrap());
+            // self.frame_drawer.draw_texture(&self.fluid.get_velocity());
+            // self.frame_drawer.draw_texture(&self.fluid.get_pressure());
 
-            self.drawer.draw_lines();
-            self.drawer.draw_endpoints();
+            if self.bloom.is_enabled() {
+                self.bloom.draw_capture();
+                self.bloom.draw();
+            } else {
+                draw_foreground();
+            }
         });
+
+        self.profiler.end_frame();
+    }
+}
+
+impl Backend for Flux {
+    type Target = ();
+    type Error = Problem;
+
+    fn update(&mut self, settings: &Rc<Settings>) -> Result<(), Problem> {
+        Flux::update(self, settings).map(|_| ())
+    }
+
+    fn resize(&mut self, logical_width: u32, logical_height: u32) -> Result<(), Problem> {
+        Flux::resize(self, logical_width, logical_height)
+    }
+
+    fn set_view(&mut self, view: Transform2D) -> () {
+        Flux::set_view(self, view);
+    }
+
+    fn animate(&mut self, timestamp: f32, _target: &()) -> () {
+        Flux::animate(self, timestamp);
+    }
+}
+
+// Build the extra line layers to match the main grid.
+fn new_layers(
+    context: &render::Context,
+    profiler: &render::Profiler,
+    main_drawer: &Drawer,
+    view: Transform2D,
+    pixel_ratio: f64,
+    settings: &Rc<Settings>,
+) -> Result<Vec<Drawer>, render::Problem> {
+    let canvas = main_drawer.canvas();
+
+    settings
+        .layers
+        .iter()
+        .map(|layer| {
+            let mut drawer = Drawer::new(
+                context,
+                profiler,
+                canvas.viewport.width,
+                canvas.viewport.height,
+                pixel_ratio,
+                &Rc::new(settings.for_layer(layer)),
+            )?;
+
+            if let Some(virtual_canvas) = main_drawer.virtual_canvas() {
+                drawer.set_virtual_canvas(Some(virtual_canvas))?;
+            }
+            drawer.set_view(view);
+            drawer.share_images_with(main_drawer);
+
+            Ok(drawer)
+        })
+        .collect()
+}
+
+// The other passes would draw each tile as if it were the whole canvas,
+// leaving seams between the displays.
+fn check_virtual_canvas(settings: &Settings) -> Result<(), Problem> {
+    if let RenderMode::Particles = settings.render_mode {
+        return Err(Problem::Unsupported(
+            "Particles can’t be drawn on a virtual canvas",
+        ));
+    }
+
+    if settings.bloom_intensity > 0.0 {
+        return Err(Problem::Unsupported(
+            "Bloom can’t be drawn on a virtual canvas",
+        ));
+    }
+
+    match settings.background {
+        Background::Solid(_) | Background::Transparent => Ok(()),
+        _ => Err(Problem::Unsupported(
+            "Only a solid or transparent background can be drawn on a virtual canvas",
+        )),
     }
 }
 
@@ -142,4 +653,5 @@ impl Flux {
 pub enum Problem {
     CannotReadSettings(String),
     CannotRender(render::Problem),
+    Unsupported(&'static str),
 }
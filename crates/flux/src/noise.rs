@@ -1,6 +1,8 @@
+#[cfg(feature = "hot-reload")]
+use crate::hot_reload::{reload_program, ShaderError};
 use crate::{data, render, settings};
 use render::{
-    Buffer, Context, DoubleFramebuffer, Framebuffer, Program, TextureOptions, Uniform,
+    Buffer, Context, DoubleFramebuffer, Framebuffer, Profiler, Program, TextureOptions, Uniform,
     UniformValue, VertexArrayObject, VertexBufferLayout,
 };
 use settings::Noise;
@@ -61,6 +63,7 @@ impl NoiseChannel {
 
 pub struct NoiseInjector {
     context: Context,
+    profiler: Profiler,
     pub channels: Vec<NoiseChannel>,
     width: u32,
     height: u32,
@@ -99,7 +102,12 @@ impl NoiseInjector {
         }
     }
 
-    pub fn new(context: &Context, width: u32, height: u32) -> Result<Self, render::Problem> {
+    pub fn new(
+        context: &Context,
+        profiler: &Profiler,
+        width: u32,
+        height: u32,
+    ) -> Result<Self, render::Problem> {
         // Geometry
         let plane_vertices = Buffer::from_f32(
             &context,
@@ -167,6 +175,7 @@ impl NoiseInjector {
 
         Ok(Self {
             context: Rc::clone(context),
+            profiler: profiler.clone(),
             channels: Vec::new(),
             width,
             height,
@@ -206,7 +215,7 @@ impl NoiseInjector {
             &self.context,
             &bytemuck::cast_slice(&[uniforms]),
             glow::ARRAY_BUFFER,
-            glow::STATIC_DRAW,
+            glow::DYNAMIC_DRAW,
         )?;
 
         self.channels.push(NoiseChannel {
@@ -223,6 +232,8 @@ impl NoiseInjector {
     }
 
     pub fn generate_all(&mut self, elapsed_time: f32) -> () {
+        let _pass = self.profiler.pass("noise/generate");
+
         for channel in self.channels.iter_mut() {
             let time_since_last_update = elapsed_time - channel.blend_begin_time;
 
@@ -249,6 +260,8 @@ impl NoiseInjector {
         }
     }
     pub fn generate_by_channel_number(&mut self, channel_number: usize, elapsed_time: f32) {
+        let _pass = self.profiler.pass("noise/generate");
+
         if let Some(channel) = self.channels.get_mut(channel_number) {
             self.generate_noise_pass.use_program();
 
@@ -273,6 +286,8 @@ impl NoiseInjector {
         target_textures: &DoubleFramebuffer,
         elapsed_time: f32,
     ) -> () {
+        let _pass = self.profiler.pass("noise/blend");
+
         for channel in self.channels.iter_mut() {
             let blend_progress: f32 = ((elapsed_time - channel.blend_begin_time)
                 / channel.noise.blend_duration)
@@ -322,6 +337,29 @@ impl NoiseInjector {
         }
     }
 
+    // Rebuild the programs whose shaders are in `changed`.
+    #[cfg(feature = "hot-reload")]
+    pub fn reload_shaders(&mut self, changed: &[String], errors: &mut Vec<ShaderError>) -> () {
+        reload_program(
+            &mut self.generate_noise_pass,
+            ("noise.vert", "simplex_noise.frag"),
+            changed,
+            errors,
+        );
+        reload_program(
+            &mut self.blend_with_curl_pass,
+            ("noise.vert", "blend_with_curl.frag"),
+            changed,
+            errors,
+        );
+        reload_program(
+            &mut self.blend_with_wiggle_pass,
+            ("noise.vert", "blend_with_wiggle.frag"),
+            changed,
+            errors,
+        );
+    }
+
     #[allow(dead_code)]
     pub fn get_noise_channel(&self, channel_number: usize) -> Option<&Framebuffer> {
         self.channels
@@ -0,0 +1,523 @@
+use crate::{drawer, render, settings, view};
+use drawer::{Projection, LINE_VERTICES};
+use render::{
+    Buffer, Context, Framebuffer, Profiler, Uniform, UniformValue, VertexArrayObject,
+    VertexBufferLayout,
+};
+use settings::Settings;
+use view::Transform2D;
+
+use bytemuck::{Pod, Zeroable};
+use glow::HasContext;
+use std::rc::Rc;
+
+static PARTICLE_VERT_SHADER: &'static str =
+    include_str!(concat!(env!("OUT_DIR"), "/shaders/particle.vert"));
+static PARTICLE_FRAG_SHADER: &'static str =
+    include_str!(concat!(env!("OUT_DIR"), "/shaders/particle.frag"));
+static PLACE_PARTICLES_VERT_SHADER: &'static str =
+    include_str!(concat!(env!("OUT_DIR"), "/shaders/place_particles.vert"));
+static PLACE_PARTICLES_FRAG_SHADER: &'static str =
+    include_str!(concat!(env!("OUT_DIR"), "/shaders/place_particles.frag"));
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct ParticleState {
+    position: [f32; 2],
+    velocity: [f32; 2],
+    color: [f32; 4],
+    age: f32,
+    lifetime: f32,
+}
+
+// Particles that drift freely through the fluid, as opposed to the lines,
+// which are anchored to their basepoints.
+pub struct Particles {
+    context: Context,
+    profiler: Profiler,
+    settings: Rc<Settings>,
+
+    physical_width: u32,
+    physical_height: u32,
+    pixel_ratio: f64,
+
+    pub particle_count: u32,
+    elapsed_time: f32,
+    view: Transform2D,
+
+    particle_vertices: Buffer,
+    particle_state_buffer: Buffer,
+    transform_feedback_buffer: glow::TransformFeedback,
+    // A dedicated buffer to write out the data from the transform feedback pass
+    particle_state_feedback_buffer: Buffer,
+
+    place_particles_buffer: VertexArrayObject,
+    draw_particles_buffer: VertexArrayObject,
+
+    view_buffer: Buffer,
+
+    place_particles_pass: render::Program,
+    draw_particles_pass: render::Program,
+}
+
+impl Particles {
+    pub fn new(
+        context: &Context,
+        profiler: &Profiler,
+        logical_width: u32,
+        logical_height: u32,
+        pixel_ratio: f64,
+        settings: &Rc<Settings>,
+    ) -> Result<Self, render::Problem> {
+        let physical_width = (f64::from(logical_width) * pixel_ratio) as u32;
+        let physical_height = (f64::from(logical_height) * pixel_ratio) as u32;
+
+        let particle_count = settings.particle_count;
+        let particle_state = new_particle_state(particle_count);
+        let particle_state_buffer = Buffer::from_f32(
+            &context,
+            &bytemuck::cast_slice(&particle_state),
+            glow::ARRAY_BUFFER,
+            glow::DYNAMIC_COPY,
+        )?;
+        let particle_state_feedback_buffer = Buffer::from_f32(
+            &context,
+            &bytemuck::cast_slice(&particle_state),
+            glow::ARRAY_BUFFER,
+            glow::DYNAMIC_READ,
+        )?;
+        let transform_feedback_buffer = unsafe {
+            context
+                .create_transform_feedback()
+                .map_err(|_| render::Problem::OutOfMemory)?
+        };
+
+        let particle_vertices = Buffer::from_f32(
+            &context,
+            &bytemuck::cast_slice(&LINE_VERTICES),
+            glow::ARRAY_BUFFER,
+            glow::STATIC_DRAW,
+        )?;
+
+        // Programs
+
+        let place_particles_program = render::Program::new_with_transform_feedback(
+            &context,
+            (PLACE_PARTICLES_VERT_SHADER, PLACE_PARTICLES_FRAG_SHADER),
+            &render::TransformFeedback {
+                // The order here must match the order in the buffer!
+                names: &[
+                    "vPosition",
+                    "vVelocityVector",
+                    "vColor",
+                    "vAge",
+                    "vLifetime",
+                ],
+                mode: glow::INTERLEAVED_ATTRIBS,
+            },
+        )?;
+        let draw_particles_program =
+            render::Program::new(&context, (PARTICLE_VERT_SHADER, PARTICLE_FRAG_SHADER))?;
+
+        let place_particles_buffer = VertexArrayObject::empty(context)?;
+        let draw_particles_buffer = VertexArrayObject::empty(context)?;
+
+        // Uniforms
+
+        let projection_matrix = drawer::new_projection_matrix(physical_width, physical_height);
+        let view = Transform2D::default();
+        let view_matrix = view.to_matrix(settings.view_scale, pixel_ratio);
+        let projection = Projection {
+            projection: projection_matrix.as_slice().try_into().unwrap(),
+            view: view_matrix.as_slice().try_into().unwrap(),
+        };
+        let view_buffer = Buffer::from_f32(
+            &context,
+            &bytemuck::cast_slice(&[projection]),
+            glow::ARRAY_BUFFER,
+            glow::STATIC_DRAW,
+        )?;
+
+        place_particles_program.set_uniform(&Uniform {
+            name: "velocityTexture",
+            value: UniformValue::Texture2D(0),
+        });
+        draw_particles_program.set_uniform_block("Projection", 0);
+
+        let mut particles = Self {
+            context: Rc::clone(context),
+            profiler: profiler.clone(),
+            settings: Rc::clone(settings),
+
+            physical_width,
+            physical_height,
+            pixel_ratio,
+
+            particle_count,
+            elapsed_time: 0.0,
+            view,
+
+            particle_vertices,
+            particle_state_buffer,
+            transform_feedback_buffer,
+            particle_state_feedback_buffer,
+
+            place_particles_buffer,
+            draw_particles_buffer,
+
+            view_buffer,
+
+            place_particles_pass: place_particles_program,
+            draw_particles_pass: draw_particles_program,
+        };
+
+        particles.update_projection();
+        particles.update(settings)?;
+
+        Ok(particles)
+    }
+
+    pub fn update(&mut self, settings: &Rc<Settings>) -> Result<(), render::Problem> {
+        self.settings = Rc::clone(settings);
+
+        if settings.particle_count != self.particle_count {
+            self.particle_count = settings.particle_count;
+
+            let particle_state = new_particle_state(self.particle_count);
+            self.particle_state_buffer = Buffer::from_f32(
+                &self.context,
+                &bytemuck::cast_slice(&particle_state),
+                glow::ARRAY_BUFFER,
+                glow::DYNAMIC_COPY,
+            )?;
+            self.particle_state_feedback_buffer = Buffer::from_f32(
+                &self.context,
+                &bytemuck::cast_slice(&particle_state),
+                glow::ARRAY_BUFFER,
+                glow::DYNAMIC_READ,
+            )?;
+        }
+
+        self.update_particle_buffers()?;
+        self.update_view();
+
+        // Workaround for iOS
+        let color_wheel = settings::color_wheel_from_scheme(&settings.color_scheme);
+        let particle_speed = (f64::from(settings.particle_speed) * self.pixel_ratio) as f32;
+        self.place_particles_pass.set_uniforms(&[
+            &Uniform {
+                name: "uParticleLifetime",
+                value: UniformValue::Float(settings.particle_lifetime),
+            },
+            &Uniform {
+                name: "uParticleSpeed",
+                value: UniformValue::Float(particle_speed),
+            },
+            &Uniform {
+                name: "uAdvectionDirection",
+                value: UniformValue::Float(settings.advection_direction),
+            },
+            &Uniform {
+                name: "uAdjustAdvection",
+                value: UniformValue::Float(settings.adjust_advection),
+            },
+            &Uniform {
+                name: "uColorWheel[0]",
+                value: UniformValue::Vec4Array(&color_wheel),
+            },
+        ]);
+        self.draw_particles_pass.set_uniforms(&[
+            &Uniform {
+                name: "uParticleSize",
+                value: UniformValue::Float(
+                    (f64::from(settings.particle_size) * self.pixel_ratio) as f32,
+                ),
+            },
+            &Uniform {
+                name: "uParticleSpeed",
+                value: UniformValue::Float(particle_speed),
+            },
+            &Uniform {
+                name: "uParticleStreakLength",
+                value: UniformValue::Float(settings.particle_streak_length),
+            },
+            &Uniform {
+                name: "uAdjustAdvection",
+                value: UniformValue::Float(settings.adjust_advection),
+            },
+        ]);
+
+        Ok(())
+    }
+
+    pub fn resize(&mut self, logical_width: u32, logical_height: u32) -> () {
+        self.physical_width = (f64::from(logical_width) * self.pixel_ratio) as u32;
+        self.physical_height = (f64::from(logical_height) * self.pixel_ratio) as u32;
+
+        // Particles that end up off-screen respawn on the next pass.
+        self.update_projection();
+    }
+
+    pub fn set_view(&mut self, view: Transform2D) -> () {
+        self.view = view;
+        self.update_view();
+    }
+
+    fn update_view(&self) -> () {
+        let view = self
+            .view
+            .to_matrix(self.settings.view_scale, self.pixel_ratio);
+        let view: [f32; 16] = view.as_slice().try_into().unwrap();
+
+        unsafe {
+            self.context
+                .bind_buffer(glow::UNIFORM_BUFFER, Some(self.view_buffer.id));
+            self.context.buffer_sub_data_u8_slice(
+                glow::UNIFORM_BUFFER,
+                std::mem::size_of::<[f32; 16]>() as i32,
+                &bytemuck::cast_slice(&view),
+            );
+            self.context.bind_buffer(glow::UNIFORM_BUFFER, None);
+        }
+    }
+
+    fn update_projection(&self) -> () {
+        let projection: [f32; 16] =
+            drawer::new_projection_matrix(self.physical_width, self.physical_height)
+                .as_slice()
+                .try_into()
+                .unwrap();
+
+        unsafe {
+            self.context
+                .bind_buffer(glow::UNIFORM_BUFFER, Some(self.view_buffer.id));
+            self.context.buffer_sub_data_u8_slice(
+                glow::UNIFORM_BUFFER,
+                0,
+                &bytemuck::cast_slice(&projection),
+            );
+            self.context.bind_buffer(glow::UNIFORM_BUFFER, None);
+        }
+
+        // Workaround for iOS
+        self.place_particles_pass.set_uniforms(&[
+            &Uniform {
+                name: "uProjection",
+                value: UniformValue::Mat4(&projection),
+            },
+            &Uniform {
+                name: "uBounds",
+                value: UniformValue::Vec2(&[
+                    self.physical_width as f32 / 2.0,
+                    self.physical_height as f32 / 2.0,
+                ]),
+            },
+        ]);
+    }
+
+    fn update_particle_buffers(&self) -> Result<(), render::Problem> {
+        let particle_state_attribs = |divisor| {
+            [
+                (
+                    &self.particle_state_buffer,
+                    VertexBufferLayout {
+                        name: "iPosition",
+                        size: 2,
+                        type_: glow::FLOAT,
+                        stride: 10 * 4,
+                        offset: 0 * 4,
+                        divisor,
+                    },
+                ),
+                (
+                    &self.particle_state_buffer,
+                    VertexBufferLayout {
+                        name: "iVelocityVector",
+                        size: 2,
+                        type_: glow::FLOAT,
+                        stride: 10 * 4,
+                        offset: 2 * 4,
+                        divisor,
+                    },
+                ),
+                (
+                    &self.particle_state_buffer,
+                    VertexBufferLayout {
+                        name: "iColor",
+                        size: 4,
+                        type_: glow::FLOAT,
+                        stride: 10 * 4,
+                        offset: 4 * 4,
+                        divisor,
+                    },
+                ),
+                (
+                    &self.particle_state_buffer,
+                    VertexBufferLayout {
+                        name: "iAge",
+                        size: 1,
+                        type_: glow::FLOAT,
+                        stride: 10 * 4,
+                        offset: 8 * 4,
+                        divisor,
+                    },
+                ),
+                (
+                    &self.particle_state_buffer,
+                    VertexBufferLayout {
+                        name: "iLifetime",
+                        size: 1,
+                        type_: glow::FLOAT,
+                        stride: 10 * 4,
+                        offset: 9 * 4,
+                        divisor,
+                    },
+                ),
+            ]
+        };
+
+        self.place_particles_buffer.update(
+            &self.place_particles_pass,
+            &particle_state_attribs(0),
+            None,
+        )?;
+
+        self.draw_particles_buffer.update(
+            &self.draw_particles_pass,
+            &[(
+                &self.particle_vertices,
+                VertexBufferLayout {
+                    name: "particleVertex",
+                    size: 2,
+                    type_: glow::FLOAT,
+                    ..Default::default()
+                },
+            )],
+            None,
+        )?;
+        self.draw_particles_buffer.update(
+            &self.draw_particles_pass,
+            &particle_state_attribs(1),
+            None,
+        )?;
+
+        Ok(())
+    }
+
+    pub fn place_particles(&mut self, timestep: f32, texture: &Framebuffer) -> () {
+        let _pass = self.profiler.pass("particles/place");
+
+        self.elapsed_time += timestep;
+
+        unsafe {
+            self.context.disable(glow::BLEND);
+
+            self.place_particles_pass.use_program();
+
+            self.context
+                .bind_vertex_array(Some(self.place_particles_buffer.id));
+
+            self.place_particles_pass.set_uniforms(&[
+                &Uniform {
+                    name: "deltaT",
+                    value: UniformValue::Float(timestep),
+                },
+                &Uniform {
+                    name: "uElapsedTime",
+                    value: UniformValue::Float(self.elapsed_time),
+                },
+            ]);
+
+            self.context.active_texture(glow::TEXTURE0);
+            self.context
+                .bind_texture(glow::TEXTURE_2D, Some(texture.texture));
+
+            self.context.bind_transform_feedback(
+                glow::TRANSFORM_FEEDBACK,
+                Some(self.transform_feedback_buffer),
+            );
+            self.context.bind_buffer_base(
+                glow::TRANSFORM_FEEDBACK_BUFFER,
+                0,
+                Some(self.particle_state_feedback_buffer.id),
+            );
+
+            self.context.enable(glow::RASTERIZER_DISCARD);
+            self.context.begin_transform_feedback(glow::POINTS);
+
+            self.context
+                .draw_arrays(glow::POINTS, 0, self.particle_count as i32);
+
+            self.context.end_transform_feedback();
+            self.context
+                .bind_buffer_base(glow::TRANSFORM_FEEDBACK_BUFFER, 0, None);
+            self.context
+                .bind_transform_feedback(glow::TRANSFORM_FEEDBACK, None);
+            self.context.disable(glow::RASTERIZER_DISCARD);
+
+            self.context
+                .bind_buffer(glow::COPY_WRITE_BUFFER, Some(self.particle_state_buffer.id));
+            self.context.bind_buffer(
+                glow::COPY_READ_BUFFER,
+                Some(self.particle_state_feedback_buffer.id),
+            );
+            // Copy new particle state
+            self.context.copy_buffer_sub_data(
+                glow::COPY_READ_BUFFER,
+                glow::COPY_WRITE_BUFFER,
+                0,
+                0,
+                (std::mem::size_of::<ParticleState>() as i32) * (self.particle_count as i32),
+            );
+            self.context.bind_buffer(glow::COPY_READ_BUFFER, None);
+            self.context.bind_buffer(glow::COPY_WRITE_BUFFER, None);
+        }
+    }
+
+    pub fn draw_particles(&self) -> () {
+        let _pass = self.profiler.pass("particles/draw");
+
+        unsafe {
+            self.context.viewport(
+                0,
+                0,
+                self.physical_width as i32,
+                self.physical_height as i32,
+            );
+
+            self.context.enable(glow::BLEND);
+            self.context.blend_func_separate(
+                glow::ONE,
+                glow::ONE,
+                glow::ONE,
+                glow::ONE_MINUS_SRC_ALPHA,
+            );
+
+            self.draw_particles_pass.use_program();
+            self.context
+                .bind_vertex_array(Some(self.draw_particles_buffer.id));
+
+            self.context
+                .bind_buffer_base(glow::UNIFORM_BUFFER, 0, Some(self.view_buffer.id));
+
+            self.context
+                .draw_arrays_instanced(glow::TRIANGLES, 0, 6, self.particle_count as i32);
+
+            self.context.disable(glow::BLEND);
+        }
+    }
+}
+
+// Every particle starts out expired, so the first pass spawns them.
+fn new_particle_state(particle_count: u32) -> Vec<ParticleState> {
+    vec![
+        ParticleState {
+            position: [0.0, 0.0],
+            velocity: [0.0, 0.0],
+            color: [0.0, 0.0, 0.0, 0.0],
+            age: 0.0,
+            lifetime: 0.0,
+        };
+        particle_count as usize
+    ]
+}
@@ -0,0 +1,307 @@
+// A small GLSL preprocessor. It adds the version and target defines, and
+// resolves `#include "common/xyz.glsl"` directives relative to the shader
+// directory.
+//
+// This file is also compiled into build.rs, so it can only depend on std.
+#![allow(dead_code)]
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Target {
+    WebGL,
+    Desktop,
+}
+
+impl Target {
+    pub fn current() -> Self {
+        if cfg!(target_arch = "wasm32") {
+            Target::WebGL
+        } else {
+            Target::Desktop
+        }
+    }
+
+    fn header(&self) -> &'static str {
+        match self {
+            Target::WebGL => "#version 300 es\n#define WEBGL 1\n",
+            Target::Desktop => "#version 330\n#define DESKTOP 1\n",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct PreprocessError {
+    pub file: String,
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for PreprocessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.file, self.line, self.message)
+    }
+}
+
+pub struct Shader {
+    pub source: String,
+    // Every file that went into the source. The index is the source string
+    // number used in the `#line` directives.
+    pub files: Vec<String>,
+}
+
+// The source string numbers are listed in comments at the top of the shader,
+// so that compile errors can be mapped back at runtime.
+static SOURCE_COMMENT: &'static str = "// #source ";
+
+pub fn preprocess(directory: &Path, name: &str, target: Target) -> Result<Shader, PreprocessError> {
+    let mut body = String::new();
+    let mut files = Vec::new();
+    include(directory, name, None, &mut body, &mut files)?;
+
+    let mut source = String::from(target.header());
+    for (number, file) in files.iter().enumerate() {
+        source.push_str(&format!("{}{} {}\n", SOURCE_COMMENT, number, file));
+    }
+    source.push_str(&body);
+
+    Ok(Shader { source, files })
+}
+
+// Each file is included at most once per shader, so shared declarations
+// can include whatever they depend on.
+fn include(
+    directory: &Path,
+    name: &str,
+    included_from: Option<(&str, usize)>,
+    output: &mut String,
+    files: &mut Vec<String>,
+) -> Result<(), PreprocessError> {
+    if files.iter().any(|file| file == name) {
+        return Ok(());
+    }
+
+    let contents = fs::read_to_string(directory.join(name)).map_err(|error| {
+        let (file, line) = included_from.unwrap_or((name, 0));
+        PreprocessError {
+            file: file.to_string(),
+            line,
+            message: format!("cannot read {}: {}", name, error),
+        }
+    })?;
+
+    let number = files.len();
+    files.push(name.to_string());
+    output.push_str(&format!("#line 1 {}\n", number));
+
+    for (index, line) in contents.lines().enumerate() {
+        let line_number = index + 1;
+
+        if let Some(path) = line.trim_start().strip_prefix("#include") {
+            let path = path.trim();
+            let path = path
+                .strip_prefix('"')
+                .and_then(|path| path.strip_suffix('"'))
+                .ok_or_else(|| PreprocessError {
+                    file: name.to_string(),
+                    line: line_number,
+                    message: format!("expected a quoted path after #include, found {}", path),
+                })?;
+
+            include(directory, path, Some((name, line_number)), output, files)?;
+            output.push_str(&format!("#line {} {}\n", line_number + 1, number));
+        } else {
+            output.push_str(line);
+            output.push('\n');
+        }
+    }
+
+    Ok(())
+}
+
+// Replace the source string numbers in a compiler log with the file names
+// listed in `source`. Drivers format locations as `0:12`, `0:12(5)` or
+// `0(12)`.
+pub fn map_log(source: &str, log: &str) -> String {
+    let files = source_files(source);
+
+    if files.is_empty() {
+        return log.to_string();
+    }
+
+    log.lines()
+        .map(|line| map_location(line, &files))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+// The file and line in the original sources of a byte offset into a
+// preprocessed shader.
+pub fn locate(source: &str, offset: usize) -> Option<(String, usize)> {
+    let files = source_files(source);
+    let line_index = source.get(..offset)?.matches('\n').count();
+    let lines: Vec<&str> = source.lines().take(line_index).collect();
+
+    lines.iter().enumerate().rev().find_map(|(index, line)| {
+        let mut directive = line.strip_prefix("#line ")?.split_whitespace();
+        let line_number: usize = directive.next()?.parse().ok()?;
+        let number: usize = directive.next()?.parse().ok()?;
+        let file = files.get(number)?;
+
+        Some((file.to_string(), line_number + line_index - index - 1))
+    })
+}
+
+fn source_files(source: &str) -> Vec<&str> {
+    source
+        .lines()
+        .filter_map(|line| line.strip_prefix(SOURCE_COMMENT))
+        .filter_map(|entry| entry.split_once(' ').map(|(_, file)| file))
+        .collect()
+}
+
+// Map the first location on a line of the log.
+fn map_location(line: &str, files: &[&str]) -> String {
+    let bytes = line.as_bytes();
+    let mut start = 0;
+
+    while start < bytes.len() {
+        let digits = bytes[start..]
+            .iter()
+            .take_while(|byte| byte.is_ascii_digit())
+            .count();
+        let follows_word = start > 0 && bytes[start - 1].is_ascii_alphanumeric();
+
+        if digits > 0 && !follows_word {
+            let end = start + digits;
+            let is_location = matches!(bytes.get(end), Some(b':') | Some(b'('))
+                && bytes.get(end + 1).is_some_and(u8::is_ascii_digit);
+
+            if is_location {
+                if let Some(file) = line[start..end]
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|number| files.get(number))
+                {
+                    return format!("{}{}{}", &line[..start], file, &line[end..]);
+                }
+            }
+        }
+
+        start += digits.max(1);
+    }
+
+    line.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    // Write the files into a fresh directory under the system temp directory.
+    fn shader_directory(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("flux-preprocessor-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+
+        for (path, contents) in files {
+            let path = directory.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+
+        directory
+    }
+
+    #[test]
+    fn resolves_nested_includes() {
+        let directory = shader_directory(
+            "nested",
+            &[
+                ("main.vert", "#include \"common/a.glsl\"\nvoid main() {}\n"),
+                ("common/a.glsl", "#include \"common/b.glsl\"\nfloat a;\n"),
+                ("common/b.glsl", "float b;\n"),
+            ],
+        );
+        let shader = preprocess(&directory, "main.vert", Target::Desktop).unwrap();
+
+        assert_eq!(
+            shader.files,
+            ["main.vert", "common/a.glsl", "common/b.glsl"]
+        );
+        assert!(shader
+            .source
+            .starts_with("#version 330\n#define DESKTOP 1\n"));
+        assert!(shader.source.ends_with(
+            "#line 1 0\n\
+             #line 1 1\n\
+             #line 1 2\n\
+             float b;\n\
+             #line 2 1\n\
+             float a;\n\
+             #line 2 0\n\
+             void main() {}\n"
+        ));
+    }
+
+    #[test]
+    fn includes_each_file_once() {
+        let directory = shader_directory(
+            "repeated",
+            &[
+                (
+                    "main.vert",
+                    "#include \"common/a.glsl\"\n#include \"common/b.glsl\"\n#include \"common/a.glsl\"\n",
+                ),
+                ("common/a.glsl", "float a;\n"),
+                ("common/b.glsl", "#include \"common/a.glsl\"\nfloat b;\n"),
+            ],
+        );
+        let shader = preprocess(&directory, "main.vert", Target::WebGL).unwrap();
+
+        assert_eq!(
+            shader.files,
+            ["main.vert", "common/a.glsl", "common/b.glsl"]
+        );
+        assert_eq!(shader.source.matches("float a;").count(), 1);
+        assert_eq!(shader.source.matches("float b;").count(), 1);
+    }
+
+    #[test]
+    fn reports_a_missing_include() {
+        let directory = shader_directory(
+            "missing",
+            &[("main.vert", "float a;\n#include \"common/missing.glsl\"\n")],
+        );
+        let error = preprocess(&directory, "main.vert", Target::Desktop)
+            .err()
+            .unwrap();
+
+        assert_eq!(error.file, "main.vert");
+        assert_eq!(error.line, 2);
+        assert!(error.message.starts_with("cannot read common/missing.glsl"));
+    }
+
+    #[test]
+    fn maps_log_locations() {
+        let source = "#version 330\n// #source 0 main.vert\n// #source 1 common/a.glsl\n";
+
+        assert_eq!(
+            map_log(source, "ERROR: 1:12: undeclared identifier"),
+            "ERROR: common/a.glsl:12: undeclared identifier"
+        );
+        assert_eq!(
+            map_log(source, "1:12(5): error: syntax error"),
+            "common/a.glsl:12(5): error: syntax error"
+        );
+        assert_eq!(
+            map_log(source, "0(12) : error C1008: undefined variable"),
+            "main.vert(12) : error C1008: undefined variable"
+        );
+        // Unknown source numbers are left alone.
+        assert_eq!(map_log(source, "ERROR: 7:3: oops"), "ERROR: 7:3: oops");
+    }
+}
@@ -1,6 +1,9 @@
+use crate::preprocessor;
+
 use glow::HasContext;
 use rustc_hash::FxHashMap;
 use std::cell::{Ref, RefCell};
+use std::collections::VecDeque;
 use std::rc::Rc;
 use thiserror::Error;
 
@@ -80,6 +83,7 @@ impl Buffer {
 
             buffer
         };
+        check_error(context, "Buffer::from_f32");
 
         Ok(Self {
             context: Rc::clone(context),
@@ -109,6 +113,7 @@ impl Buffer {
 
             buffer
         };
+        check_error(context, "Buffer::from_u16");
 
         Ok(Self {
             context: Rc::clone(context),
@@ -143,6 +148,7 @@ impl Buffer {
 
             buffer
         };
+        check_error(context, "Buffer::from_u32");
 
         Ok(Self {
             context: Rc::clone(context),
@@ -234,6 +240,7 @@ impl Framebuffer {
 
             (framebuffer, texture)
         };
+        check_error(context, "Framebuffer::new");
 
         Ok(Self {
             context: Rc::clone(context),
@@ -246,6 +253,14 @@ impl Framebuffer {
     }
 
     pub fn with_f32_data(self, data: &[f32]) -> Result<Self> {
+        self.with_data(bytemuck::cast_slice(&data), data.len())
+    }
+
+    pub fn with_u8_data(self, data: &[u8]) -> Result<Self> {
+        self.with_data(data, data.len())
+    }
+
+    fn with_data(self, data: &[u8], data_len: usize) -> Result<Self> {
         let TextureFormat {
             internal_format,
             format,
@@ -254,10 +269,10 @@ impl Framebuffer {
         } = detect_texture_format(self.options.format)?;
 
         let expected_size = size * ((self.width * self.height) as usize);
-        if data.len() != expected_size {
+        if data_len != expected_size {
             return Err(Problem::WrongDataSize {
                 expected: expected_size,
-                actual: data.len(),
+                actual: data_len,
             });
         }
 
@@ -265,6 +280,9 @@ impl Framebuffer {
             self.context
                 .bind_texture(glow::TEXTURE_2D, Some(self.texture));
 
+            // Rows of single-channel byte textures aren’t necessarily 4-byte aligned.
+            self.context.pixel_store_i32(glow::UNPACK_ALIGNMENT, 1);
+
             // let array = js_sys::Float32Array::view(data);
             self.context.tex_image_2d(
                 glow::TEXTURE_2D,
@@ -275,9 +293,11 @@ impl Framebuffer {
                 0,
                 format,
                 type_,
-                Some(&bytemuck::cast_slice(&data)),
+                Some(data),
             );
             // .map_err(|Err(Problem::CannotWriteToTexture))?;
+            // Back to the default for everything else
+            self.context.pixel_store_i32(glow::UNPACK_ALIGNMENT, 4);
 
             self.context.bind_texture(glow::TEXTURE_2D, None);
 
@@ -292,6 +312,7 @@ impl Framebuffer {
             );
             self.context.bind_framebuffer(glow::FRAMEBUFFER, None);
         }
+        check_error(&self.context, "Framebuffer::with_data");
 
         Ok(self)
     }
@@ -313,6 +334,7 @@ impl Framebuffer {
 
             self.context.bind_framebuffer(glow::FRAMEBUFFER, None);
         }
+        check_error(&self.context, "Framebuffer::clear_color_with");
 
         Ok(())
     }
@@ -327,6 +349,7 @@ impl Framebuffer {
             draw_call();
             context.bind_framebuffer(glow::DRAW_FRAMEBUFFER, None);
         }
+        check_error(context, "Framebuffer::draw_to");
     }
 }
 
@@ -415,6 +438,11 @@ pub struct Program {
     pub program: glow::Program,
     attributes: FxHashMap<String, AttributeInfo>,
     uniforms: FxHashMap<String, UniformInfo>,
+    // Kept to rebuild the program with the same varyings and block bindings
+    #[cfg(feature = "hot-reload")]
+    transform_feedback: Option<(Vec<&'static str>, u32)>,
+    #[cfg(feature = "hot-reload")]
+    uniform_blocks: Rc<RefCell<FxHashMap<String, u32>>>,
 }
 
 impl Program {
@@ -435,89 +463,85 @@ impl Program {
         shaders: (&str, &str),
         transform_feedback: Option<&TransformFeedback>,
     ) -> Result<Self> {
-        let vertex_shader = compile_shader(&context, glow::VERTEX_SHADER, shaders.0)?;
-        let fragment_shader = compile_shader(&context, glow::FRAGMENT_SHADER, shaders.1)?;
-
-        let program = unsafe {
-            let program = context
-                .create_program()
-                .map_err(|_| Problem::CannotCreateProgram)?;
-            context.attach_shader(program, vertex_shader);
-            context.attach_shader(program, fragment_shader);
-
-            if let Some(TransformFeedback { names, mode }) = transform_feedback {
-                context.transform_feedback_varyings(program, names, *mode);
-            }
+        let program = link_program(
+            context,
+            shaders,
+            transform_feedback.map(|tf| (tf.names, tf.mode)),
+            &FxHashMap::default(),
+        )?;
+        let (attributes, uniforms) = unsafe { get_active_variables(context, program) };
+        check_error(context, "Program::new");
 
-            context.link_program(program);
-
-            if !context.get_program_link_status(program) {
-                return Err(Problem::CannotLinkProgram(
-                    context.get_program_info_log(program),
-                ));
-            }
-
-            // Delete the shaders to free up memory
-            context.detach_shader(program, vertex_shader);
-            context.detach_shader(program, fragment_shader);
-            context.delete_shader(vertex_shader);
-            context.delete_shader(fragment_shader);
+        Ok(Program {
+            context: Rc::clone(context),
+            program,
+            attributes,
+            uniforms,
+            #[cfg(feature = "hot-reload")]
+            transform_feedback: transform_feedback.map(|tf| (tf.names.to_vec(), tf.mode)),
+            #[cfg(feature = "hot-reload")]
+            uniform_blocks: Rc::new(RefCell::new(FxHashMap::default())),
+        })
+    }
 
-            program
-        };
+    // Rebuild the program from new sources. The attribute locations, uniform
+    // values and uniform block bindings carry over, so the vertex arrays and
+    // uniforms set up for the old program keep working. If the new sources
+    // don’t compile or link, the old program is left as is.
+    //
+    // The old program is deleted, so replace any clones of it afterwards.
+    #[cfg(feature = "hot-reload")]
+    pub fn reload(&mut self, shaders: (&str, &str)) -> Result<()> {
+        let context = &self.context;
+        let attribute_locations = self
+            .attributes
+            .iter()
+            .map(|(name, info)| (name.clone(), info.location))
+            .collect();
+        let program = link_program(
+            context,
+            shaders,
+            self.transform_feedback
+                .as_ref()
+                .map(|(names, mode)| (names.as_slice(), *mode)),
+            &attribute_locations,
+        )?;
+        let (attributes, uniforms) = unsafe { get_active_variables(context, program) };
 
-        // Get attribute locations
-        let mut attributes = FxHashMap::default();
         unsafe {
-            let attribute_count = context.get_active_attributes(program);
-            for num in 0..attribute_count {
-                if let Some(info) = context.get_active_attribute(program, num) {
-                    if let Some(location) = context.get_attrib_location(program, &info.name) {
-                        attributes.insert(
-                            info.name,
-                            AttributeInfo {
-                                type_: info.atype,
-                                size: info.size as u32,
-                                location: location,
-                            },
-                        );
+            context.use_program(Some(program));
+
+            for (name, info) in self.uniforms.iter() {
+                if let Some(new_info) = uniforms.get(name) {
+                    if new_info.type_ == info.type_ && new_info.size == info.size {
+                        copy_uniform(context, (self.program, program), name, info);
                     }
                 }
             }
-        }
 
-        // Get uniform locations
-        let mut uniforms = FxHashMap::default();
-        unsafe {
-            let uniform_count = context.get_active_uniforms(program);
-            for num in 0..uniform_count {
-                if let Some(info) = context.get_active_uniform(program, num) {
-                    if let Some(location) = context.get_uniform_location(program, &info.name) {
-                        uniforms.insert(
-                            info.name,
-                            UniformInfo {
-                                type_: info.utype,
-                                size: info.size,
-                                location,
-                            },
-                        );
-                    }
+            for (name, index) in self.uniform_blocks.borrow().iter() {
+                if let Some(location) = context.get_uniform_block_index(program, name) {
+                    context.uniform_block_binding(program, location, *index);
                 }
             }
+
+            context.delete_program(self.program);
         }
 
-        Ok(Program {
-            context: Rc::clone(context),
-            program,
-            attributes,
-            uniforms,
-        })
+        self.program = program;
+        self.attributes = attributes;
+        self.uniforms = uniforms;
+
+        check_error(context, "Program::reload");
+
+        Ok(())
     }
 
     pub fn use_program(&self) -> () {
         unsafe {
             self.context.use_program(Some(self.program));
         }
+        check_error(&self.context, "Program::use_program");
     }
 
     pub fn set_uniforms(&self, uniforms: &[&Uniform]) {
@@ -544,6 +568,12 @@ impl Program {
                     context.uniform_1_f32(self.get_uniform_location(&uniform.name).as_ref(), value)
                 }
 
+                UniformValue::IVec2(value) => context.uniform_2_i32(
+                    self.get_uniform_location(&uniform.name).as_ref(),
+                    value[0],
+                    value[1],
+                ),
+
                 UniformValue::Vec2(value) => context.uniform_2_f32(
                     self.get_uniform_location(&uniform.name).as_ref(),
                     value[0],
@@ -557,6 +587,14 @@ impl Program {
                     value[2],
                 ),
 
+                UniformValue::Vec4(value) => context.uniform_4_f32(
+                    self.get_uniform_location(&uniform.name).as_ref(),
+                    value[0],
+                    value[1],
+                    value[2],
+                    value[3],
+                ),
+
                 UniformValue::Vec3Array(ref value) => context
                     .uniform_3_f32_slice(self.get_uniform_location(&uniform.name).as_ref(), &value),
 
@@ -577,15 +615,22 @@ impl Program {
                 }
             }
         }
+        check_error(context, "Program::set_uniform");
     }
 
     pub fn set_uniform_block(&self, name: &str, index: u32) -> () {
+        #[cfg(feature = "hot-reload")]
+        self.uniform_blocks
+            .borrow_mut()
+            .insert(name.to_string(), index);
+
         if let Some(location) = self.get_uniform_block_location(name) {
             unsafe {
                 self.context
                     .uniform_block_binding(self.program, location, index);
             }
         }
+        check_error(&self.context, "Program::set_uniform_block");
         // TODO return an error here?
     }
 
@@ -639,8 +684,10 @@ pub enum UniformValue<'a> {
     SignedInt(i32),
     UnsignedInt(u32),
     Float(f32),
+    IVec2(&'a [i32; 2]),
     Vec2(&'a [f32; 2]),
     Vec3(&'a [f32; 3]),
+    Vec4(&'a [f32; 4]),
     // TODO: use nalgebra types here
     Vec3Array(&'a [f32]),
     Vec4Array(&'a [f32]),
@@ -648,6 +695,162 @@ pub enum UniformValue<'a> {
     Texture2D(u32),
 }
 
+fn link_program(
+    context: &Context,
+    shaders: (&str, &str),
+    transform_feedback: Option<(&[&str], u32)>,
+    attribute_locations: &FxHashMap<String, u32>,
+) -> Result<glow::Program> {
+    let vertex_shader = compile_shader(&context, glow::VERTEX_SHADER, shaders.0)?;
+    let fragment_shader = compile_shader(&context, glow::FRAGMENT_SHADER, shaders.1)?;
+
+    unsafe {
+        let program = context
+            .create_program()
+            .map_err(|_| Problem::CannotCreateProgram)?;
+        context.attach_shader(program, vertex_shader);
+        context.attach_shader(program, fragment_shader);
+
+        if let Some((names, mode)) = transform_feedback {
+            context.transform_feedback_varyings(program, names, mode);
+        }
+
+        for (name, location) in attribute_locations.iter() {
+            context.bind_attrib_location(program, *location, name);
+        }
+
+        context.link_program(program);
+
+        // Delete the shaders to free up memory
+        context.detach_shader(program, vertex_shader);
+        context.detach_shader(program, fragment_shader);
+        context.delete_shader(vertex_shader);
+        context.delete_shader(fragment_shader);
+
+        if !context.get_program_link_status(program) {
+            let log = context.get_program_info_log(program);
+            context.delete_program(program);
+            return Err(Problem::CannotLinkProgram(log));
+        }
+
+        Ok(program)
+    }
+}
+
+unsafe fn get_active_variables(
+    context: &Context,
+    program: glow::Program,
+) -> (
+    FxHashMap<String, AttributeInfo>,
+    FxHashMap<String, UniformInfo>,
+) {
+    // Get attribute locations
+    let mut attributes = FxHashMap::default();
+    let attribute_count = context.get_active_attributes(program);
+    for num in 0..attribute_count {
+        if let Some(info) = context.get_active_attribute(program, num) {
+            if let Some(location) = context.get_attrib_location(program, &info.name) {
+                attributes.insert(
+                    info.name,
+                    AttributeInfo {
+                        type_: info.atype,
+                        size: info.size as u32,
+                        location: location,
+                    },
+                );
+            }
+        }
+    }
+
+    // Get uniform locations
+    let mut uniforms = FxHashMap::default();
+    let uniform_count = context.get_active_uniforms(program);
+    for num in 0..uniform_count {
+        if let Some(info) = context.get_active_uniform(program, num) {
+            if let Some(location) = context.get_uniform_location(program, &info.name) {
+                uniforms.insert(
+                    info.name,
+                    UniformInfo {
+                        type_: info.utype,
+                        size: info.size,
+                        location,
+                    },
+                );
+            }
+        }
+    }
+
+    (attributes, uniforms)
+}
+
+// Copy a uniform’s value from one program to another. The new program must be
+// in use. Arrays are copied element by element.
+#[cfg(feature = "hot-reload")]
+unsafe fn copy_uniform(
+    context: &Context,
+    (from, to): (glow::Program, glow::Program),
+    name: &str,
+    info: &UniformInfo,
+) -> () {
+    let components = match info.type_ {
+        glow::FLOAT | glow::INT | glow::UNSIGNED_INT | glow::BOOL | glow::SAMPLER_2D => 1,
+        glow::FLOAT_VEC2 | glow::INT_VEC2 => 2,
+        glow::FLOAT_VEC3 => 3,
+        glow::FLOAT_VEC4 => 4,
+        glow::FLOAT_MAT4 => 16,
+        _ => return,
+    };
+    let base_name = name.trim_end_matches("[0]");
+
+    for element in 0..info.size {
+        let element_name = if info.size > 1 {
+            format!("{}[{}]", base_name, element)
+        } else {
+            name.to_string()
+        };
+        let (from_location, to_location) = match (
+            context.get_uniform_location(from, &element_name),
+            context.get_uniform_location(to, &element_name),
+        ) {
+            (Some(from_location), Some(to_location)) => (from_location, to_location),
+            _ => continue,
+        };
+
+        match info.type_ {
+            glow::FLOAT
+            | glow::FLOAT_VEC2
+            | glow::FLOAT_VEC3
+            | glow::FLOAT_VEC4
+            | glow::FLOAT_MAT4 => {
+                let mut value = [0.0; 16];
+                let value = &mut value[..components];
+                context.get_uniform_f32(from, &from_location, value);
+
+                match info.type_ {
+                    glow::FLOAT => context.uniform_1_f32_slice(Some(&to_location), value),
+                    glow::FLOAT_VEC2 => context.uniform_2_f32_slice(Some(&to_location), value),
+                    glow::FLOAT_VEC3 => context.uniform_3_f32_slice(Some(&to_location), value),
+                    glow::FLOAT_VEC4 => context.uniform_4_f32_slice(Some(&to_location), value),
+                    _ => context.uniform_matrix_4_f32_slice(Some(&to_location), false, value),
+                }
+            }
+            _ => {
+                let mut value = [0; 2];
+                let value = &mut value[..components];
+                context.get_uniform_i32(from, &from_location, value);
+
+                match info.type_ {
+                    glow::INT_VEC2 => context.uniform_2_i32_slice(Some(&to_location), value),
+                    glow::UNSIGNED_INT => {
+                        context.uniform_1_u32(Some(&to_location), value[0] as u32)
+                    }
+                    _ => context.uniform_1_i32_slice(Some(&to_location), value),
+                }
+            }
+        }
+    }
+}
+
 pub fn compile_shader(context: &Context, shader_type: u32, source: &str) -> Result<glow::Shader> {
     unsafe {
         let shader = context
@@ -659,9 +862,10 @@ pub fn compile_shader(context: &Context, shader_type: u32, source: &str) -> Resu
         if context.get_shader_compile_status(shader) {
             Ok(shader)
         } else {
-            Err(Problem::CannotCreateShader(Some(
-                context.get_shader_info_log(shader),
-            )))
+            Err(Problem::CannotCreateShader(Some(preprocessor::map_log(
+                source,
+                &context.get_shader_info_log(shader),
+            ))))
         }
     }
 }
@@ -681,52 +885,91 @@ pub struct MsaaPass {
     width: u32,
     height: u32,
     samples: u32,
+    // The internal format of the renderbuffer
+    format: u32,
     framebuffer: glow::Framebuffer,
     renderbuffer: glow::Renderbuffer,
 }
 
 impl MsaaPass {
-    pub fn new(context: &Context, width: u32, height: u32, requested_samples: u32) -> Result<Self> {
-        let (framebuffer, renderbuffer, samples) = unsafe {
+    pub fn new(
+        context: &Context,
+        width: u32,
+        height: u32,
+        requested_samples: u32,
+        format: u32,
+    ) -> Result<Self> {
+        let (framebuffer, renderbuffer) = unsafe {
             let framebuffer = context
                 .create_framebuffer()
                 .map_err(|_| Problem::CannotCreateFramebuffer)?;
             let renderbuffer = context
                 .create_renderbuffer()
                 .map_err(|_| Problem::CannotCreateRenderbuffer)?;
-            context.bind_framebuffer(glow::FRAMEBUFFER, Some(framebuffer));
-            context.bind_renderbuffer(glow::RENDERBUFFER, Some(renderbuffer));
 
-            let max_samples = context.get_parameter_i32(glow::MAX_SAMPLES) as u32;
-            let samples = u32::min(requested_samples, max_samples);
+            (framebuffer, renderbuffer)
+        };
+
+        let mut msaa_pass = MsaaPass {
+            context: Rc::clone(context),
+            width,
+            height,
+            samples: 0,
+            format,
+            framebuffer,
+            renderbuffer,
+        };
+        msaa_pass.set_samples(requested_samples)?;
+
+        Ok(msaa_pass)
+    }
 
-            context.renderbuffer_storage_multisample(
+    pub fn max_samples(context: &Context) -> u32 {
+        unsafe { context.get_parameter_i32(glow::MAX_SAMPLES) as u32 }
+    }
+
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    // Recreate the renderbuffer with a new sample count. The count is clamped
+    // to `MAX_SAMPLES`; returns the number of samples actually used.
+    pub fn set_samples(&mut self, requested_samples: u32) -> Result<u32> {
+        let samples = u32::min(requested_samples, Self::max_samples(&self.context));
+
+        unsafe {
+            let renderbuffer = self
+                .context
+                .create_renderbuffer()
+                .map_err(|_| Problem::CannotCreateRenderbuffer)?;
+            self.context
+                .bind_framebuffer(glow::FRAMEBUFFER, Some(self.framebuffer));
+            self.context
+                .bind_renderbuffer(glow::RENDERBUFFER, Some(renderbuffer));
+
+            self.context.renderbuffer_storage_multisample(
                 glow::RENDERBUFFER,
                 samples as i32,
-                glow::RGBA8,
-                width as i32,
-                height as i32,
+                self.format,
+                self.width as i32,
+                self.height as i32,
             );
-            context.framebuffer_renderbuffer(
+            self.context.framebuffer_renderbuffer(
                 glow::FRAMEBUFFER,
                 glow::COLOR_ATTACHMENT0,
                 glow::RENDERBUFFER,
                 Some(renderbuffer),
             );
-            context.bind_framebuffer(glow::FRAMEBUFFER, None);
-            context.bind_renderbuffer(glow::RENDERBUFFER, None);
+            self.context.bind_framebuffer(glow::FRAMEBUFFER, None);
+            self.context.bind_renderbuffer(glow::RENDERBUFFER, None);
 
-            (framebuffer, renderbuffer, samples)
-        };
+            self.context.delete_renderbuffer(self.renderbuffer);
+            self.renderbuffer = renderbuffer;
+        }
 
-        Ok(MsaaPass {
-            context: Rc::clone(context),
-            width,
-            height,
-            samples,
-            framebuffer,
-            renderbuffer,
-        })
+        self.samples = samples;
+
+        Ok(samples)
     }
 
     pub fn resize(&mut self, width: u32, height: u32) -> () {
@@ -739,7 +982,7 @@ impl MsaaPass {
             self.context.renderbuffer_storage_multisample(
                 glow::RENDERBUFFER,
                 self.samples as i32,
-                glow::RGBA8,
+                self.format,
                 width as i32,
                 height as i32,
             );
@@ -751,9 +994,6 @@ impl MsaaPass {
     where
         T: Fn() -> (),
     {
-        let width = self.width as i32;
-        let height = self.height as i32;
-
         unsafe {
             self.context
                 .bind_framebuffer(glow::DRAW_FRAMEBUFFER, Some(self.framebuffer));
@@ -762,8 +1002,28 @@ impl MsaaPass {
             draw_call();
 
             self.context.bind_framebuffer(glow::DRAW_FRAMEBUFFER, None);
+        }
+    }
+
+    // Blit what was drawn with `draw_to` onto the default framebuffer.
+    pub fn resolve(&self) -> () {
+        self.resolve_to(None);
+    }
+
+    // Blit what was drawn with `draw_to` onto `target`, which should be the
+    // same size.
+    pub fn resolve_into(&self, target: &Framebuffer) -> () {
+        self.resolve_to(Some(target.id));
+    }
 
+    fn resolve_to(&self, target: Option<glow::Framebuffer>) -> () {
+        let width = self.width as i32;
+        let height = self.height as i32;
+
+        unsafe {
             self.context.disable(glow::BLEND);
+            self.context
+                .bind_framebuffer(glow::DRAW_FRAMEBUFFER, target);
             self.context
                 .bind_framebuffer(glow::READ_FRAMEBUFFER, Some(self.framebuffer));
             self.context.blit_framebuffer(
@@ -779,6 +1039,7 @@ impl MsaaPass {
                 glow::LINEAR,
             );
             self.context.bind_framebuffer(glow::READ_FRAMEBUFFER, None);
+            self.context.bind_framebuffer(glow::DRAW_FRAMEBUFFER, None);
         }
     }
 }
@@ -817,6 +1078,24 @@ fn detect_texture_format(internal_format: GlDataType) -> Result<TextureFormat> {
             type_: glow::FLOAT,
             size: 4,
         }),
+        glow::RGBA16F => Ok(TextureFormat {
+            internal_format,
+            format: glow::RGBA,
+            type_: glow::FLOAT,
+            size: 4,
+        }),
+        glow::R8 => Ok(TextureFormat {
+            internal_format,
+            format: glow::RED,
+            type_: glow::UNSIGNED_BYTE,
+            size: 1,
+        }),
+        glow::RGBA8 => Ok(TextureFormat {
+            internal_format,
+            format: glow::RGBA,
+            type_: glow::UNSIGNED_BYTE,
+            size: 4,
+        }),
         _ => Err(Problem::UnsupportedTextureFormat),
     }
 }
@@ -833,6 +1112,7 @@ impl VertexArrayObject {
                 .create_vertex_array()
                 .map_err(|_| Problem::OutOfMemory)?
         };
+        check_error(context, "VertexArrayObject::empty");
 
         Ok(Self {
             id,
@@ -859,6 +1139,7 @@ impl VertexArrayObject {
 
             context.bind_vertex_array(None);
         }
+        check_error(context, "VertexArrayObject::new");
 
         Ok(vao)
     }
@@ -883,6 +1164,7 @@ impl VertexArrayObject {
 
             self.context.bind_vertex_array(None);
         }
+        check_error(&self.context, "VertexArrayObject::update");
 
         Ok(())
     }
@@ -926,3 +1208,406 @@ pub fn bind_attributes(
 
     Ok(())
 }
+
+// Debugging
+//
+// With the `debug` feature, the wrappers above check for GL errors after each
+// call, desktop drivers report problems through KHR_debug, and the calls of
+// each pass are grouped so that they show up in tools like RenderDoc. Without
+// it, all of this compiles to nothing.
+
+#[cfg(feature = "debug")]
+thread_local! {
+    static LOG_SINK: RefCell<Box<dyn Fn(&str)>> =
+        RefCell::new(Box::new(|message| eprintln!("{}", message)));
+}
+
+// Send the debug output somewhere other than stderr, like the browser console.
+#[cfg(feature = "debug")]
+pub fn set_log_sink(sink: impl Fn(&str) + 'static) -> () {
+    LOG_SINK.with(|log_sink| *log_sink.borrow_mut() = Box::new(sink));
+}
+
+#[cfg(feature = "debug")]
+fn log(message: &str) -> () {
+    LOG_SINK.with(|log_sink| (log_sink.borrow())(message));
+}
+
+// Forward the driver’s debug messages to the log sink. WebGL doesn’t support
+// KHR_debug, so this only does something on desktop.
+pub fn enable_debug_output(context: &Context) -> () {
+    #[cfg(feature = "debug")]
+    unsafe {
+        if !context.supports_debug() {
+            return;
+        }
+
+        context.enable(glow::DEBUG_OUTPUT);
+        // Report the messages on the thread that made the call, so that they
+        // line up with the error checks.
+        context.enable(glow::DEBUG_OUTPUT_SYNCHRONOUS);
+        // glow only keeps a pointer to the callback for the duration of this
+        // call, so it has to be a plain function without any state.
+        context.debug_message_callback(log_debug_message);
+    }
+
+    #[cfg(not(feature = "debug"))]
+    let _ = context;
+}
+
+#[cfg(feature = "debug")]
+fn log_debug_message(_source: u32, type_: u32, _id: u32, severity: u32, message: &str) -> () {
+    let severity = match severity {
+        glow::DEBUG_SEVERITY_HIGH => "high",
+        glow::DEBUG_SEVERITY_MEDIUM => "medium",
+        glow::DEBUG_SEVERITY_LOW => "low",
+        // Mostly buffer usage hints
+        _ => return,
+    };
+    let type_ = match type_ {
+        glow::DEBUG_TYPE_ERROR => "error",
+        glow::DEBUG_TYPE_DEPRECATED_BEHAVIOR => "deprecated behaviour",
+        glow::DEBUG_TYPE_UNDEFINED_BEHAVIOR => "undefined behaviour",
+        glow::DEBUG_TYPE_PERFORMANCE => "performance",
+        glow::DEBUG_TYPE_PORTABILITY => "portability",
+        _ => "message",
+    };
+
+    log(&format!(
+        "GL {} ({} severity): {}",
+        type_, severity, message
+    ));
+}
+
+// Log any GL errors raised since the last check. `operation` says where the
+// check was made.
+#[inline]
+fn check_error(context: &Context, operation: &str) -> () {
+    #[cfg(feature = "debug")]
+    loop {
+        let error = unsafe { context.get_error() };
+        let name = match error {
+            glow::NO_ERROR => break,
+            glow::INVALID_ENUM => "INVALID_ENUM",
+            glow::INVALID_VALUE => "INVALID_VALUE",
+            glow::INVALID_OPERATION => "INVALID_OPERATION",
+            glow::INVALID_FRAMEBUFFER_OPERATION => "INVALID_FRAMEBUFFER_OPERATION",
+            glow::OUT_OF_MEMORY => "OUT_OF_MEMORY",
+            // A lost context keeps returning this
+            glow::CONTEXT_LOST => {
+                log(&format!("GL CONTEXT_LOST in {}", operation));
+                break;
+            }
+            _ => "unknown error",
+        };
+        log(&format!("GL {} in {}", name, operation));
+    }
+
+    #[cfg(not(feature = "debug"))]
+    let _ = (context, operation);
+}
+
+// Groups the GL calls made while it’s alive under `name`, like "fluid/advect",
+// in debugging tools.
+pub struct DebugGroup {
+    #[cfg(feature = "debug")]
+    context: Option<Context>,
+}
+
+impl DebugGroup {
+    pub fn new(context: &Context, name: &str) -> Self {
+        #[cfg(feature = "debug")]
+        {
+            if !context.supports_debug() {
+                return Self { context: None };
+            }
+
+            unsafe {
+                context.push_debug_group(glow::DEBUG_SOURCE_APPLICATION, 0, name);
+            }
+            Self {
+                context: Some(Rc::clone(context)),
+            }
+        }
+
+        #[cfg(not(feature = "debug"))]
+        {
+            let _ = (context, name);
+            Self {}
+        }
+    }
+}
+
+#[cfg(feature = "debug")]
+impl Drop for DebugGroup {
+    fn drop(&mut self) {
+        if let Some(context) = &self.context {
+            unsafe {
+                context.pop_debug_group();
+            }
+        }
+    }
+}
+
+// Profiling
+//
+// Each pass can be timed on the GPU with a timer query, to find out whether
+// it’s the fluid solver or the line drawing that holds a device back. The
+// results come back a few frames later, so `end_frame` collects whatever is
+// ready without waiting on the GPU. WebGL needs
+// EXT_disjoint_timer_query_webgl2, which isn’t available everywhere.
+
+// The number of frames to average the timings over
+const PROFILER_WINDOW: usize = 60;
+// Stop starting queries if the results stop coming back, like after the
+// context is lost.
+const MAX_PENDING_QUERIES: usize = 256;
+// From EXT_disjoint_timer_query. glow doesn’t define it.
+const GPU_DISJOINT_EXT: u32 = 0x8FBB;
+
+#[derive(Clone, Copy, Debug)]
+pub struct GpuTiming {
+    pub pass: &'static str,
+    // The average time spent in the pass per frame
+    pub milliseconds: f32,
+}
+
+// Shared by everything that draws, so that all the passes in a frame are
+// timed together.
+#[derive(Clone)]
+pub struct Profiler {
+    state: Rc<RefCell<ProfilerState>>,
+}
+
+struct ProfilerState {
+    context: Context,
+    is_supported: bool,
+    // The GPU can report that the timings are meaningless, like after
+    // changing clocks.
+    can_be_disjoint: bool,
+    is_enabled: bool,
+    // Timer queries can’t be nested, so only the outermost pass is timed. The
+    // passes inside it, like the lines drawn into the trails, are counted as
+    // part of it.
+    is_timing: bool,
+    frame: u64,
+    pending: VecDeque<(glow::Query, &'static str, u64)>,
+    free_queries: Vec<glow::Query>,
+    // The frame whose results are being collected, with the time in
+    // nanoseconds for each pass.
+    resolving: Option<(u64, Vec<(&'static str, u64)>)>,
+    history: VecDeque<Vec<(&'static str, u64)>>,
+    // In the order they were first timed
+    passes: Vec<&'static str>,
+}
+
+impl Profiler {
+    pub fn new(context: &Context) -> Self {
+        let version = context.version();
+        let extensions = context.supported_extensions();
+        let can_be_disjoint = extensions.contains("EXT_disjoint_timer_query_webgl2")
+            || extensions.contains("GL_EXT_disjoint_timer_query");
+        let is_supported = can_be_disjoint
+            || (!version.is_embedded && (version.major, version.minor) >= (3, 3))
+            || extensions.contains("GL_ARB_timer_query");
+
+        Self {
+            state: Rc::new(RefCell::new(ProfilerState {
+                context: Rc::clone(context),
+                is_supported,
+                can_be_disjoint,
+                is_enabled: false,
+                is_timing: false,
+                frame: 0,
+                pending: VecDeque::new(),
+                free_queries: Vec::new(),
+                resolving: None,
+                history: VecDeque::with_capacity(PROFILER_WINDOW),
+                passes: Vec::new(),
+            })),
+        }
+    }
+
+    pub fn is_supported(&self) -> bool {
+        self.state.borrow().is_supported
+    }
+
+    // The timings start over whenever this is called.
+    pub fn set_enabled(&self, is_enabled: bool) -> () {
+        let mut state = self.state.borrow_mut();
+        state.is_enabled = is_enabled && state.is_supported;
+        state.resolving = None;
+        state.history.clear();
+        state.passes.clear();
+    }
+
+    // Time everything drawn while the pass is alive under `name`, like
+    // "fluid/advect". This also groups the GL calls in debugging tools.
+    pub fn pass(&self, name: &'static str) -> Pass {
+        let mut state = self.state.borrow_mut();
+        let group = DebugGroup::new(&state.context, name);
+
+        if !state.is_enabled || state.is_timing || state.pending.len() >= MAX_PENDING_QUERIES {
+            return Pass {
+                _group: group,
+                timer: None,
+            };
+        }
+
+        let query = match state.free_queries.pop() {
+            Some(query) => query,
+            None => match unsafe { state.context.create_query() } {
+                Ok(query) => query,
+                Err(_) => {
+                    return Pass {
+                        _group: group,
+                        timer: None,
+                    }
+                }
+            },
+        };
+
+        unsafe {
+            state.context.begin_query(glow::TIME_ELAPSED, query);
+        }
+        state.is_timing = true;
+
+        Pass {
+            _group: group,
+            timer: Some((self.clone(), query, name)),
+        }
+    }
+
+    // Collect the results that are ready. Call this once at the end of every
+    // frame.
+    pub fn end_frame(&self) -> () {
+        let mut state = self.state.borrow_mut();
+        let context = Rc::clone(&state.context);
+
+        let is_disjoint = state.can_be_disjoint
+            && !state.pending.is_empty()
+            && unsafe { context.get_parameter_i32(GPU_DISJOINT_EXT) } != 0;
+        if is_disjoint {
+            let queries: Vec<glow::Query> =
+                state.pending.drain(..).map(|(query, ..)| query).collect();
+            state.free_queries.extend(queries);
+            state.resolving = None;
+        }
+
+        while let Some(&(query, name, frame)) = state.pending.front() {
+            let is_available =
+                unsafe { context.get_query_parameter_u32(query, glow::QUERY_RESULT_AVAILABLE) }
+                    != 0;
+            if !is_available {
+                break;
+            }
+
+            let nanoseconds = unsafe { context.get_query_parameter_u32(query, glow::QUERY_RESULT) };
+            state.pending.pop_front();
+            state.free_queries.push(query);
+
+            if !matches!(state.resolving, Some((resolving_frame, _)) if resolving_frame == frame) {
+                state.finish_frame();
+                state.resolving = Some((frame, Vec::new()));
+            }
+            if let Some((_, timings)) = &mut state.resolving {
+                timings.push((name, u64::from(nanoseconds)));
+            }
+        }
+
+        // A frame is complete once there’s nothing left to wait for from it.
+        let is_complete = match (&state.resolving, state.pending.front()) {
+            (Some((resolving_frame, _)), Some(&(_, _, frame))) => frame > *resolving_frame,
+            _ => true,
+        };
+        if is_complete {
+            state.finish_frame();
+        }
+
+        state.frame += 1;
+    }
+
+    pub fn timings(&self) -> Vec<GpuTiming> {
+        let state = self.state.borrow();
+        let frame_count = state.history.len().max(1) as f32;
+
+        state
+            .passes
+            .iter()
+            .map(|&pass| {
+                let nanoseconds: u64 = state
+                    .history
+                    .iter()
+                    .flat_map(|timings| timings.iter())
+                    .filter(|(other, _)| *other == pass)
+                    .map(|(_, nanoseconds)| nanoseconds)
+                    .sum();
+
+                GpuTiming {
+                    pass,
+                    milliseconds: nanoseconds as f32 / frame_count / 1_000_000.0,
+                }
+            })
+            .collect()
+    }
+}
+
+impl ProfilerState {
+    // Add up the passes that ran more than once in the frame, like the fluid
+    // steps, and add the frame to the history.
+    fn finish_frame(&mut self) -> () {
+        let (_, timings) = match self.resolving.take() {
+            Some(resolving) => resolving,
+            None => return,
+        };
+
+        let mut totals: Vec<(&'static str, u64)> = Vec::new();
+        for (name, nanoseconds) in timings {
+            match totals.iter_mut().find(|(other, _)| *other == name) {
+                Some((_, total)) => *total += nanoseconds,
+                None => totals.push((name, nanoseconds)),
+            }
+            if !self.passes.contains(&name) {
+                self.passes.push(name);
+            }
+        }
+
+        if self.history.len() == PROFILER_WINDOW {
+            self.history.pop_front();
+        }
+        self.history.push_back(totals);
+    }
+}
+
+impl Drop for ProfilerState {
+    fn drop(&mut self) {
+        unsafe {
+            for query in self.free_queries.drain(..) {
+                self.context.delete_query(query);
+            }
+            for (query, ..) in self.pending.drain(..) {
+                self.context.delete_query(query);
+            }
+        }
+    }
+}
+
+pub struct Pass {
+    // Dropped after the query ends, so that the group covers it.
+    _group: DebugGroup,
+    timer: Option<(Profiler, glow::Query, &'static str)>,
+}
+
+impl Drop for Pass {
+    fn drop(&mut self) {
+        if let Some((profiler, query, name)) = self.timer.take() {
+            let mut state = profiler.state.borrow_mut();
+            unsafe {
+                state.context.end_query(glow::TIME_ELAPSED);
+            }
+            state.is_timing = false;
+            let frame = state.frame;
+            state.pending.push_back((query, name, frame));
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Settings {
     pub viscosity: f32,
@@ -8,30 +8,149 @@ pub struct Settings {
     pub fluid_width: u32,
     pub fluid_height: u32,
     pub fluid_simulation_frame_rate: f32,
+    // The lines are animated in fixed steps at this rate, independent of the
+    // rate at which frames are drawn.
+    pub line_simulation_frame_rate: f32,
     pub diffusion_iterations: u32,
     pub pressure_iterations: u32,
 
     pub color_scheme: ColorScheme,
+    pub background: Background,
+    pub render_mode: RenderMode,
+    pub antialiasing: Antialiasing,
 
     pub line_length: f32,
     pub line_width: f32,
     pub line_begin_offset: f32,
     pub line_fade_out_length: f32,
+    // The length at which lines become fully opaque. Lines fade in between
+    // `line_fade_out_length` and this length.
+    pub line_opaque_length: f32,
+    // The range of line widths, relative to `line_width`
+    pub line_min_width: f32,
+    pub line_max_width: f32,
+    // The furthest an endpoint can stretch, relative to `line_length`. Set to
+    // 0 for no limit.
+    pub line_max_length: f32,
+    // How quickly lines take on a new color, per second
+    pub line_color_response: f32,
+    pub line_style: LineStyle,
+    // How the lines and endpoints are blended onto the background
+    pub blend_mode: BlendMode,
+    pub endpoint_shape: EndpointShape,
+
+    // How strongly the mask set with `Flux::set_mask` fades out the lines,
+    // from 0 to 1.
+    pub mask_opacity: f32,
+    // How strongly the mask limits the length of the lines, from 0 to 1.
+    pub mask_line_length: f32,
+    // The number of segments used to trace `LineStyle::Streamline`
+    pub streamline_segments: u32,
+
+    // How quickly the trails left behind by the lines fade away, per second.
+    // Set to 0 to disable trails.
+    pub trail_decay: f32,
+    pub trail_blend_mode: TrailBlendMode,
+
+    // How strongly the bright parts of the frame glow. Set to 0 to disable
+    // bloom.
+    pub bloom_intensity: f32,
+    // The brightness above which colors start to glow
+    pub bloom_threshold: f32,
+    // Scales the spread of the blur at each level
+    pub bloom_radius: f32,
+
+    pub particle_count: u32,
+    // In seconds. Each particle lives for 50–150% of this.
+    pub particle_lifetime: f32,
+    pub particle_speed: f32,
+    pub particle_size: f32,
+    // How far back in time to stretch each particle along its path, in
+    // seconds. Set to 0 to draw round points.
+    pub particle_streak_length: f32,
 
     pub spring_stiffness: f32,
     pub spring_variance: f32,
     pub spring_mass: f32,
     pub spring_rest_length: f32,
+    // Slows down the endpoints. Set to 0 for undamped springs.
+    pub spring_damping: f32,
+    // How strongly each endpoint is pulled towards the endpoints of its
+    // neighbors. Set to 0 to let the lines move independently.
+    pub spring_coupling_stiffness: f32,
 
     pub advection_direction: f32,
     pub adjust_advection: f32,
     pub grid_spacing: u32,
     pub view_scale: f32,
+    // Scales the fluid velocity that moves the lines
+    pub velocity_scale: f32,
+    // Shifts where the lines sample the fluid, as a fraction of the canvas
+    pub velocity_offset: [f32; 2],
+    pub camera: Camera,
+    // Lift the endpoints off the canvas in proportion to the speed of the
+    // fluid, as a multiple of the line length. Only visible with a
+    // perspective camera.
+    pub endpoint_height: f32,
+
+    // Extra grids of lines, drawn behind the main one from back to front
+    pub layers: Vec<LineLayer>,
 
     pub noise_channel_1: Noise,
     pub noise_channel_2: Noise,
 }
 
+// A grid of lines driven by the same fluid as the main grid. Everything not
+// set here is shared with the main grid.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LineLayer {
+    pub grid_spacing: u32,
+    pub line_length: f32,
+    pub line_width: f32,
+    pub color_scheme: ColorScheme,
+    pub view_scale: f32,
+    // Slower layers feel further away.
+    pub velocity_scale: f32,
+    pub velocity_offset: [f32; 2],
+}
+
+// The springs become unstable with fewer steps per second, and each step is a
+// full pass over the lines, so much faster rates would cost more than the
+// lines gain.
+const MIN_LINE_SIMULATION_FRAME_RATE: f32 = 30.0;
+const MAX_LINE_SIMULATION_FRAME_RATE: f32 = 240.0;
+
+impl Settings {
+    // The fixed timestep of the line simulation, in seconds. The rate is
+    // clamped to 30–240 steps per second.
+    pub fn line_frame_time(&self) -> f32 {
+        1.0 / self
+            .line_simulation_frame_rate
+            .max(MIN_LINE_SIMULATION_FRAME_RATE)
+            .min(MAX_LINE_SIMULATION_FRAME_RATE)
+    }
+
+    // The settings for drawing one of the extra layers. Antialiasing and
+    // trails are left to the main grid, which draws the layers into its own
+    // frame.
+    pub fn for_layer(&self, layer: &LineLayer) -> Self {
+        Self {
+            grid_spacing: layer.grid_spacing,
+            line_length: layer.line_length,
+            line_width: layer.line_width,
+            color_scheme: layer.color_scheme.clone(),
+            view_scale: layer.view_scale,
+            velocity_scale: layer.velocity_scale,
+            velocity_offset: layer.velocity_offset,
+            antialiasing: Antialiasing::Off,
+            trail_decay: 0.0,
+            layers: Vec::new(),
+            ..self.clone()
+        }
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub enum ColorScheme {
     Plasma,
@@ -40,6 +159,107 @@ pub enum ColorScheme {
     Pollen,
 }
 
+// Colors are straight (not premultiplied) RGBA.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum Background {
+    Solid([f32; 4]),
+    // The angle is in degrees, measured anticlockwise from the positive x-axis.
+    LinearGradient {
+        from: [f32; 4],
+        to: [f32; 4],
+        angle: f32,
+    },
+    RadialGradient {
+        inner: [f32; 4],
+        outer: [f32; 4],
+    },
+    // Uses the image passed to `Flux::set_background_image`.
+    Image,
+    Transparent,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum RenderMode {
+    // Lines anchored to a grid of basepoints
+    Lines,
+    // Particles advected freely through the fluid
+    Particles,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub enum Antialiasing {
+    Off,
+    Msaa2,
+    Msaa4,
+    Msaa8,
+    // A post-processing pass for contexts without multisampled renderbuffers
+    Fxaa,
+}
+
+impl Antialiasing {
+    pub fn samples(&self) -> u32 {
+        match self {
+            Antialiasing::Msaa2 => 2,
+            Antialiasing::Msaa4 => 4,
+            Antialiasing::Msaa8 => 8,
+            Antialiasing::Off | Antialiasing::Fxaa => 0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum LineStyle {
+    // Straight lines from the basepoint to the endpoint
+    Straight,
+    // Curved lines that follow the velocity field
+    Streamline,
+}
+
+// Angles are in degrees.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub enum Camera {
+    Orthographic,
+    // Look at the canvas as a plane receding into the distance. The tilt
+    // leans the top of the canvas away from the camera. At a distance of 1
+    // and no tilt, the canvas exactly fills the view.
+    #[serde(rename_all = "camelCase")]
+    Perspective {
+        field_of_view: f32,
+        tilt: f32,
+        distance: f32,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub enum EndpointShape {
+    Circle { resolution: u32 },
+    Square,
+    Diamond,
+    // A square textured with the image passed to `Flux::set_endpoint_sprite`
+    Sprite,
+    // Don’t draw endpoints at all
+    Hidden,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub enum BlendMode {
+    // Overlapping lines add up, which works best on dark backgrounds.
+    Additive,
+    // Lines are painted over each other
+    AlphaOver,
+    Screen,
+    // Darkens the background. Works best on light backgrounds.
+    Multiply,
+    // Keeps the brightest of the overlapping colors
+    Max,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum TrailBlendMode {
+    Additive,
+    Lighten,
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub enum BlendMethod {
     Curl,
@@ -60,6 +280,86 @@ pub struct Noise {
     pub blend_method: BlendMethod,
 }
 
+// Plain settings for the tests to override with struct update syntax
+#[cfg(test)]
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            viscosity: 1.0,
+            velocity_dissipation: 0.0,
+            fluid_width: 128,
+            fluid_height: 128,
+            fluid_simulation_frame_rate: 20.0,
+            line_simulation_frame_rate: 120.0,
+            diffusion_iterations: 30,
+            pressure_iterations: 50,
+            color_scheme: ColorScheme::Plasma,
+            background: Background::Solid([0.0, 0.0, 0.0, 1.0]),
+            render_mode: RenderMode::Lines,
+            antialiasing: Antialiasing::Off,
+            line_length: 160.0,
+            line_width: 5.0,
+            line_begin_offset: 0.0,
+            line_fade_out_length: 0.05,
+            line_opaque_length: 0.7,
+            line_min_width: 0.15,
+            line_max_width: 1.0,
+            line_max_length: 0.0,
+            line_color_response: 1.0,
+            line_style: LineStyle::Straight,
+            blend_mode: BlendMode::Additive,
+            endpoint_shape: EndpointShape::Hidden,
+            mask_opacity: 1.0,
+            mask_line_length: 0.0,
+            streamline_segments: 8,
+            trail_decay: 0.0,
+            trail_blend_mode: TrailBlendMode::Additive,
+            bloom_intensity: 0.0,
+            bloom_threshold: 0.8,
+            bloom_radius: 1.0,
+            particle_count: 10000,
+            particle_lifetime: 4.0,
+            particle_speed: 30.0,
+            particle_size: 3.0,
+            particle_streak_length: 0.1,
+            spring_stiffness: 0.3,
+            spring_variance: 0.25,
+            spring_mass: 2.0,
+            spring_rest_length: 0.0,
+            spring_damping: 0.0,
+            spring_coupling_stiffness: 0.0,
+            advection_direction: 1.0,
+            adjust_advection: 16.0,
+            grid_spacing: 18,
+            view_scale: 1.0,
+            velocity_scale: 1.0,
+            velocity_offset: [0.0, 0.0],
+            camera: Camera::Orthographic,
+            endpoint_height: 0.0,
+            layers: Vec::new(),
+            noise_channel_1: Noise::default(),
+            noise_channel_2: Noise::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+impl Default for Noise {
+    fn default() -> Self {
+        Noise {
+            scale: 1.0,
+            multiplier: 0.0,
+            offset_1: 0.0,
+            offset_2: 0.0,
+            offset_increment: 0.0,
+            delay: 0.0,
+            blend_duration: 0.0,
+            blend_threshold: 0.0,
+            blend_method: BlendMethod::Curl,
+        }
+    }
+}
+
 pub fn color_wheel_from_scheme(color_scheme: &ColorScheme) -> [f32; 24] {
     match color_scheme {
         ColorScheme::Plasma => COLOR_SCHEME_PLASMA,
@@ -0,0 +1,265 @@
+extern crate nalgebra_glm as glm;
+
+use crate::drawer::Line;
+use crate::settings::{Background, BlendMode, Camera, Settings};
+use crate::view::VirtualCanvas;
+
+use std::io::{self, Write};
+
+// The lines of one grid, along with the view they’re drawn with
+pub struct Grid {
+    pub view: glm::TMat4<f32>,
+    pub lines: Vec<Line>,
+}
+
+// Write the lines as an SVG document. The document uses the same logical
+// pixels as the canvas, so it can be scaled to any resolution. The lines are
+// written flat, so only the orthographic camera is supported.
+pub fn write_frame<W: Write>(
+    writer: &mut W,
+    canvas: &VirtualCanvas,
+    settings: &Settings,
+    grids: &[Grid],
+) -> io::Result<()> {
+    if let Camera::Perspective { .. } = settings.camera {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "cannot export a frame drawn with a perspective camera",
+        ));
+    }
+
+    let (width, height) = (canvas.width as f32, canvas.height as f32);
+    let (left, top) = (-width / 2.0, -height / 2.0);
+    let viewport = canvas.viewport;
+
+    writeln!(
+        writer,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="{} {} {} {}">"#,
+        viewport.width,
+        viewport.height,
+        left + viewport.x as f32,
+        top + viewport.y as f32,
+        viewport.width,
+        viewport.height,
+    )?;
+    writeln!(
+        writer,
+        "<style>line {{ mix-blend-mode: {}; }}</style>",
+        to_css_blend_mode(&settings.blend_mode)
+    )?;
+
+    write_background(writer, &settings.background, width, height)?;
+
+    for grid in grids.iter() {
+        write_grid(writer, grid, settings)?;
+    }
+
+    writeln!(writer, "</svg>")
+}
+
+fn write_grid<W: Write>(writer: &mut W, grid: &Grid, settings: &Settings) -> io::Result<()> {
+    // Lines are in world space: y points up and the view is applied on top.
+    writeln!(
+        writer,
+        r#"<g transform="scale(1 -1) matrix({} {} {} {} {} {})" stroke-linecap="round">"#,
+        grid.view[(0, 0)],
+        grid.view[(1, 0)],
+        grid.view[(0, 1)],
+        grid.view[(1, 1)],
+        grid.view[(0, 3)],
+        grid.view[(1, 3)],
+    )?;
+
+    // The lines fade in from `line_begin_offset`. Start them halfway into the
+    // fade.
+    let begin = (1.0 + settings.line_begin_offset) / 2.0;
+
+    for line in grid.lines.iter() {
+        // Skip the lines that are too faint to see
+        if line.opacity < 0.001 || line.width <= 0.0 {
+            continue;
+        }
+
+        let [x1, y1] = line.basepoint;
+        let [x2, y2] = line.endpoint;
+
+        writeln!(
+            writer,
+            r#"<line x1="{:.2}" y1="{:.2}" x2="{:.2}" y2="{:.2}" stroke="{}" stroke-width="{:.2}" stroke-opacity="{:.3}"/>"#,
+            x1 + (x2 - x1) * begin,
+            y1 + (y2 - y1) * begin,
+            x2,
+            y2,
+            to_hex(&line.color),
+            line.width,
+            line.opacity.min(1.0),
+        )?;
+    }
+
+    writeln!(writer, "</g>")
+}
+
+fn write_background<W: Write>(
+    writer: &mut W,
+    background: &Background,
+    width: f32,
+    height: f32,
+) -> io::Result<()> {
+    let fill = match background {
+        Background::Solid(color) => {
+            format!(r#"fill="{}" fill-opacity="{}""#, to_hex(color), color[3])
+        }
+
+        // Match background.frag: the gradient spans the canvas corner to
+        // corner.
+        Background::LinearGradient { from, to, angle } => {
+            let (dx, dy) = (angle.to_radians().cos(), angle.to_radians().sin());
+            let extent = 0.5 * (width * dx.abs() + height * dy.abs());
+
+            writeln!(
+                writer,
+                r#"<defs><linearGradient id="background" gradientUnits="userSpaceOnUse" x1="{}" y1="{}" x2="{}" y2="{}">"#,
+                -dx * extent,
+                dy * extent,
+                dx * extent,
+                -dy * extent,
+            )?;
+            write_stop(writer, 0.0, from)?;
+            write_stop(writer, 1.0, to)?;
+            writeln!(writer, "</linearGradient></defs>")?;
+
+            r#"fill="url(#background)""#.to_string()
+        }
+
+        Background::RadialGradient { inner, outer } => {
+            writeln!(
+                writer,
+                r#"<defs><radialGradient id="background" gradientUnits="userSpaceOnUse" cx="0" cy="0" r="{}">"#,
+                (0.5 * width).hypot(0.5 * height),
+            )?;
+            write_stop(writer, 0.0, inner)?;
+            write_stop(writer, 1.0, outer)?;
+            writeln!(writer, "</radialGradient></defs>")?;
+
+            r#"fill="url(#background)""#.to_string()
+        }
+
+        // The image lives on the GPU, so there’s nothing to export.
+        Background::Image | Background::Transparent => return Ok(()),
+    };
+
+    writeln!(
+        writer,
+        r#"<rect x="{}" y="{}" width="{}" height="{}" {}/>"#,
+        -width / 2.0,
+        -height / 2.0,
+        width,
+        height,
+        fill,
+    )
+}
+
+fn write_stop<W: Write>(writer: &mut W, offset: f32, color: &[f32; 4]) -> io::Result<()> {
+    writeln!(
+        writer,
+        r#"<stop offset="{}" stop-color="{}" stop-opacity="{}"/>"#,
+        offset,
+        to_hex(color),
+        color[3],
+    )
+}
+
+// The CSS blend mode closest to how the renderer blends the lines
+fn to_css_blend_mode(blend_mode: &BlendMode) -> &'static str {
+    match blend_mode {
+        BlendMode::Additive => "plus-lighter",
+        BlendMode::AlphaOver => "normal",
+        BlendMode::Screen => "screen",
+        BlendMode::Multiply => "multiply",
+        BlendMode::Max => "lighten",
+    }
+}
+
+fn to_hex(color: &[f32]) -> String {
+    let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+
+    format!(
+        "#{:02x}{:02x}{:02x}",
+        channel(color[0]),
+        channel(color[1]),
+        channel(color[2]),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::*;
+
+    fn grid() -> Grid {
+        Grid {
+            view: glm::identity(),
+            lines: vec![
+                Line {
+                    basepoint: [-10.0, 0.0],
+                    endpoint: [30.0, 20.0],
+                    color: [1.0, 0.5, 0.0],
+                    width: 4.0,
+                    opacity: 0.75,
+                },
+                // Too faint to see
+                Line {
+                    basepoint: [10.0, 10.0],
+                    endpoint: [20.0, 20.0],
+                    color: [1.0, 1.0, 1.0],
+                    width: 4.0,
+                    opacity: 0.0,
+                },
+            ],
+        }
+    }
+
+    fn write(settings: &Settings) -> io::Result<String> {
+        let mut document = Vec::new();
+        write_frame(
+            &mut document,
+            &VirtualCanvas::single(200, 100),
+            settings,
+            &[grid()],
+        )?;
+
+        Ok(String::from_utf8(document).unwrap())
+    }
+
+    #[test]
+    fn writes_the_lines() {
+        assert_eq!(
+            write(&Settings::default()).unwrap(),
+            r##"<svg xmlns="http://www.w3.org/2000/svg" width="200" height="100" viewBox="-100 -50 200 100">
+<style>line { mix-blend-mode: plus-lighter; }</style>
+<rect x="-100" y="-50" width="200" height="100" fill="#000000" fill-opacity="1"/>
+<g transform="scale(1 -1) matrix(1 0 0 1 0 0)" stroke-linecap="round">
+<line x1="10.00" y1="10.00" x2="30.00" y2="20.00" stroke="#ff8000" stroke-width="4.00" stroke-opacity="0.750"/>
+</g>
+</svg>
+"##
+        );
+    }
+
+    #[test]
+    fn rejects_a_perspective_camera() {
+        let settings = Settings {
+            camera: Camera::Perspective {
+                field_of_view: 60.0,
+                tilt: 0.0,
+                distance: 1.0,
+            },
+            ..Settings::default()
+        };
+
+        assert_eq!(
+            write(&settings).unwrap_err().kind(),
+            io::ErrorKind::Unsupported
+        );
+    }
+}
@@ -0,0 +1,226 @@
+extern crate nalgebra_glm as glm;
+
+// A camera over the canvas. The translation is in logical pixels, y up, and the
+// rotation is in radians, anticlockwise. The scale is applied on top of
+// `Settings::view_scale`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform2D {
+    pub translation: [f32; 2],
+    pub scale: f32,
+    pub rotation: f32,
+}
+
+impl Default for Transform2D {
+    fn default() -> Self {
+        Self {
+            translation: [0.0, 0.0],
+            scale: 1.0,
+            rotation: 0.0,
+        }
+    }
+}
+
+// Anything smaller collapses the view to a point.
+const MIN_SCALE: f32 = 0.001;
+
+impl Transform2D {
+    // Replace a zero, negative or NaN scale, which would collapse the view and
+    // can’t be interpolated.
+    pub fn with_valid_scale(self) -> Self {
+        Self {
+            scale: self.scale.max(MIN_SCALE),
+            ..self
+        }
+    }
+
+    // Scale is interpolated geometrically, so that zooming feels even.
+    pub fn lerp(&self, to: &Self, t: f32) -> Self {
+        let mix = |from: f32, to: f32| from + (to - from) * t;
+
+        Self {
+            translation: [
+                mix(self.translation[0], to.translation[0]),
+                mix(self.translation[1], to.translation[1]),
+            ],
+            scale: mix(self.scale.ln(), to.scale.ln()).exp(),
+            rotation: mix(self.rotation, to.rotation),
+        }
+    }
+
+    pub fn to_matrix(&self, view_scale: f32, pixel_ratio: f64) -> glm::TMat4<f32> {
+        let scale = self.scale * view_scale;
+        let translation = glm::vec3(
+            (f64::from(self.translation[0]) * pixel_ratio) as f32,
+            (f64::from(self.translation[1]) * pixel_ratio) as f32,
+            0.0,
+        );
+
+        let matrix = glm::translate(&glm::identity(), &translation);
+        let matrix = glm::rotate_z(&matrix, self.rotation);
+        glm::scale(&matrix, &glm::vec3(scale, scale, 1.0))
+    }
+}
+
+// A canvas spread across several displays, such as a video wall. Each node
+// simulates the fluid over the whole canvas, but only draws the part of it
+// inside its viewport. The viewport should match the size of the node’s own
+// canvas.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VirtualCanvas {
+    pub width: u32,
+    pub height: u32,
+    pub viewport: Viewport,
+}
+
+// In logical pixels, from the top-left corner of the virtual canvas.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Viewport {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl VirtualCanvas {
+    // A canvas that fits entirely on one display
+    pub fn single(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            viewport: Viewport {
+                x: 0,
+                y: 0,
+                width,
+                height,
+            },
+        }
+    }
+}
+
+// An eased transition from one view to another.
+pub struct ViewTransition {
+    from: Transform2D,
+    to: Transform2D,
+    // In seconds
+    duration: f32,
+    elapsed_time: f32,
+}
+
+impl ViewTransition {
+    pub fn new(from: Transform2D, to: Transform2D, duration: f32) -> Self {
+        Self {
+            from,
+            to,
+            duration,
+            elapsed_time: 0.0,
+        }
+    }
+
+    pub fn advance(&mut self, timestep: f32) -> Transform2D {
+        self.elapsed_time += timestep;
+
+        if self.is_finished() {
+            return self.to;
+        }
+
+        let t = self.elapsed_time / self.duration;
+        let eased = t * t * (3.0 - 2.0 * t);
+        self.from.lerp(&self.to, eased)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.elapsed_time >= self.duration
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    fn assert_close(actual: f32, expected: f32) -> () {
+        assert!(
+            (actual - expected).abs() < 1e-5,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    fn view(x: f32, y: f32, scale: f32, rotation: f32) -> Transform2D {
+        Transform2D {
+            translation: [x, y],
+            scale,
+            rotation,
+        }
+    }
+
+    #[test]
+    fn lerps_between_the_ends() {
+        let from = view(-10.0, 20.0, 0.5, 0.0);
+        let to = view(30.0, -40.0, 2.0, PI);
+
+        assert_eq!(from.lerp(&to, 0.0), from);
+
+        let end = from.lerp(&to, 1.0);
+        assert_close(end.translation[0], 30.0);
+        assert_close(end.translation[1], -40.0);
+        assert_close(end.scale, 2.0);
+        assert_close(end.rotation, PI);
+    }
+
+    #[test]
+    fn lerps_the_scale_geometrically() {
+        let from = view(0.0, 0.0, 1.0, 0.0);
+        let to = view(100.0, 50.0, 4.0, PI);
+
+        let middle = from.lerp(&to, 0.5);
+
+        assert_close(middle.translation[0], 50.0);
+        assert_close(middle.translation[1], 25.0);
+        assert_close(middle.scale, 2.0);
+        assert_close(middle.rotation, PI / 2.0);
+    }
+
+    #[test]
+    fn eases_in_and_out() {
+        let from = view(0.0, 0.0, 1.0, 0.0);
+        let to = view(100.0, 0.0, 1.0, PI);
+        let mut transition = ViewTransition::new(from, to, 2.0);
+
+        // A quarter of the way through, smoothstep gives 0.15625.
+        let view = transition.advance(0.5);
+        assert_close(view.translation[0], 15.625);
+        assert_close(view.rotation, 0.15625 * PI);
+        assert!(!transition.is_finished());
+
+        // Halfway through, it’s halfway there.
+        let view = transition.advance(0.5);
+        assert_close(view.translation[0], 50.0);
+        assert_close(view.rotation, PI / 2.0);
+        assert!(!transition.is_finished());
+    }
+
+    #[test]
+    fn finishes_at_the_target() {
+        let from = view(0.0, 0.0, 1.0, 0.0);
+        let to = view(100.0, -100.0, 3.0, 1.0);
+        let mut transition = ViewTransition::new(from, to, 1.0);
+
+        transition.advance(0.75);
+        assert!(!transition.is_finished());
+
+        assert_eq!(transition.advance(0.5), to);
+        assert!(transition.is_finished());
+    }
+
+    #[test]
+    fn jumps_to_the_target_without_a_duration() {
+        let from = view(0.0, 0.0, 1.0, 0.0);
+        let to = view(10.0, 10.0, 2.0, 1.0);
+        let mut transition = ViewTransition::new(from, to, 0.0);
+
+        assert_eq!(transition.advance(0.0), to);
+        assert!(transition.is_finished());
+    }
+}
@@ -0,0 +1,593 @@
+use super::{new_bind_group, new_compute_pipeline, new_shader_module};
+use crate::drawer::{
+    grid_margin, new_basepoints, new_camera_projection_matrix, new_endpoint, new_projection_matrix,
+    Grid, LINE_VERTICES,
+};
+use crate::settings::{self, Background, BlendMode, Settings};
+use crate::view::{Transform2D, VirtualCanvas};
+
+extern crate nalgebra_glm as glm;
+use bytemuck::{Pod, Zeroable};
+use std::rc::Rc;
+use wgpu::util::DeviceExt;
+
+static PLACE_LINES_SHADER: &'static str = include_str!("../../shaders/wgsl/place_lines.wgsl");
+static LINE_SHADER: &'static str = include_str!("../../shaders/wgsl/line.wgsl");
+
+// The OpenGL clip space has a depth range of -1 to 1. wgpu uses 0 to 1.
+#[rustfmt::skip]
+const OPENGL_TO_WGPU_MATRIX: [f32; 16] = [
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, 0.5, 0.0,
+    0.0, 0.0, 0.5, 1.0,
+];
+
+// Laid out like a struct in a WGSL storage buffer, which is aligned to 16 bytes.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct LineState {
+    endpoint: [f32; 2],
+    velocity: [f32; 2],
+    color: [f32; 4],
+    width: f32,
+    opacity: f32,
+    _padding: [f32; 2],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct PlaceLinesUniforms {
+    projection: [f32; 16],
+    color_wheel: [f32; 24],
+    velocity_offset: [f32; 2],
+    grid_size: [i32; 2],
+    fluid_size: [i32; 2],
+    timestep: f32,
+    spring_stiffness: f32,
+    spring_variance: f32,
+    spring_mass: f32,
+    spring_rest_length: f32,
+    spring_damping: f32,
+    spring_coupling_stiffness: f32,
+    line_fade_out_length: f32,
+    line_opaque_length: f32,
+    line_min_width: f32,
+    line_max_width: f32,
+    line_max_length: f32,
+    color_response: f32,
+    adjust_advection: f32,
+    advection_direction: f32,
+    velocity_scale: f32,
+    _padding: [f32; 2],
+}
+
+// Byte offset of `timestep` in `PlaceLinesUniforms`
+const TIMESTEP_OFFSET: wgpu::BufferAddress = (16 + 24 + 6) * 4;
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct DrawUniforms {
+    projection: [f32; 16],
+    view: [f32; 16],
+    line_width: f32,
+    line_length: f32,
+    line_begin_offset: f32,
+    line_fade_out_length: f32,
+    endpoint_height: f32,
+    _padding: [f32; 3],
+}
+
+pub struct Drawer {
+    device: Rc<wgpu::Device>,
+    queue: Rc<wgpu::Queue>,
+    settings: Rc<Settings>,
+    format: wgpu::TextureFormat,
+
+    pixel_ratio: f64,
+    grid: Grid,
+    view: Transform2D,
+
+    basepoint_buffer: wgpu::Buffer,
+    // Double buffered. The current one is at `line_state_index`.
+    line_state_buffers: [wgpu::Buffer; 2],
+    line_state_index: usize,
+    line_vertices: wgpu::Buffer,
+    endpoint_vertices: wgpu::Buffer,
+    endpoint_vertex_count: u32,
+
+    place_lines_uniforms: wgpu::Buffer,
+    draw_uniforms: wgpu::Buffer,
+    draw_bind_group: wgpu::BindGroup,
+
+    place_lines_pass: wgpu::ComputePipeline,
+    // Kept around to rebuild the draw pipelines when the blend mode changes
+    line_module: wgpu::ShaderModule,
+    draw_pipeline_layout: wgpu::PipelineLayout,
+    draw_lines_pass: wgpu::RenderPipeline,
+    draw_endpoints_pass: wgpu::RenderPipeline,
+}
+
+impl Drawer {
+    pub fn new(
+        device: &Rc<wgpu::Device>,
+        queue: &Rc<wgpu::Queue>,
+        format: wgpu::TextureFormat,
+        logical_width: u32,
+        logical_height: u32,
+        pixel_ratio: f64,
+        settings: &Rc<Settings>,
+    ) -> Self {
+        let grid = Grid::new(
+            VirtualCanvas::single(logical_width, logical_height),
+            settings.grid_spacing,
+            grid_margin(settings),
+        );
+        let (basepoint_buffer, line_state_buffers) = new_line_buffers(device, &grid, pixel_ratio);
+
+        let line_vertices = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Line vertices"),
+            contents: bytemuck::cast_slice(&LINE_VERTICES),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let (endpoint_vertices, endpoint_vertex_count) = new_endpoint_buffer(device, settings);
+
+        let place_lines_uniforms = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Place lines uniforms"),
+            size: std::mem::size_of::<PlaceLinesUniforms>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let draw_uniforms = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Draw uniforms"),
+            size: std::mem::size_of::<DrawUniforms>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let place_lines_module = new_shader_module(device, "Place lines", PLACE_LINES_SHADER);
+        let place_lines_pass = new_compute_pipeline(device, &place_lines_module, "main");
+
+        let line_module = new_shader_module(device, "Lines", LINE_SHADER);
+        let draw_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Draw uniforms"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            });
+        let draw_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Draw uniforms"),
+            layout: &draw_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: draw_uniforms.as_entire_binding(),
+            }],
+        });
+        let draw_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Draw lines"),
+            bind_group_layouts: &[&draw_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let drawer = Self {
+            device: Rc::clone(device),
+            queue: Rc::clone(queue),
+            settings: Rc::clone(settings),
+            format,
+
+            pixel_ratio,
+            grid,
+            view: Transform2D::default(),
+
+            basepoint_buffer,
+            line_state_buffers,
+            line_state_index: 0,
+            line_vertices,
+            endpoint_vertices,
+            endpoint_vertex_count,
+
+            place_lines_uniforms,
+            draw_uniforms,
+            draw_bind_group,
+
+            place_lines_pass,
+            draw_lines_pass: new_draw_pipeline(
+                device,
+                &draw_pipeline_layout,
+                &line_module,
+                ("lineVertex", "lineFragment"),
+                format,
+                settings.blend_mode,
+            ),
+            draw_endpoints_pass: new_draw_pipeline(
+                device,
+                &draw_pipeline_layout,
+                &line_module,
+                ("endpointVertex", "endpointFragment"),
+                format,
+                settings.blend_mode,
+            ),
+            line_module,
+            draw_pipeline_layout,
+        };
+
+        drawer.update_uniforms();
+        drawer
+    }
+
+    pub fn update(&mut self, settings: &Rc<Settings>) -> () {
+        let blend_mode_changed = self.settings.blend_mode != settings.blend_mode;
+        self.settings = Rc::clone(settings);
+
+        let (endpoint_vertices, endpoint_vertex_count) =
+            new_endpoint_buffer(&self.device, &self.settings);
+        self.endpoint_vertices = endpoint_vertices;
+        self.endpoint_vertex_count = endpoint_vertex_count;
+
+        // The blend state is baked into the pipelines
+        if blend_mode_changed {
+            self.draw_lines_pass = new_draw_pipeline(
+                &self.device,
+                &self.draw_pipeline_layout,
+                &self.line_module,
+                ("lineVertex", "lineFragment"),
+                self.format,
+                self.settings.blend_mode,
+            );
+            self.draw_endpoints_pass = new_draw_pipeline(
+                &self.device,
+                &self.draw_pipeline_layout,
+                &self.line_module,
+                ("endpointVertex", "endpointFragment"),
+                self.format,
+                self.settings.blend_mode,
+            );
+        }
+
+        self.update_uniforms();
+    }
+
+    // The lines start over on the new grid.
+    pub fn resize(&mut self, logical_width: u32, logical_height: u32) -> () {
+        self.grid = Grid::new(
+            VirtualCanvas::single(logical_width, logical_height),
+            self.settings.grid_spacing,
+            grid_margin(&self.settings),
+        );
+
+        let (basepoint_buffer, line_state_buffers) =
+            new_line_buffers(&self.device, &self.grid, self.pixel_ratio);
+        self.basepoint_buffer = basepoint_buffer;
+        self.line_state_buffers = line_state_buffers;
+        self.line_state_index = 0;
+
+        self.update_uniforms();
+    }
+
+    pub fn set_view(&mut self, view: Transform2D) -> () {
+        self.view = view;
+        self.update_uniforms();
+    }
+
+    fn update_uniforms(&self) -> () {
+        let settings = &self.settings;
+        let canvas = self.grid.canvas;
+
+        let fluid_projection = new_projection_matrix(
+            (f64::from(canvas.width) * self.pixel_ratio) as u32,
+            (f64::from(canvas.height) * self.pixel_ratio) as u32,
+        );
+        let place_lines_uniforms = PlaceLinesUniforms {
+            projection: to_array(&fluid_projection),
+            color_wheel: settings::color_wheel_from_scheme(&settings.color_scheme),
+            velocity_offset: settings.velocity_offset,
+            grid_size: [self.grid.columns as i32, self.grid.rows as i32],
+            fluid_size: [settings.fluid_width as i32, settings.fluid_height as i32],
+            timestep: 0.0,
+            spring_stiffness: settings.spring_stiffness,
+            spring_variance: settings.spring_variance,
+            spring_mass: settings.spring_mass,
+            spring_rest_length: settings.spring_rest_length,
+            spring_damping: settings.spring_damping,
+            spring_coupling_stiffness: settings.spring_coupling_stiffness,
+            line_fade_out_length: settings.line_fade_out_length,
+            line_opaque_length: settings.line_opaque_length,
+            line_min_width: settings.line_min_width,
+            line_max_width: settings.line_max_width,
+            line_max_length: settings.line_max_length,
+            color_response: settings.line_color_response,
+            adjust_advection: settings.adjust_advection,
+            advection_direction: settings.advection_direction,
+            velocity_scale: settings.velocity_scale,
+            _padding: [0.0; 2],
+        };
+
+        let projection = glm::make_mat4(&OPENGL_TO_WGPU_MATRIX)
+            * new_camera_projection_matrix(&canvas, self.pixel_ratio, settings.camera);
+        let view = self.view.to_matrix(settings.view_scale, self.pixel_ratio);
+        let draw_uniforms = DrawUniforms {
+            projection: to_array(&projection),
+            view: to_array(&view),
+            line_width: (f64::from(settings.line_width) * self.pixel_ratio) as f32,
+            line_length: (f64::from(settings.line_length) * self.pixel_ratio) as f32,
+            line_begin_offset: settings.line_begin_offset,
+            line_fade_out_length: settings.line_fade_out_length,
+            endpoint_height: settings.endpoint_height,
+            _padding: [0.0; 3],
+        };
+
+        self.queue.write_buffer(
+            &self.place_lines_uniforms,
+            0,
+            bytemuck::bytes_of(&place_lines_uniforms),
+        );
+        self.queue
+            .write_buffer(&self.draw_uniforms, 0, bytemuck::bytes_of(&draw_uniforms));
+    }
+
+    pub fn prepare_lines(&self, timestep: f32) -> () {
+        self.queue.write_buffer(
+            &self.place_lines_uniforms,
+            TIMESTEP_OFFSET,
+            bytemuck::bytes_of(&timestep),
+        );
+    }
+
+    pub fn place_lines(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        velocity_buffer: &wgpu::Buffer,
+    ) -> () {
+        const WORKGROUP_SIZE: u32 = 64;
+
+        let line_count = self.grid.line_count();
+        if line_count == 0 {
+            return;
+        }
+
+        let bind_group = new_bind_group(
+            &self.device,
+            &self.place_lines_pass,
+            &[
+                (0, &self.place_lines_uniforms),
+                (1, &self.basepoint_buffer),
+                (2, velocity_buffer),
+                (3, &self.line_state_buffers[self.line_state_index]),
+                (4, &self.line_state_buffers[1 - self.line_state_index]),
+            ],
+        );
+
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Place lines"),
+            });
+            pass.set_pipeline(&self.place_lines_pass);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch((line_count + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE, 1, 1);
+        }
+
+        self.line_state_index = 1 - self.line_state_index;
+    }
+
+    // Clear the target to the background and draw the lines and endpoints.
+    pub fn draw(&self, encoder: &mut wgpu::CommandEncoder, target: &wgpu::TextureView) -> () {
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Draw lines"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(background_color(&self.settings.background)),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+
+        let line_count = self.grid.line_count();
+        if line_count == 0 {
+            return;
+        }
+
+        let line_state = &self.line_state_buffers[self.line_state_index];
+        pass.set_bind_group(0, &self.draw_bind_group, &[]);
+        pass.set_vertex_buffer(1, self.basepoint_buffer.slice(..));
+        pass.set_vertex_buffer(2, line_state.slice(..));
+
+        pass.set_pipeline(&self.draw_lines_pass);
+        pass.set_vertex_buffer(0, self.line_vertices.slice(..));
+        pass.draw(0..6, 0..line_count);
+
+        if self.endpoint_vertex_count > 0 {
+            pass.set_pipeline(&self.draw_endpoints_pass);
+            pass.set_vertex_buffer(0, self.endpoint_vertices.slice(..));
+            pass.draw(0..self.endpoint_vertex_count, 0..line_count);
+        }
+    }
+}
+
+fn new_line_buffers(
+    device: &wgpu::Device,
+    grid: &Grid,
+    pixel_ratio: f64,
+) -> (wgpu::Buffer, [wgpu::Buffer; 2]) {
+    let basepoint_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Basepoints"),
+        contents: bytemuck::cast_slice(&new_basepoints(grid, pixel_ratio)),
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE,
+    });
+
+    // Buffers can’t be empty
+    let line_state = vec![
+        LineState {
+            endpoint: [0.0, 0.0],
+            velocity: [0.0, 0.0],
+            color: [0.0, 0.0, 0.0, 0.0],
+            width: 0.1,
+            opacity: 0.0,
+            _padding: [0.0; 2],
+        };
+        grid.line_count().max(1) as usize
+    ];
+    let new_line_state_buffer = || {
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Line state"),
+            contents: bytemuck::cast_slice(&line_state),
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE,
+        })
+    };
+
+    (
+        basepoint_buffer,
+        [new_line_state_buffer(), new_line_state_buffer()],
+    )
+}
+
+// wgpu has no triangle fans, so the endpoint is split into a triangle list.
+fn new_endpoint_buffer(device: &wgpu::Device, settings: &Settings) -> (wgpu::Buffer, u32) {
+    let fan = new_endpoint(settings.endpoint_shape);
+    let vertex_count = fan.len() / 2;
+
+    let mut vertices = Vec::with_capacity(3 * 2 * vertex_count);
+    for vertex in 1..vertex_count.saturating_sub(1) {
+        for index in [0, vertex, vertex + 1] {
+            vertices.extend_from_slice(&fan[2 * index..2 * index + 2]);
+        }
+    }
+
+    let count = (vertices.len() / 2) as u32;
+    if vertices.is_empty() {
+        // Buffers can’t be empty
+        vertices.extend_from_slice(&[0.0, 0.0]);
+    }
+
+    let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Endpoint vertices"),
+        contents: bytemuck::cast_slice(&vertices),
+        usage: wgpu::BufferUsages::VERTEX,
+    });
+
+    (buffer, count)
+}
+
+fn new_draw_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    module: &wgpu::ShaderModule,
+    (vertex_entry_point, fragment_entry_point): (&str, &str),
+    format: wgpu::TextureFormat,
+    blend_mode: BlendMode,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(vertex_entry_point),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module,
+            entry_point: vertex_entry_point,
+            buffers: &[
+                wgpu::VertexBufferLayout {
+                    array_stride: 2 * 4,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &wgpu::vertex_attr_array![0 => Float32x2],
+                },
+                wgpu::VertexBufferLayout {
+                    array_stride: 2 * 4,
+                    step_mode: wgpu::VertexStepMode::Instance,
+                    attributes: &wgpu::vertex_attr_array![1 => Float32x2],
+                },
+                wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<LineState>() as wgpu::BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Instance,
+                    attributes: &wgpu::vertex_attr_array![
+                        2 => Float32x2,
+                        3 => Float32x2,
+                        4 => Float32x4,
+                        5 => Float32,
+                        6 => Float32,
+                    ],
+                },
+            ],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module,
+            entry_point: fragment_entry_point,
+            targets: &[wgpu::ColorTargetState {
+                format,
+                blend: Some(to_blend_state(blend_mode)),
+                write_mask: wgpu::ColorWrites::ALL,
+            }],
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    })
+}
+
+// Same as `drawer::enable_blend_mode`
+fn to_blend_state(blend_mode: BlendMode) -> wgpu::BlendState {
+    use wgpu::{BlendFactor, BlendOperation};
+
+    let (src_factor, dst_factor, operation) = match blend_mode {
+        BlendMode::Additive => (BlendFactor::One, BlendFactor::One, BlendOperation::Add),
+        BlendMode::AlphaOver => (
+            BlendFactor::One,
+            BlendFactor::OneMinusSrcAlpha,
+            BlendOperation::Add,
+        ),
+        BlendMode::Screen => (
+            BlendFactor::One,
+            BlendFactor::OneMinusSrc,
+            BlendOperation::Add,
+        ),
+        // Assumes an opaque background
+        BlendMode::Multiply => (
+            BlendFactor::Dst,
+            BlendFactor::OneMinusSrcAlpha,
+            BlendOperation::Add,
+        ),
+        BlendMode::Max => (BlendFactor::One, BlendFactor::One, BlendOperation::Max),
+    };
+
+    wgpu::BlendState {
+        color: wgpu::BlendComponent {
+            src_factor,
+            dst_factor,
+            operation,
+        },
+        alpha: wgpu::BlendComponent {
+            src_factor: BlendFactor::One,
+            dst_factor: BlendFactor::OneMinusSrcAlpha,
+            operation: BlendOperation::Add,
+        },
+    }
+}
+
+// Gradients and images are rejected by `check_settings`.
+fn background_color(background: &Background) -> wgpu::Color {
+    let [r, g, b, a] = match background {
+        Background::Solid(color) => *color,
+        _ => [0.0; 4],
+    };
+    let a = f64::from(a);
+
+    // Premultiplied
+    wgpu::Color {
+        r: f64::from(r) * a,
+        g: f64::from(g) * a,
+        b: f64::from(b) * a,
+        a,
+    }
+}
+
+fn to_array(matrix: &glm::TMat4<f32>) -> [f32; 16] {
+    matrix.as_slice().try_into().unwrap()
+}
@@ -0,0 +1,272 @@
+use super::{dispatch_cells, new_compute_pipeline, new_field_buffer, new_shader_module};
+use crate::settings::Settings;
+
+use bytemuck::{Pod, Zeroable};
+use std::rc::Rc;
+use wgpu::util::DeviceExt;
+
+static FLUID_SHADER: &'static str = include_str!("../../shaders/wgsl/fluid.wgsl");
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct Uniforms {
+    timestep: f32,
+    epsilon: f32,
+    half_epsilon: f32,
+    dissipation: f32,
+    texel_size: [f32; 2],
+    grid_size: [i32; 2],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct JacobiUniforms {
+    alpha: f32,
+    r_beta: f32,
+}
+
+pub struct Fluid {
+    device: Rc<wgpu::Device>,
+    queue: Rc<wgpu::Queue>,
+    settings: Rc<Settings>,
+
+    width: u32,
+    height: u32,
+    grid_size: f32,
+
+    uniform_buffer: wgpu::Buffer,
+    diffusion_uniform_buffer: wgpu::Buffer,
+    pressure_uniform_buffer: wgpu::Buffer,
+
+    // Double buffered. The current one is at `velocity_index`.
+    velocity_buffers: [wgpu::Buffer; 2],
+    velocity_index: usize,
+    divergence_buffer: wgpu::Buffer,
+    pressure_buffers: [wgpu::Buffer; 2],
+
+    advection_pass: wgpu::ComputePipeline,
+    jacobi_pass: wgpu::ComputePipeline,
+    divergence_pass: wgpu::ComputePipeline,
+    subtract_gradient_pass: wgpu::ComputePipeline,
+}
+
+impl Fluid {
+    pub fn new(
+        device: &Rc<wgpu::Device>,
+        queue: &Rc<wgpu::Queue>,
+        settings: &Rc<Settings>,
+    ) -> Self {
+        let grid_size: f32 = 1.0;
+        let width = settings.fluid_width;
+        let height = settings.fluid_height;
+
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Fluid uniforms"),
+            contents: bytemuck::bytes_of(&new_uniforms(settings, width, height, grid_size)),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let new_jacobi_buffer = |label| {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(label),
+                contents: bytemuck::bytes_of(&JacobiUniforms {
+                    alpha: 0.0,
+                    r_beta: 0.0,
+                }),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            })
+        };
+
+        let module = new_shader_module(device, "Fluid", FLUID_SHADER);
+
+        Self {
+            device: Rc::clone(device),
+            queue: Rc::clone(queue),
+            settings: Rc::clone(settings),
+
+            width,
+            height,
+            grid_size,
+
+            uniform_buffer,
+            diffusion_uniform_buffer: new_jacobi_buffer("Diffusion uniforms"),
+            pressure_uniform_buffer: new_jacobi_buffer("Pressure uniforms"),
+
+            velocity_buffers: [
+                new_field_buffer(device, "Velocity", width, height),
+                new_field_buffer(device, "Velocity", width, height),
+            ],
+            velocity_index: 0,
+            divergence_buffer: new_field_buffer(device, "Divergence", width, height),
+            pressure_buffers: [
+                new_field_buffer(device, "Pressure", width, height),
+                new_field_buffer(device, "Pressure", width, height),
+            ],
+
+            advection_pass: new_compute_pipeline(device, &module, "advect"),
+            jacobi_pass: new_compute_pipeline(device, &module, "solveJacobi"),
+            divergence_pass: new_compute_pipeline(device, &module, "divergence"),
+            subtract_gradient_pass: new_compute_pipeline(device, &module, "subtractGradient"),
+        }
+    }
+
+    pub fn update(&mut self, settings: &Rc<Settings>) -> () {
+        self.settings = Rc::clone(settings);
+
+        self.queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::bytes_of(&new_uniforms(
+                settings,
+                self.width,
+                self.height,
+                self.grid_size,
+            )),
+        );
+    }
+
+    pub fn prepare_pass(&self, timestep: f32) -> () {
+        self.queue
+            .write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&timestep));
+    }
+
+    pub fn advect(&mut self, encoder: &mut wgpu::CommandEncoder) -> () {
+        let (velocity, next_velocity) = self.velocity_pair();
+        dispatch_cells(
+            &self.device,
+            encoder,
+            &self.advection_pass,
+            &[(0, &self.uniform_buffer), (1, velocity), (3, next_velocity)],
+            self.width,
+            self.height,
+        );
+        self.swap_velocity();
+    }
+
+    pub fn diffuse(&mut self, encoder: &mut wgpu::CommandEncoder, timestep: f32) -> () {
+        let center_factor = self.grid_size.powf(2.0) / (self.settings.viscosity * timestep);
+        let stencil_factor = 1.0 / (4.0 + center_factor);
+
+        self.queue.write_buffer(
+            &self.diffusion_uniform_buffer,
+            0,
+            bytemuck::bytes_of(&JacobiUniforms {
+                alpha: center_factor,
+                r_beta: stencil_factor,
+            }),
+        );
+
+        for _ in 0..self.settings.diffusion_iterations {
+            let (velocity, next_velocity) = self.velocity_pair();
+            dispatch_cells(
+                &self.device,
+                encoder,
+                &self.jacobi_pass,
+                &[
+                    (0, &self.uniform_buffer),
+                    (1, velocity),
+                    (2, velocity),
+                    (3, next_velocity),
+                    (4, &self.diffusion_uniform_buffer),
+                ],
+                self.width,
+                self.height,
+            );
+            self.swap_velocity();
+        }
+    }
+
+    pub fn calculate_divergence(&self, encoder: &mut wgpu::CommandEncoder) -> () {
+        dispatch_cells(
+            &self.device,
+            encoder,
+            &self.divergence_pass,
+            &[
+                (0, &self.uniform_buffer),
+                (1, &self.velocity_buffers[self.velocity_index]),
+                (3, &self.divergence_buffer),
+            ],
+            self.width,
+            self.height,
+        );
+    }
+
+    pub fn solve_pressure(&self, encoder: &mut wgpu::CommandEncoder) -> () {
+        let alpha = -self.grid_size * self.grid_size;
+        let r_beta = 0.25;
+
+        self.queue.write_buffer(
+            &self.pressure_uniform_buffer,
+            0,
+            bytemuck::bytes_of(&JacobiUniforms { alpha, r_beta }),
+        );
+
+        encoder.clear_buffer(&self.pressure_buffers[0], 0, None);
+
+        for iteration in 0..self.settings.pressure_iterations as usize {
+            dispatch_cells(
+                &self.device,
+                encoder,
+                &self.jacobi_pass,
+                &[
+                    (0, &self.uniform_buffer),
+                    (1, &self.divergence_buffer),
+                    (2, &self.pressure_buffers[iteration % 2]),
+                    (3, &self.pressure_buffers[(iteration + 1) % 2]),
+                    (4, &self.pressure_uniform_buffer),
+                ],
+                self.width,
+                self.height,
+            );
+        }
+    }
+
+    pub fn subtract_gradient(&mut self, encoder: &mut wgpu::CommandEncoder) -> () {
+        let (velocity, next_velocity) = self.velocity_pair();
+        dispatch_cells(
+            &self.device,
+            encoder,
+            &self.subtract_gradient_pass,
+            &[
+                (0, &self.uniform_buffer),
+                (1, velocity),
+                (2, self.get_pressure()),
+                (3, next_velocity),
+            ],
+            self.width,
+            self.height,
+        );
+        self.swap_velocity();
+    }
+
+    pub fn get_velocity(&self) -> &wgpu::Buffer {
+        &self.velocity_buffers[self.velocity_index]
+    }
+
+    // The result of the last pressure solve
+    fn get_pressure(&self) -> &wgpu::Buffer {
+        &self.pressure_buffers[self.settings.pressure_iterations as usize % 2]
+    }
+
+    // The current velocity, and the buffer to write the next one to.
+    pub fn velocity_pair(&self) -> (&wgpu::Buffer, &wgpu::Buffer) {
+        (
+            &self.velocity_buffers[self.velocity_index],
+            &self.velocity_buffers[1 - self.velocity_index],
+        )
+    }
+
+    pub fn swap_velocity(&mut self) -> () {
+        self.velocity_index = 1 - self.velocity_index;
+    }
+}
+
+fn new_uniforms(settings: &Settings, width: u32, height: u32, grid_size: f32) -> Uniforms {
+    Uniforms {
+        timestep: 0.0,
+        epsilon: grid_size,
+        half_epsilon: 0.5 * grid_size,
+        dissipation: settings.velocity_dissipation,
+        texel_size: [1.0 / width as f32, 1.0 / height as f32],
+        grid_size: [width as i32, height as i32],
+    }
+}
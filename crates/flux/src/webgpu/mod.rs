@@ -0,0 +1,420 @@
+// An experimental wgpu renderer, working towards native Metal, Vulkan and DX12
+// builds and headless rendering on software rasterizers such as lavapipe. None
+// of the apps use it yet.
+//
+// The fluid and the lines are updated with compute shaders, and the fields
+// live in storage buffers instead of textures. Only the lines are drawn so far,
+// over a solid or transparent background, and there’s no way to set a mask.
+// Settings that ask for anything else are rejected with
+// `Problem::Unsupported`.
+mod drawer;
+mod fluid;
+mod noise;
+
+use crate::backend::Backend;
+use crate::settings::{Antialiasing, Background, EndpointShape, LineStyle, RenderMode, Settings};
+use crate::view::Transform2D;
+use drawer::Drawer;
+use fluid::Fluid;
+use noise::NoiseInjector;
+
+use std::rc::Rc;
+
+#[derive(Debug)]
+pub enum Problem {
+    // The settings ask for something this renderer can’t draw yet.
+    Unsupported(&'static str),
+}
+
+pub struct Flux {
+    device: Rc<wgpu::Device>,
+    queue: Rc<wgpu::Queue>,
+
+    fluid: Fluid,
+    drawer: Drawer,
+    noise_injector: NoiseInjector,
+    settings: Rc<Settings>,
+
+    elapsed_time: f32,
+    last_timestamp: f32,
+    frame_time: f32,
+    fluid_frame_time: f32,
+    line_time: f32,
+    line_frame_time: f32,
+    max_frame_time: f32,
+}
+
+impl Flux {
+    // `format` is the format of the textures passed to `animate`.
+    pub fn new(
+        device: &Rc<wgpu::Device>,
+        queue: &Rc<wgpu::Queue>,
+        format: wgpu::TextureFormat,
+        logical_width: u32,
+        logical_height: u32,
+        pixel_ratio: f64,
+        settings: &Rc<Settings>,
+    ) -> Result<Self, Problem> {
+        check_settings(settings)?;
+
+        let mut fluid = Fluid::new(device, queue, settings);
+        let drawer = Drawer::new(
+            device,
+            queue,
+            format,
+            logical_width,
+            logical_height,
+            pixel_ratio,
+            settings,
+        );
+
+        let mut noise_injector =
+            NoiseInjector::new(device, queue, settings.fluid_width, settings.fluid_height);
+        noise_injector.add_noise(settings.noise_channel_1.clone());
+        noise_injector.add_noise(settings.noise_channel_2.clone());
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Initial noise"),
+        });
+        noise_injector.generate_by_channel_number(&mut encoder, 0, 0.0);
+        noise_injector.blend_noise_into(&mut encoder, &mut fluid, 2000.0);
+        queue.submit(Some(encoder.finish()));
+
+        Ok(Self {
+            device: Rc::clone(device),
+            queue: Rc::clone(queue),
+
+            fluid,
+            drawer,
+            noise_injector,
+            settings: Rc::clone(settings),
+
+            elapsed_time: 0.0,
+            last_timestamp: 0.0,
+            frame_time: 0.0,
+            fluid_frame_time: 1.0 / settings.fluid_simulation_frame_rate,
+            line_time: 0.0,
+            line_frame_time: settings.line_frame_time(),
+            max_frame_time: 1.0 / 10.0,
+        })
+    }
+
+    // The settings are left as they were if any of them are unsupported.
+    pub fn update(&mut self, settings: &Rc<Settings>) -> Result<(), Problem> {
+        check_settings(settings)?;
+
+        self.settings = Rc::clone(settings);
+        self.line_frame_time = self.settings.line_frame_time();
+
+        self.fluid.update(&self.settings);
+        self.drawer.update(&self.settings);
+        self.noise_injector
+            .update_channel(0, &self.settings.noise_channel_1);
+        self.noise_injector
+            .update_channel(1, &self.settings.noise_channel_2);
+
+        Ok(())
+    }
+
+    pub fn resize(&mut self, logical_width: u32, logical_height: u32) -> () {
+        self.drawer.resize(logical_width, logical_height);
+    }
+
+    pub fn set_view(&mut self, view: Transform2D) -> () {
+        self.drawer.set_view(view.with_valid_scale());
+    }
+
+    pub fn animate(&mut self, timestamp: f32, target: &wgpu::TextureView) -> () {
+        let timestep = self
+            .max_frame_time
+            .min(0.001 * (timestamp - self.last_timestamp));
+        self.last_timestamp = timestamp;
+        self.elapsed_time += timestep;
+        self.frame_time += timestep;
+
+        // The uniforms are written as each step is recorded, so every step
+        // needs its own submission.
+        while self.frame_time >= self.fluid_frame_time {
+            let mut encoder = self
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Fluid step"),
+                });
+
+            self.noise_injector
+                .generate_all(&mut encoder, self.elapsed_time);
+            self.noise_injector
+                .blend_noise_into(&mut encoder, &mut self.fluid, self.elapsed_time);
+
+            self.fluid.prepare_pass(self.fluid_frame_time);
+            self.fluid.advect(&mut encoder);
+            self.fluid.diffuse(&mut encoder, self.fluid_frame_time);
+            self.fluid.calculate_divergence(&mut encoder);
+            self.fluid.solve_pressure(&mut encoder);
+            self.fluid.subtract_gradient(&mut encoder);
+
+            self.queue.submit(Some(encoder.finish()));
+            self.frame_time -= self.fluid_frame_time;
+        }
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Frame"),
+            });
+
+        // Step the lines at a fixed rate, so that the springs behave the same
+        // regardless of the client’s fps.
+        self.line_time += timestep;
+        self.drawer.prepare_lines(self.line_frame_time);
+        while self.line_time >= self.line_frame_time {
+            self.drawer
+                .place_lines(&mut encoder, self.fluid.get_velocity());
+            self.line_time -= self.line_frame_time;
+        }
+
+        self.drawer.draw(&mut encoder, target);
+
+        self.queue.submit(Some(encoder.finish()));
+    }
+}
+
+impl Backend for Flux {
+    type Target = wgpu::TextureView;
+    type Error = Problem;
+
+    fn update(&mut self, settings: &Rc<Settings>) -> Result<(), Problem> {
+        Flux::update(self, settings)
+    }
+
+    fn resize(&mut self, logical_width: u32, logical_height: u32) -> Result<(), Problem> {
+        Flux::resize(self, logical_width, logical_height);
+        Ok(())
+    }
+
+    fn set_view(&mut self, view: Transform2D) -> () {
+        Flux::set_view(self, view);
+    }
+
+    fn animate(&mut self, timestamp: f32, target: &Self::Target) -> () {
+        Flux::animate(self, timestamp, target);
+    }
+}
+
+// Fail loudly on the settings that would otherwise be quietly ignored.
+fn check_settings(settings: &Settings) -> Result<(), Problem> {
+    let unsupported = [
+        (
+            matches!(settings.render_mode, RenderMode::Particles),
+            "particles",
+        ),
+        (
+            matches!(settings.line_style, LineStyle::Streamline),
+            "streamlines",
+        ),
+        (settings.trail_decay > 0.0, "trails"),
+        (settings.bloom_intensity > 0.0, "bloom"),
+        (!settings.layers.is_empty(), "line layers"),
+        (settings.antialiasing != Antialiasing::Off, "antialiasing"),
+        (
+            settings.endpoint_shape == EndpointShape::Sprite,
+            "endpoint sprites",
+        ),
+        (
+            !matches!(
+                settings.background,
+                Background::Solid(_) | Background::Transparent
+            ),
+            "background gradients and images",
+        ),
+    ];
+
+    match unsupported.iter().find(|(is_used, _)| *is_used) {
+        Some((_, feature)) => Err(Problem::Unsupported(feature)),
+        None => Ok(()),
+    }
+}
+
+// Run a compute shader once for every cell of a `width` by `height` field. The
+// pipelines derive their layouts from the shaders, so `bindings` must list
+// exactly the bindings the entry point uses.
+fn dispatch_cells(
+    device: &wgpu::Device,
+    encoder: &mut wgpu::CommandEncoder,
+    pipeline: &wgpu::ComputePipeline,
+    bindings: &[(u32, &wgpu::Buffer)],
+    width: u32,
+    height: u32,
+) -> () {
+    const WORKGROUP_SIZE: u32 = 8;
+
+    let bind_group = new_bind_group(device, pipeline, bindings);
+    let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: None });
+    pass.set_pipeline(pipeline);
+    pass.set_bind_group(0, &bind_group, &[]);
+    pass.dispatch(
+        (width + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE,
+        (height + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE,
+        1,
+    );
+}
+
+fn new_bind_group(
+    device: &wgpu::Device,
+    pipeline: &wgpu::ComputePipeline,
+    bindings: &[(u32, &wgpu::Buffer)],
+) -> wgpu::BindGroup {
+    let entries: Vec<wgpu::BindGroupEntry> = bindings
+        .iter()
+        .map(|(binding, buffer)| wgpu::BindGroupEntry {
+            binding: *binding,
+            resource: buffer.as_entire_binding(),
+        })
+        .collect();
+
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: &pipeline.get_bind_group_layout(0),
+        entries: &entries,
+    })
+}
+
+fn new_compute_pipeline(
+    device: &wgpu::Device,
+    module: &wgpu::ShaderModule,
+    entry_point: &str,
+) -> wgpu::ComputePipeline {
+    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some(entry_point),
+        layout: None,
+        module,
+        entry_point,
+    })
+}
+
+fn new_shader_module(device: &wgpu::Device, label: &str, source: &str) -> wgpu::ShaderModule {
+    device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+        label: Some(label),
+        source: wgpu::ShaderSource::Wgsl(source.into()),
+    })
+}
+
+// A storage buffer holding a field of vec2s, initialized to zero.
+fn new_field_buffer(device: &wgpu::Device, label: &str, width: u32, height: u32) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some(label),
+        size: (width * height * 2 * 4) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::num::NonZeroU32;
+
+    // Renders a few frames into an offscreen texture and reads them back. It
+    // needs an adapter, so it’s ignored by default. Run it with
+    //
+    //   cargo test -p flux --features experimental-wgpu -- --ignored
+    //
+    // On machines without a GPU, lavapipe will do.
+    #[test]
+    #[ignore]
+    fn renders_offscreen() {
+        let instance = wgpu::Instance::new(wgpu::Backends::all());
+        let adapter =
+            pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()))
+                .expect("no adapter");
+        let (device, queue) = pollster::block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                label: None,
+                features: wgpu::Features::empty(),
+                limits: adapter.limits(),
+            },
+            None,
+        ))
+        .expect("no device");
+        let device = Rc::new(device);
+        let queue = Rc::new(queue);
+
+        // 64 RGBA8 pixels fill a row exactly, so the rows need no padding to
+        // meet wgpu::COPY_BYTES_PER_ROW_ALIGNMENT.
+        let (width, height) = (64, 48);
+        let bytes_per_row = width * 4;
+        let format = wgpu::TextureFormat::Rgba8Unorm;
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Offscreen target"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        });
+        let target = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let background = [0.2, 0.4, 0.6, 1.0];
+        let settings = Rc::new(Settings {
+            background: Background::Solid(background),
+            ..Settings::default()
+        });
+        let mut flux = Flux::new(&device, &queue, format, width, height, 1.0, &settings)
+            .expect("settings should be supported");
+        for frame in 0..10 {
+            flux.animate(frame as f32 * 1000.0 / 60.0, &target);
+        }
+
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Readback"),
+            size: (bytes_per_row * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &readback,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(bytes_per_row),
+                    rows_per_image: None,
+                },
+            },
+            size,
+        );
+        queue.submit(Some(encoder.finish()));
+
+        let slice = readback.slice(..);
+        let mapping = slice.map_async(wgpu::MapMode::Read);
+        device.poll(wgpu::Maintain::Wait);
+        pollster::block_on(mapping).expect("failed to map the readback buffer");
+        let pixels = slice.get_mapped_range();
+
+        // The lines are thin, so most of the frame should still show the
+        // background it was cleared to.
+        let expected: Vec<u8> = background
+            .iter()
+            .map(|channel| (channel * 255.0).round() as u8)
+            .collect();
+        let cleared = pixels
+            .chunks(4)
+            .filter(|pixel| {
+                pixel
+                    .iter()
+                    .zip(&expected)
+                    .all(|(actual, expected)| (*actual as i32 - *expected as i32).abs() <= 2)
+            })
+            .count();
+        assert!(cleared > (width * height / 2) as usize);
+    }
+}
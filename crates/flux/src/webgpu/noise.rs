@@ -0,0 +1,212 @@
+use super::fluid::Fluid;
+use super::{dispatch_cells, new_compute_pipeline, new_field_buffer, new_shader_module};
+use crate::settings::{BlendMethod, Noise};
+
+use bytemuck::{Pod, Zeroable};
+use std::rc::Rc;
+use wgpu::util::DeviceExt;
+
+static NOISE_SHADER: &'static str = include_str!("../../shaders/wgsl/noise.wgsl");
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct NoiseUniforms {
+    frequency: f32,
+    offset_1: f32,
+    offset_2: f32,
+    multiplier: f32,
+    texel_size: [f32; 2],
+    blend_threshold: f32,
+    blend_progress: f32,
+    grid_size: [i32; 2],
+    pad: [f32; 2],
+}
+
+// Byte offsets into `NoiseUniforms`
+const OFFSETS_OFFSET: wgpu::BufferAddress = 4;
+const BLEND_PROGRESS_OFFSET: wgpu::BufferAddress = 7 * 4;
+
+pub struct NoiseChannel {
+    noise: Noise,
+    buffer: wgpu::Buffer,
+    blend_begin_time: f32,
+    last_blend_progress: f32,
+    offset1: f32,
+    offset2: f32,
+    uniforms: wgpu::Buffer,
+}
+
+impl NoiseChannel {
+    // Unlike the OpenGL renderer, the new offsets aren’t uploaded here. The
+    // noise for this step has only been recorded, not run, so they’re written
+    // before the next generation instead.
+    fn tick(&mut self, elapsed_time: f32) -> () {
+        self.blend_begin_time = elapsed_time;
+        self.last_blend_progress = 0.0;
+        self.offset1 += self.noise.offset_increment;
+        self.offset2 += self.noise.offset_increment;
+    }
+}
+
+pub struct NoiseInjector {
+    device: Rc<wgpu::Device>,
+    queue: Rc<wgpu::Queue>,
+    channels: Vec<NoiseChannel>,
+    width: u32,
+    height: u32,
+
+    generate_noise_pass: wgpu::ComputePipeline,
+    blend_with_curl_pass: wgpu::ComputePipeline,
+    blend_with_wiggle_pass: wgpu::ComputePipeline,
+}
+
+impl NoiseInjector {
+    pub fn new(
+        device: &Rc<wgpu::Device>,
+        queue: &Rc<wgpu::Queue>,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let module = new_shader_module(device, "Noise", NOISE_SHADER);
+
+        Self {
+            device: Rc::clone(device),
+            queue: Rc::clone(queue),
+            channels: Vec::new(),
+            width,
+            height,
+
+            generate_noise_pass: new_compute_pipeline(device, &module, "generate"),
+            blend_with_curl_pass: new_compute_pipeline(device, &module, "blendWithCurl"),
+            blend_with_wiggle_pass: new_compute_pipeline(device, &module, "blendWithWiggle"),
+        }
+    }
+
+    pub fn update_channel(&mut self, channel_number: usize, noise: &Noise) -> () {
+        let uniforms = self.new_uniforms(noise);
+
+        if let Some(channel) = self.channels.get_mut(channel_number) {
+            channel.noise = noise.clone();
+            self.queue
+                .write_buffer(&channel.uniforms, 0, bytemuck::bytes_of(&uniforms));
+        }
+    }
+
+    pub fn add_noise(&mut self, noise: Noise) -> () {
+        let uniforms = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Noise uniforms"),
+                contents: bytemuck::bytes_of(&self.new_uniforms(&noise)),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
+
+        self.channels.push(NoiseChannel {
+            buffer: new_field_buffer(&self.device, "Noise", self.width, self.height),
+            blend_begin_time: 0.0,
+            last_blend_progress: 0.0,
+            offset1: noise.offset_1,
+            offset2: noise.offset_2,
+            noise,
+            uniforms,
+        });
+    }
+
+    pub fn generate_all(&mut self, encoder: &mut wgpu::CommandEncoder, elapsed_time: f32) -> () {
+        for channel_number in 0..self.channels.len() {
+            let channel = &self.channels[channel_number];
+            let time_since_last_update = elapsed_time - channel.blend_begin_time;
+
+            if time_since_last_update >= channel.noise.delay {
+                self.generate_by_channel_number(encoder, channel_number, elapsed_time);
+            }
+        }
+    }
+
+    pub fn generate_by_channel_number(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        channel_number: usize,
+        elapsed_time: f32,
+    ) -> () {
+        if let Some(channel) = self.channels.get_mut(channel_number) {
+            self.queue.write_buffer(
+                &channel.uniforms,
+                OFFSETS_OFFSET,
+                bytemuck::bytes_of(&[channel.offset1, channel.offset2]),
+            );
+
+            dispatch_cells(
+                &self.device,
+                encoder,
+                &self.generate_noise_pass,
+                &[(0, &channel.uniforms), (3, &channel.buffer)],
+                self.width,
+                self.height,
+            );
+
+            channel.tick(elapsed_time);
+        }
+    }
+
+    pub fn blend_noise_into(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        fluid: &mut Fluid,
+        elapsed_time: f32,
+    ) -> () {
+        for channel in self.channels.iter_mut() {
+            let blend_progress: f32 = ((elapsed_time - channel.blend_begin_time)
+                / channel.noise.blend_duration)
+                .clamp(0.0, 1.0);
+
+            if blend_progress >= 1.0 - 0.0001 {
+                continue;
+            }
+
+            let delta_blend_progress = blend_progress - channel.last_blend_progress;
+            let blend_pass = match channel.noise.blend_method {
+                BlendMethod::Curl => &self.blend_with_curl_pass,
+                BlendMethod::Wiggle => &self.blend_with_wiggle_pass,
+            };
+
+            self.queue.write_buffer(
+                &channel.uniforms,
+                BLEND_PROGRESS_OFFSET,
+                bytemuck::bytes_of(&delta_blend_progress),
+            );
+
+            let (velocity, next_velocity) = fluid.velocity_pair();
+            dispatch_cells(
+                &self.device,
+                encoder,
+                blend_pass,
+                &[
+                    (0, &channel.uniforms),
+                    (1, velocity),
+                    (2, &channel.buffer),
+                    (3, next_velocity),
+                ],
+                self.width,
+                self.height,
+            );
+            fluid.swap_velocity();
+
+            channel.last_blend_progress = blend_progress;
+        }
+    }
+
+    fn new_uniforms(&self, noise: &Noise) -> NoiseUniforms {
+        NoiseUniforms {
+            frequency: noise.scale,
+            offset_1: noise.offset_1,
+            offset_2: noise.offset_2,
+            multiplier: noise.multiplier,
+            texel_size: [1.0 / self.width as f32, 1.0 / self.height as f32],
+            blend_threshold: noise.blend_threshold,
+            blend_progress: 0.0,
+            grid_size: [self.width as i32, self.height as i32],
+            pad: [0.0; 2],
+        }
+    }
+}
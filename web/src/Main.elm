@@ -48,21 +48,53 @@ type alias Settings =
     , fluidWidth : Int
     , fluidHeight : Int
     , fluidSimulationFrameRate : Int
+    , lineSimulationFrameRate : Int
     , diffusionIterations : Int
     , pressureIterations : Int
     , colorScheme : ColorScheme
+    , background : Background
+    , renderMode : RenderMode
+    , antialiasing : Antialiasing
     , lineLength : Float
     , lineWidth : Float
     , lineBeginOffset : Float
     , lineFadeOutLength : Float
+    , lineOpaqueLength : Float
+    , lineMinWidth : Float
+    , lineMaxWidth : Float
+    , lineMaxLength : Float
+    , lineColorResponse : Float
+    , lineStyle : LineStyle
+    , blendMode : BlendMode
+    , endpointShape : EndpointShape
+    , maskOpacity : Float
+    , maskLineLength : Float
+    , streamlineSegments : Int
+    , trailDecay : Float
+    , trailBlendMode : TrailBlendMode
+    , bloomIntensity : Float
+    , bloomThreshold : Float
+    , bloomRadius : Float
+    , particleCount : Int
+    , particleLifetime : Float
+    , particleSpeed : Float
+    , particleSize : Float
+    , particleStreakLength : Float
     , springStiffness : Float
     , springVariance : Float
     , springMass : Float
     , springRestLength : Float
+    , springDamping : Float
+    , springCouplingStiffness : Float
     , advectionDirection : AdvectionDirection
     , adjustAdvection : Float
     , gridSpacing : Int
     , viewScale : Float
+    , velocityScale : Float
+    , velocityOffset : ( Float, Float )
+    , camera : Camera
+    , endpointHeight : Float
+    , layers : List LineLayer
     , noiseChannel1 : Noise
     , noiseChannel2 : Noise
     }
@@ -80,6 +112,77 @@ type ColorScheme
     | Pollen
 
 
+type RenderMode
+    = Lines
+    | Particles
+
+
+type Antialiasing
+    = Off
+    | Msaa2
+    | Msaa4
+    | Msaa8
+    | Fxaa
+
+
+type LineStyle
+    = Straight
+    | Streamline
+
+
+type Camera
+    = Orthographic
+    | Perspective { fieldOfView : Float, tilt : Float, distance : Float }
+
+
+type EndpointShape
+    = Circle { resolution : Int }
+    | Square
+    | Diamond
+    | Sprite
+    | Hidden
+
+
+type BlendMode
+    = AdditiveBlend
+    | AlphaOverBlend
+    | ScreenBlend
+    | MultiplyBlend
+    | MaxBlend
+
+
+type TrailBlendMode
+    = Additive
+    | Lighten
+
+
+type Background
+    = Solid Color
+    | LinearGradient { from : Color, to : Color, angle : Float }
+    | RadialGradient { inner : Color, outer : Color }
+    | Image
+    | Transparent
+
+
+type alias Color =
+    { red : Float
+    , green : Float
+    , blue : Float
+    , alpha : Float
+    }
+
+
+type alias LineLayer =
+    { gridSpacing : Int
+    , lineLength : Float
+    , lineWidth : Float
+    , colorScheme : ColorScheme
+    , viewScale : Float
+    , velocityScale : Float
+    , velocityOffset : ( Float, Float )
+    }
+
+
 type alias Noise =
     { scale : Float
     , multiplier : Float
@@ -105,21 +208,53 @@ defaultSettings =
     , fluidWidth = 128
     , fluidHeight = 128
     , fluidSimulationFrameRate = 20
+    , lineSimulationFrameRate = 120
     , diffusionIterations = 20
     , pressureIterations = 40
     , colorScheme = Peacock
+    , background = Solid { red = 0.0, green = 0.0, blue = 0.0, alpha = 1.0 }
+    , renderMode = Lines
+    , antialiasing = Msaa4
     , lineLength = 150.0
     , lineWidth = 5.0
     , lineBeginOffset = 0.4
     , lineFadeOutLength = 0.05
+    , lineOpaqueLength = 0.7
+    , lineMinWidth = 0.15
+    , lineMaxWidth = 1.0
+    , lineMaxLength = 0.0
+    , lineColorResponse = 1.0
+    , lineStyle = Straight
+    , blendMode = AdditiveBlend
+    , endpointShape = Circle { resolution = 16 }
+    , maskOpacity = 1.0
+    , maskLineLength = 0.0
+    , streamlineSegments = 8
+    , trailDecay = 0.0
+    , trailBlendMode = Additive
+    , bloomIntensity = 0.0
+    , bloomThreshold = 0.8
+    , bloomRadius = 1.0
+    , particleCount = 10000
+    , particleLifetime = 4.0
+    , particleSpeed = 30.0
+    , particleSize = 3.0
+    , particleStreakLength = 0.1
     , springStiffness = 0.3
     , springVariance = 0.25
     , springMass = 2.0
     , springRestLength = 0.0
+    , springDamping = 0.0
+    , springCouplingStiffness = 0.0
     , advectionDirection = Forward
     , adjustAdvection = 16.0
     , gridSpacing = 18
     , viewScale = 1.2
+    , velocityScale = 1.0
+    , velocityOffset = ( 0.0, 0.0 )
+    , camera = Orthographic
+    , endpointHeight = 0.0
+    , layers = []
     , noiseChannel1 =
         { scale = 1.1
         , multiplier = 0.3
@@ -991,21 +1126,53 @@ encodeSettings settings =
         , ( "fluidWidth", Encode.int settings.fluidWidth )
         , ( "fluidHeight", Encode.int settings.fluidHeight )
         , ( "fluidSimulationFrameRate", Encode.int settings.fluidSimulationFrameRate )
+        , ( "lineSimulationFrameRate", Encode.int settings.lineSimulationFrameRate )
         , ( "diffusionIterations", Encode.int settings.diffusionIterations )
         , ( "pressureIterations", Encode.int settings.pressureIterations )
         , ( "colorScheme", encodeColorScheme settings.colorScheme )
+        , ( "background", encodeBackground settings.background )
+        , ( "renderMode", encodeRenderMode settings.renderMode )
+        , ( "antialiasing", encodeAntialiasing settings.antialiasing )
         , ( "lineLength", Encode.float settings.lineLength )
         , ( "lineWidth", Encode.float settings.lineWidth )
         , ( "lineBeginOffset", Encode.float settings.lineBeginOffset )
         , ( "lineFadeOutLength", Encode.float settings.lineFadeOutLength )
+        , ( "lineOpaqueLength", Encode.float settings.lineOpaqueLength )
+        , ( "lineMinWidth", Encode.float settings.lineMinWidth )
+        , ( "lineMaxWidth", Encode.float settings.lineMaxWidth )
+        , ( "lineMaxLength", Encode.float settings.lineMaxLength )
+        , ( "lineColorResponse", Encode.float settings.lineColorResponse )
+        , ( "lineStyle", encodeLineStyle settings.lineStyle )
+        , ( "blendMode", encodeBlendMode settings.blendMode )
+        , ( "endpointShape", encodeEndpointShape settings.endpointShape )
+        , ( "maskOpacity", Encode.float settings.maskOpacity )
+        , ( "maskLineLength", Encode.float settings.maskLineLength )
+        , ( "streamlineSegments", Encode.int settings.streamlineSegments )
+        , ( "trailDecay", Encode.float settings.trailDecay )
+        , ( "trailBlendMode", encodeTrailBlendMode settings.trailBlendMode )
+        , ( "bloomIntensity", Encode.float settings.bloomIntensity )
+        , ( "bloomThreshold", Encode.float settings.bloomThreshold )
+        , ( "bloomRadius", Encode.float settings.bloomRadius )
+        , ( "particleCount", Encode.int settings.particleCount )
+        , ( "particleLifetime", Encode.float settings.particleLifetime )
+        , ( "particleSpeed", Encode.float settings.particleSpeed )
+        , ( "particleSize", Encode.float settings.particleSize )
+        , ( "particleStreakLength", Encode.float settings.particleStreakLength )
         , ( "springStiffness", Encode.float settings.springStiffness )
         , ( "springVariance", Encode.float settings.springVariance )
         , ( "springMass", Encode.float settings.springMass )
         , ( "springRestLength", Encode.float settings.springRestLength )
+        , ( "springDamping", Encode.float settings.springDamping )
+        , ( "springCouplingStiffness", Encode.float settings.springCouplingStiffness )
         , ( "advectionDirection", encodeAdvectionDirection settings.advectionDirection )
         , ( "adjustAdvection", Encode.float settings.adjustAdvection )
         , ( "gridSpacing", Encode.int settings.gridSpacing )
         , ( "viewScale", Encode.float settings.viewScale )
+        , ( "velocityScale", Encode.float settings.velocityScale )
+        , ( "velocityOffset", encodeVec2 settings.velocityOffset )
+        , ( "camera", encodeCamera settings.camera )
+        , ( "endpointHeight", Encode.float settings.endpointHeight )
+        , ( "layers", Encode.list encodeLineLayer settings.layers )
         , ( "noiseChannel1", encodeNoise settings.noiseChannel1 )
         , ( "noiseChannel2", encodeNoise settings.noiseChannel2 )
         ]
@@ -1047,6 +1214,154 @@ colorSchemeToString colorscheme =
             "Pollen"
 
 
+encodeRenderMode : RenderMode -> Encode.Value
+encodeRenderMode renderMode =
+    case renderMode of
+        Lines ->
+            Encode.string "Lines"
+
+        Particles ->
+            Encode.string "Particles"
+
+
+encodeAntialiasing : Antialiasing -> Encode.Value
+encodeAntialiasing antialiasing =
+    case antialiasing of
+        Off ->
+            Encode.string "Off"
+
+        Msaa2 ->
+            Encode.string "Msaa2"
+
+        Msaa4 ->
+            Encode.string "Msaa4"
+
+        Msaa8 ->
+            Encode.string "Msaa8"
+
+        Fxaa ->
+            Encode.string "Fxaa"
+
+
+encodeLineStyle : LineStyle -> Encode.Value
+encodeLineStyle lineStyle =
+    case lineStyle of
+        Straight ->
+            Encode.string "Straight"
+
+        Streamline ->
+            Encode.string "Streamline"
+
+
+encodeCamera : Camera -> Encode.Value
+encodeCamera camera =
+    case camera of
+        Orthographic ->
+            Encode.string "Orthographic"
+
+        Perspective { fieldOfView, tilt, distance } ->
+            Encode.object
+                [ ( "Perspective"
+                  , Encode.object
+                        [ ( "fieldOfView", Encode.float fieldOfView )
+                        , ( "tilt", Encode.float tilt )
+                        , ( "distance", Encode.float distance )
+                        ]
+                  )
+                ]
+
+
+encodeEndpointShape : EndpointShape -> Encode.Value
+encodeEndpointShape endpointShape =
+    case endpointShape of
+        Circle { resolution } ->
+            Encode.object
+                [ ( "Circle"
+                  , Encode.object [ ( "resolution", Encode.int resolution ) ]
+                  )
+                ]
+
+        Square ->
+            Encode.string "Square"
+
+        Diamond ->
+            Encode.string "Diamond"
+
+        Sprite ->
+            Encode.string "Sprite"
+
+        Hidden ->
+            Encode.string "Hidden"
+
+
+encodeBlendMode : BlendMode -> Encode.Value
+encodeBlendMode blendMode =
+    case blendMode of
+        AdditiveBlend ->
+            Encode.string "Additive"
+
+        AlphaOverBlend ->
+            Encode.string "AlphaOver"
+
+        ScreenBlend ->
+            Encode.string "Screen"
+
+        MultiplyBlend ->
+            Encode.string "Multiply"
+
+        MaxBlend ->
+            Encode.string "Max"
+
+
+encodeTrailBlendMode : TrailBlendMode -> Encode.Value
+encodeTrailBlendMode trailBlendMode =
+    case trailBlendMode of
+        Additive ->
+            Encode.string "Additive"
+
+        Lighten ->
+            Encode.string "Lighten"
+
+
+encodeBackground : Background -> Encode.Value
+encodeBackground background =
+    case background of
+        Solid color ->
+            Encode.object [ ( "Solid", encodeColor color ) ]
+
+        LinearGradient { from, to, angle } ->
+            Encode.object
+                [ ( "LinearGradient"
+                  , Encode.object
+                        [ ( "from", encodeColor from )
+                        , ( "to", encodeColor to )
+                        , ( "angle", Encode.float angle )
+                        ]
+                  )
+                ]
+
+        RadialGradient { inner, outer } ->
+            Encode.object
+                [ ( "RadialGradient"
+                  , Encode.object
+                        [ ( "inner", encodeColor inner )
+                        , ( "outer", encodeColor outer )
+                        ]
+                  )
+                ]
+
+        Image ->
+            Encode.string "Image"
+
+        Transparent ->
+            Encode.string "Transparent"
+
+
+encodeColor : Color -> Encode.Value
+encodeColor { red, green, blue, alpha } =
+    Encode.list Encode.float [ red, green, blue, alpha ]
+
+
 encodeBlendMethod : BlendMethod -> Encode.Value
 encodeBlendMethod =
     blendMethodToString >> Encode.string
@@ -1062,6 +1377,24 @@ blendMethodToString blendMethod =
             "Wiggle"
 
 
+encodeLineLayer : LineLayer -> Encode.Value
+encodeLineLayer layer =
+    Encode.object
+        [ ( "gridSpacing", Encode.int layer.gridSpacing )
+        , ( "lineLength", Encode.float layer.lineLength )
+        , ( "lineWidth", Encode.float layer.lineWidth )
+        , ( "colorScheme", encodeColorScheme layer.colorScheme )
+        , ( "viewScale", Encode.float layer.viewScale )
+        , ( "velocityScale", Encode.float layer.velocityScale )
+        , ( "velocityOffset", encodeVec2 layer.velocityOffset )
+        ]
+
+
+encodeVec2 : ( Float, Float ) -> Encode.Value
+encodeVec2 ( x, y ) =
+    Encode.list Encode.float [ x, y ]
+
+
 encodeNoise : Noise -> Encode.Value
 encodeNoise noise =
     Encode.object
@@ -26,10 +26,20 @@ ui.ports.initFlux.subscribe(function (settings) {
 
   window.requestAnimationFrame(animate);
 
-
+  // Add ?gpu-timings to the URL to log the time spent in each pass.
+  if (new URLSearchParams(window.location.search).has('gpu-timings')) {
+    if (flux.set_gpu_profiling(true)) {
+      setInterval(() => flux.log_gpu_timings(), 5000);
+    } else {
+      console.log('This browser doesn’t support EXT_disjoint_timer_query_webgl2.');
+    }
+  }
 });
 
 // Update settings
 ui.ports.setSettings.subscribe(function (newSettings) {
-  flux.settings = newSettings;
+  const antialiasing = flux.set_settings(newSettings);
+  if (antialiasing !== newSettings.antialiasing) {
+    console.warn(`${newSettings.antialiasing} antialiasing isn’t supported. Using ${antialiasing} instead.`);
+  }
 });